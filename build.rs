@@ -0,0 +1,19 @@
+fn main() {
+    // the C header for the `ffi` feature, generated from src/ffi.rs only (not the whole crate)
+    // into OUT_DIR; include/rustfastq.h is the checked-in copy, tests/ffi.rs checks it is current
+    #[cfg(feature = "ffi")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+
+        let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml")).unwrap();
+        cbindgen::Builder::new()
+            .with_src(format!("{crate_dir}/src/ffi.rs"))
+            .with_config(config)
+            .generate()
+            .expect("Unable to generate C header")
+            .write_to_file(format!("{out_dir}/rustfastq.h"));
+    }
+}
@@ -0,0 +1,89 @@
+#ifndef RUSTFASTQ_H
+#define RUSTFASTQ_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A record was written into the buffers
+#define RFQ_OK 0
+
+// All files are exhausted, no record was written
+#define RFQ_EOF 1
+
+// A required pointer argument was NULL
+#define RFQ_ERR_NULL_POINTER -1
+
+// An argument was invalid (e.g. a path that is not valid UTF-8)
+#define RFQ_ERR_INVALID_ARGUMENT -2
+
+// A file could not be opened or parsed
+#define RFQ_ERR_IO -3
+
+// At least one of the buffers is too small; the `len` fields contain the required lengths
+#define RFQ_ERR_BUFFER_TOO_SMALL -4
+
+// An unexpected internal error (a Rust panic) occurred
+#define RFQ_ERR_INTERNAL -5
+
+// Opaque handle to a chain of FastQ files
+typedef struct RfqReader RfqReader;
+
+// A caller-owned buffer that a record field is copied into.
+// On return, `len` holds the length of the field (excluding the terminating NUL),
+// even if `capacity` was too small to hold it
+typedef struct RfqBuffer {
+  char *data;
+  size_t capacity;
+  size_t len;
+} RfqBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Opens a list of BGZF compressed FastQ files (e.g. written by bgzip), read one after the other.
+//
+// Returns NULL on error, see `rfq_last_error_message`.
+//
+// # Safety
+// `paths` must point to `n_paths` valid, NUL-terminated strings
+struct RfqReader *rfq_reader_open(const char *const *paths, size_t n_paths);
+
+// Reads the next record into the three buffers.
+//
+// Returns `RFQ_OK`, `RFQ_EOF` or a negative error code. On `RFQ_ERR_BUFFER_TOO_SMALL`
+// the `len` fields hold the field lengths (each buffer needs `len + 1` bytes) and the
+// same record is returned by the next call.
+//
+// # Safety
+// `reader` must come from `rfq_reader_open`; each buffer's `data` must point to at least
+// `capacity` writable bytes
+int rfq_reader_next(struct RfqReader *reader,
+                    struct RfqBuffer *header,
+                    struct RfqBuffer *seq,
+                    struct RfqBuffer *qual);
+
+// Error code of the last call on the calling thread, `RFQ_OK` if it succeeded.
+// Mostly useful after `rfq_reader_open` returned NULL
+int rfq_last_error_code(void);
+
+// Message describing the last error on the calling thread, or NULL if the last
+// call succeeded. The pointer stays valid until the next `rfq_*` call on this thread.
+const char *rfq_last_error_message(void);
+
+// Closes the reader and frees all its resources. Passing NULL is a no-op.
+//
+// # Safety
+// `reader` must come from `rfq_reader_open` and must not be used afterwards
+void rfq_reader_close(struct RfqReader *reader);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RUSTFASTQ_H */
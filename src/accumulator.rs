@@ -0,0 +1,101 @@
+//! Common interface of the statistics collected over fastq files,
+//! so that several of them can be computed in a single pass
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::io::{fastq_list_iter, FastqEntry};
+use crate::sampling::{sample_file, Sampling};
+use crate::utils::get_spinner;
+
+/// Collects some statistic, one read at a time.
+/// Accumulators of different files can be combined via `merge`
+pub trait FastqAccumulator: Default + Send {
+    fn add(&mut self, fq: &FastqEntry);
+    fn merge(&mut self, other: Self);
+}
+
+/// Two accumulators filled in the same pass
+impl<A: FastqAccumulator, B: FastqAccumulator> FastqAccumulator for (A, B) {
+    fn add(&mut self, fq: &FastqEntry) {
+        self.0.add(fq);
+        self.1.add(fq);
+    }
+    fn merge(&mut self, other: Self) {
+        self.0.merge(other.0);
+        self.1.merge(other.1);
+    }
+}
+
+/// Accumulates all reads of the given fastq files (one after the other)
+pub fn accumulate_files<A: FastqAccumulator>(fastq_files: &[String]) -> A {
+    let mut acc = A::default();
+    for fq in fastq_list_iter(fastq_files) {
+        acc.add(&fq);
+    }
+    acc
+}
+
+/// Accumulates each fastq file separately, processing several files in parallel.
+/// Returns one accumulator per file (in the same order as `fastq_files`)
+pub fn accumulate_files_parallel<A: FastqAccumulator>(fastq_files: &[String]) -> Vec<A> {
+    accumulate_files_sampled(fastq_files, &Sampling::All)
+}
+
+/// Like [`accumulate_files_parallel`], but only looking at the sampled reads of each file
+pub fn accumulate_files_sampled<A: FastqAccumulator>(fastq_files: &[String], sampling: &Sampling) -> Vec<A> {
+    let bar = get_spinner();
+    let results = map_files_parallel(fastq_files, |fname| {
+        let mut acc = A::default();
+        for (j, fq) in sample_file(fname, sampling).enumerate() {
+            acc.add(&fq);
+            if j % 100_000 == 0 {
+                bar.inc(100_000);
+            }
+        }
+        acc
+    });
+    bar.finish();
+    results
+}
+
+/// Applies `f` to each file, processing several files in parallel
+/// (as many as there are CPUs). Results are in the same order as `files`
+pub fn map_files_parallel<T, F>(files: &[String], f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&str) -> T + Sync,
+{
+    let n_threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(files.len());
+
+    let next_file = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<T>>> = Mutex::new((0..files.len()).map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..n_threads {
+            scope.spawn(|| {
+                // each thread keeps taking the next unprocessed file
+                loop {
+                    let i = next_file.fetch_add(1, Ordering::SeqCst);
+                    if i >= files.len() {
+                        break;
+                    }
+                    let r = f(&files[i]);
+                    results.lock().unwrap()[i] = Some(r);
+                }
+            });
+        }
+    });
+
+    results.into_inner().unwrap().into_iter().map(|c| c.unwrap()).collect()
+}
+
+/// Merges all accumulators into one
+pub fn merge_all<A: FastqAccumulator>(accumulators: Vec<A>) -> A {
+    accumulators.into_iter().fold(A::default(), |mut acc, a| {
+        acc.merge(a);
+        acc
+    })
+}
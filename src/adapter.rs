@@ -0,0 +1,484 @@
+//! Adapter trimming, similar to cutadapt/fastp.
+//!
+//! Adapters are found by error-tolerant semi-global alignment: a 3' adapter may start anywhere
+//! in the read and run past its end (only a prefix of the adapter is sequenced), a 5' adapter
+//! may start before the read (only a suffix is sequenced). At most `max_error_rate` errors
+//! (mismatches/indels) per aligned adapter base are allowed.
+//!
+//! For paired reads, the adapter can also be found without knowing its sequence:
+//! if the insert is shorter than the reads, R1 and the reverse complement of R2 overlap
+//! with a negative offset, see [`insert_size`]
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::io::{fastq_list_iter, read_fasta, reverse_complement, FastqEntry};
+
+/// Default maximum errors per aligned adapter base (cutadapt's `-e`)
+pub const DEFAULT_MAX_ERROR_RATE: f64 = 0.1;
+/// Default minimum number of adapter bases that have to match (cutadapt's `-O`)
+pub const DEFAULT_MIN_OVERLAP: usize = 3;
+/// Number of reads looked at by [`detect_adapter`]
+pub const DETECT_READS: usize = 100_000;
+
+/// Adapters that can be given by name
+pub const BUILTIN_ADAPTERS: &[(&str, &str)] = &[
+    ("truseq", "AGATCGGAAGAGCACACGTCTGAACTCCAGTCA"),
+    ("truseq_r2", "AGATCGGAAGAGCGTCGTGTAGGGAAAGAGTGT"),
+    ("nextera", "CTGTCTCTTATACACATCT"),
+    ("smallrna", "TGGAATTCTCGGGTGCCAAGG"),
+];
+
+/// Which end of the read an adapter is ligated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterEnd {
+    /// removed together with everything after it
+    ThreePrime,
+    /// removed together with everything before it
+    FivePrime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adapter {
+    pub name: String,
+    pub sequence: String,
+    pub end: AdapterEnd,
+}
+
+impl Adapter {
+    pub fn new(name: &str, sequence: &str, end: AdapterEnd) -> Self {
+        Adapter { name: name.to_string(), sequence: sequence.to_uppercase(), end }
+    }
+
+    /// A name of [`BUILTIN_ADAPTERS`] or a sequence (named after itself)
+    pub fn parse(s: &str, end: AdapterEnd) -> Result<Self, String> {
+        if let Some((name, seq)) = BUILTIN_ADAPTERS.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+            return Ok(Adapter::new(name, seq, end));
+        }
+        if s.is_empty() || !s.chars().all(|c| "ACGTNacgtn".contains(c)) {
+            return Err(format!("{s} is neither a known adapter nor a DNA sequence"));
+        }
+        Ok(Adapter::new(s, s, end))
+    }
+}
+
+/// Reads the adapters from a (plain or gzip) FASTA file
+pub fn read_fasta_adapters(fname: &str, end: AdapterEnd) -> Result<Vec<Adapter>, String> {
+    Ok(read_fasta(fname)?.into_iter().map(|(name, seq)| Adapter::new(&name, &seq, end)).collect())
+}
+
+/// Where an adapter was found in the read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdapterMatch {
+    /// index into the adapters of the [`AdapterTrimmer`]
+    pub adapter: usize,
+    /// aligned part of the read, `start..end`
+    pub start: usize,
+    pub end: usize,
+    /// number of adapter bases aligned
+    pub length: usize,
+    pub errors: usize,
+}
+
+impl AdapterMatch {
+    fn matches(&self) -> usize {
+        self.length - self.errors
+    }
+}
+
+fn bases_match(adapter_base: u8, read_base: u8) -> bool {
+    adapter_base == read_base || adapter_base == b'N'
+}
+
+/// Best alignment of a 3' adapter to the read (adapter index 0): either the whole adapter
+/// somewhere in the read, or a prefix of it at the end of the read
+fn align_3p(adapter: &[u8], read: &[u8], max_error_rate: f64, min_overlap: usize) -> Option<AdapterMatch> {
+    let (m, n) = (adapter.len(), read.len());
+    // (errors, start in read) for the current row of the DP matrix; the adapter can start anywhere
+    let mut prev: Vec<(usize, usize)> = (0..=n).map(|j| (0, j)).collect();
+    let mut last_column = vec![(0, n); m + 1];
+    let mut best: Option<AdapterMatch> = None;
+    let mut consider = |candidate: AdapterMatch| {
+        let allowed = (max_error_rate * candidate.length as f64) as usize;
+        if candidate.length < min_overlap || candidate.errors > allowed {
+            return;
+        }
+        let better = match best {
+            None => true,
+            Some(b) => (candidate.matches(), std::cmp::Reverse(candidate.errors), std::cmp::Reverse(candidate.start))
+                > (b.matches(), std::cmp::Reverse(b.errors), std::cmp::Reverse(b.start)),
+        };
+        if better {
+            best = Some(candidate);
+        }
+    };
+
+    for i in 1..=m {
+        let mut row = vec![(i, 0); n + 1];
+        for j in 1..=n {
+            let (diag, diag_start) = prev[j - 1];
+            let diag = diag + usize::from(!bases_match(adapter[i - 1], read[j - 1]));
+            let up = (prev[j].0 + 1, prev[j].1);
+            let left = (row[j - 1].0 + 1, row[j - 1].1);
+            row[j] = (diag, diag_start).min(up).min(left);
+        }
+        last_column[i] = row[n];
+        prev = row;
+    }
+    // the whole adapter, ending anywhere
+    for (end, &(errors, start)) in prev.iter().enumerate() {
+        consider(AdapterMatch { adapter: 0, start, end, length: m, errors });
+    }
+    // a prefix of the adapter at the end of the read
+    for (length, &(errors, start)) in last_column.iter().enumerate().take(m) {
+        consider(AdapterMatch { adapter: 0, start, end: n, length, errors });
+    }
+    best
+}
+
+/// Finds a single adapter in the read sequence
+pub fn find_adapter(adapter: &Adapter, seq: &str, max_error_rate: f64, min_overlap: usize) -> Option<AdapterMatch> {
+    match adapter.end {
+        AdapterEnd::ThreePrime => align_3p(adapter.sequence.as_bytes(), seq.as_bytes(), max_error_rate, min_overlap),
+        // a 5' adapter is a 3' adapter of the reversed read
+        AdapterEnd::FivePrime => {
+            let adapter_rev: Vec<u8> = adapter.sequence.bytes().rev().collect();
+            let read_rev: Vec<u8> = seq.bytes().rev().collect();
+            align_3p(&adapter_rev, &read_rev, max_error_rate, min_overlap)
+                .map(|m| AdapterMatch { start: seq.len() - m.end, end: seq.len() - m.start, ..m })
+        }
+    }
+}
+
+/// Removes the best matching of its adapters from reads
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterTrimmer {
+    pub adapters: Vec<Adapter>,
+    pub max_error_rate: f64,
+    pub min_overlap: usize,
+}
+
+impl AdapterTrimmer {
+    pub fn new(adapters: Vec<Adapter>) -> Self {
+        AdapterTrimmer { adapters, max_error_rate: DEFAULT_MAX_ERROR_RATE, min_overlap: DEFAULT_MIN_OVERLAP }
+    }
+
+    /// The adapter with the most matching bases
+    pub fn find(&self, seq: &str) -> Option<AdapterMatch> {
+        self.adapters.iter().enumerate()
+            .filter_map(|(i, adapter)| {
+                find_adapter(adapter, seq, self.max_error_rate, self.min_overlap).map(|m| AdapterMatch { adapter: i, ..m })
+            })
+            .max_by_key(|m| (m.matches(), std::cmp::Reverse(m.errors)))
+    }
+}
+
+impl FastqEntry {
+    /// Removes the best matching adapter (and what's beyond it); returns the match
+    pub fn trim_adapters(&mut self, trimmer: &AdapterTrimmer) -> Option<AdapterMatch> {
+        let m = trimmer.find(&self.seq)?;
+        match trimmer.adapters[m.adapter].end {
+            AdapterEnd::ThreePrime => self.keep_range(0, m.start),
+            AdapterEnd::FivePrime => self.keep_range(m.end, self.len()),
+        }
+        Some(m)
+    }
+}
+
+/// Adapters of R1 and R2, as given on the command line or in a pipeline config
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AdapterOptions {
+    /// 3' adapters: sequences or names of [`BUILTIN_ADAPTERS`]
+    pub adapters: Vec<String>,
+    /// 5' adapters, of both reads
+    pub front: Vec<String>,
+    /// FASTA file of 3' adapters
+    pub adapter_fasta: Option<String>,
+    /// 3' adapters of R2, instead of `adapters`
+    pub adapters_r2: Vec<String>,
+    /// add a 3' adapter detected in the first reads (of R1 and R2 separately)
+    pub detect_adapter: bool,
+    pub error_rate: f64,
+    pub min_overlap: usize,
+}
+
+impl Default for AdapterOptions {
+    fn default() -> Self {
+        AdapterOptions {
+            adapters: Vec::new(),
+            front: Vec::new(),
+            adapter_fasta: None,
+            adapters_r2: Vec::new(),
+            detect_adapter: false,
+            error_rate: DEFAULT_MAX_ERROR_RATE,
+            min_overlap: DEFAULT_MIN_OVERLAP,
+        }
+    }
+}
+
+impl AdapterOptions {
+    fn trimmer(&self, adapters: Vec<Adapter>) -> Option<AdapterTrimmer> {
+        (!adapters.is_empty()).then_some(AdapterTrimmer { adapters, max_error_rate: self.error_rate, min_overlap: self.min_overlap })
+    }
+
+    /// Adapter trimmers for R1 and R2 (`None` without adapters); `r2_list` may be empty
+    pub fn trimmers(&self, r1_list: &[String], r2_list: &[String]) -> Result<(Option<AdapterTrimmer>, Option<AdapterTrimmer>), String> {
+        let parse = |seqs: &[String], end: AdapterEnd| -> Result<Vec<Adapter>, String> {
+            seqs.iter().map(|s| Adapter::parse(s, end)).collect()
+        };
+        let front = parse(&self.front, AdapterEnd::FivePrime)?;
+        let mut adapters = parse(&self.adapters, AdapterEnd::ThreePrime)?;
+        if let Some(fname) = &self.adapter_fasta {
+            adapters.extend(read_fasta_adapters(fname, AdapterEnd::ThreePrime)?);
+        }
+        let mut adapters_r2 = if self.adapters_r2.is_empty() { adapters.clone() } else { parse(&self.adapters_r2, AdapterEnd::ThreePrime)? };
+        if self.detect_adapter {
+            for (list, adapters) in [(r1_list, &mut adapters), (r2_list, &mut adapters_r2)] {
+                if list.is_empty() {
+                    continue;
+                }
+                match detect_adapter(list) {
+                    Some(a) => {
+                        println!("detected adapter {}: {}", a.name, a.sequence);
+                        adapters.push(a);
+                    }
+                    None => println!("no adapter detected"),
+                }
+            }
+        }
+        adapters.extend(front.iter().cloned());
+        adapters_r2.extend(front);
+        Ok((self.trimmer(adapters), self.trimmer(adapters_r2)))
+    }
+}
+
+/// How well R1 and the reverse complement of R2 have to overlap (fastp's defaults)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlapParams {
+    pub min_overlap: usize,
+    pub max_mismatches: usize,
+    pub max_mismatch_rate: f64,
+}
+
+impl Default for OverlapParams {
+    fn default() -> Self {
+        OverlapParams { min_overlap: 30, max_mismatches: 5, max_mismatch_rate: 0.2 }
+    }
+}
+
+fn overlap_matches(a: &[u8], b: &[u8], params: &OverlapParams) -> bool {
+    let limit = params.max_mismatches.min((params.max_mismatch_rate * a.len() as f64) as usize);
+    let mut mismatches = 0;
+    for (x, y) in a.iter().zip(b) {
+        if x != y {
+            mismatches += 1;
+            if mismatches > limit {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Length of the insert, from the overlap of R1 and the reverse complement of R2.
+/// If the insert is shorter than a read, the rest of that read is adapter
+pub fn insert_size(r1: &str, r2: &str, params: &OverlapParams) -> Option<usize> {
+    let r1 = r1.as_bytes();
+    let r2_rc = reverse_complement(r2);
+    let r2_rc = r2_rc.as_bytes();
+    let (l1, l2) = (r1.len(), r2_rc.len());
+    if l1 < params.min_overlap || l2 < params.min_overlap {
+        return None;
+    }
+    // R2 (reverse complemented) starting within R1
+    for offset in 0..=l1 - params.min_overlap {
+        let len = (l1 - offset).min(l2);
+        if len >= params.min_overlap && overlap_matches(&r1[offset..offset + len], &r2_rc[..len], params) {
+            return Some(offset + l2);
+        }
+    }
+    // R2 starting before R1: both reads run into the adapter
+    for shift in 1..=l2 - params.min_overlap {
+        let len = l1.min(l2 - shift);
+        if overlap_matches(&r1[..len], &r2_rc[shift..shift + len], params) {
+            return Some(l2 - shift);
+        }
+    }
+    None
+}
+
+/// Cuts both reads to the insert size, if it's shorter; returns the insert size
+pub fn trim_pair_by_overlap(fq1: &mut FastqEntry, fq2: &mut FastqEntry, params: &OverlapParams) -> Option<usize> {
+    let insert = insert_size(&fq1.seq, &fq2.seq, params)?;
+    for fq in [fq1, fq2] {
+        if insert < fq.len() {
+            fq.keep_range(0, insert);
+        }
+    }
+    Some(insert)
+}
+
+const DETECT_K: usize = 12;
+/// a k-mer has to be in this fraction of the reads to be considered adapter
+const DETECT_MIN_FRACTION: f64 = 0.01;
+/// extend the adapter while this fraction of the reads agree on the next base
+const DETECT_MIN_AGREEMENT: f64 = 0.8;
+const DETECT_MIN_SUPPORT: usize = 10;
+
+/// Most common base at an offset from the k-mer, if enough reads agree
+fn consensus_base(hits: &[(&[u8], usize)], base_at: impl Fn(&[u8], usize) -> Option<u8>) -> Option<u8> {
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    for &(seq, pos) in hits {
+        if let Some(b) = base_at(seq, pos) {
+            *counts.entry(b).or_insert(0) += 1;
+        }
+    }
+    let total: usize = counts.values().sum();
+    let (&base, &count) = counts.iter().max_by_key(|(_, &c)| c)?;
+    (total >= DETECT_MIN_SUPPORT && count as f64 >= DETECT_MIN_AGREEMENT * total as f64 && base != b'N').then_some(base)
+}
+
+/// Detects a 3' adapter in the reads: adapter read-through shows up as a k-mer present
+/// in many reads, which is extended into the consensus around it.
+/// If it contains the start of a [`BUILTIN_ADAPTERS`], that one is returned
+pub fn detect_adapter_in(seqs: &[String]) -> Option<Adapter> {
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for seq in seqs {
+        for kmer in seq.as_bytes().windows(DETECT_K) {
+            // low complexity (e.g. polyA/polyG) is handled elsewhere
+            let distinct = b"ACGT".iter().filter(|b| kmer.contains(b)).count();
+            if distinct >= 3 && !kmer.contains(&b'N') {
+                *counts.entry(kmer).or_insert(0) += 1;
+            }
+        }
+    }
+    let (&kmer, &count) = counts.iter().max_by_key(|(kmer, &c)| (c, *kmer))?;
+    if (count as f64) < DETECT_MIN_FRACTION * seqs.len() as f64 {
+        return None;
+    }
+
+    let hits: Vec<(&[u8], usize)> = seqs.iter()
+        .filter_map(|s| s.find(std::str::from_utf8(kmer).unwrap()).map(|pos| (s.as_bytes(), pos)))
+        .collect();
+    let mut consensus: Vec<u8> = kmer.to_vec();
+    while let Some(b) = consensus_base(&hits, |s, pos| s.get(pos + consensus.len()).copied()) {
+        consensus.push(b);
+    }
+    let mut left = 0;
+    while let Some(b) = consensus_base(&hits, |s, pos| pos.checked_sub(left + 1).map(|i| s[i])) {
+        consensus.insert(0, b);
+        left += 1;
+    }
+    let consensus = String::from_utf8(consensus).unwrap();
+
+    // several adapters start the same, take the one agreeing longest with the consensus
+    let agreement = |seq: &str| -> usize {
+        consensus.find(&seq[..DETECT_K]).map_or(0, |pos| {
+            consensus[pos..].bytes().zip(seq.bytes()).take_while(|(a, b)| a == b).count()
+        })
+    };
+    let known = BUILTIN_ADAPTERS.iter()
+        .map(|&(name, seq)| (agreement(seq), name, seq))
+        .filter(|&(n, _, _)| n > 0)
+        .max()
+        .map(|(_, name, seq)| (name, seq));
+    Some(match known {
+        Some((name, seq)) => Adapter::new(name, seq, AdapterEnd::ThreePrime),
+        None => Adapter::new("detected", &consensus, AdapterEnd::ThreePrime),
+    })
+}
+
+/// [`detect_adapter_in`] the first [`DETECT_READS`] reads of the files
+pub fn detect_adapter(fastq_list: &[String]) -> Option<Adapter> {
+    let seqs: Vec<String> = fastq_list_iter(fastq_list).take(DETECT_READS).map(|fq| fq.seq).collect();
+    detect_adapter_in(&seqs)
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{detect_adapter_in, find_adapter, insert_size, read_fasta_adapters, trim_pair_by_overlap, Adapter, AdapterEnd, AdapterTrimmer, OverlapParams};
+    use crate::io::{reverse_complement, FastqEntry};
+
+    const TRUSEQ: &str = "AGATCGGAAGAGCACACGTCTGAACTCCAGTCA";
+
+    fn entry(seq: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: "I".repeat(seq.len()) }
+    }
+
+    #[test]
+    fn test_find_3p() {
+        let adapter = Adapter::parse("truseq", AdapterEnd::ThreePrime).unwrap();
+        // whole adapter inside the read
+        let read = format!("ACCTTGCA{TRUSEQ}GGG");
+        let m = find_adapter(&adapter, &read, 0.1, 3).unwrap();
+        assert_eq!((m.start, m.end, m.errors), (8, 8 + TRUSEQ.len(), 0));
+        // partial adapter, with a mismatch
+        let m = find_adapter(&adapter, "ACCTTGCAAGATCGCAAGAGCAC", 0.1, 3).unwrap();
+        assert_eq!((m.start, m.errors), (8, 1));
+        // only 3 adapter bases at the end
+        assert_eq!(find_adapter(&adapter, "ACCTTGCATTAGA", 0.1, 3).unwrap().start, 10);
+        assert!(find_adapter(&adapter, "ACCTTGCATTAGA", 0.1, 4).is_none());
+        assert!(find_adapter(&adapter, "ACCTTGCATTTTT", 0.1, 3).is_none());
+    }
+
+    #[test]
+    fn test_find_with_deletion() {
+        let adapter = Adapter::new("a", "ACGTACGTACGTACGTACGT", AdapterEnd::ThreePrime);
+        // one base of the adapter missing
+        let m = find_adapter(&adapter, "TTTTTACGTACGTACGACGTACGT", 0.1, 3).unwrap();
+        assert_eq!((m.start, m.errors), (5, 1));
+    }
+
+    #[test]
+    fn test_trim_5p() {
+        let trimmer = AdapterTrimmer::new(vec![Adapter::new("front", "GTTCAGAGTTCTACAGTCCG", AdapterEnd::FivePrime)]);
+        // only the end of the adapter sequenced
+        let mut fq = entry("CAGTCCGACGATCTT");
+        assert!(fq.trim_adapters(&trimmer).is_some());
+        assert_eq!(fq.seq, "ACGATCTT");
+        assert_eq!(fq.phred.len(), 8);
+    }
+
+    #[test]
+    fn test_fasta() {
+        let fname = "/tmp/rustfastq_adapters.fa";
+        std::fs::write(fname, ">a1\nACGT\nacgt\n>a2\nTTTT\n").unwrap();
+        let adapters = read_fasta_adapters(fname, AdapterEnd::ThreePrime).unwrap();
+        assert_eq!(adapters.len(), 2);
+        assert_eq!(adapters[0].sequence, "ACGTACGT");
+        assert_eq!(adapters[1].name, "a2");
+        assert!(Adapter::parse("nonsense", AdapterEnd::ThreePrime).is_err());
+    }
+
+    #[test]
+    fn test_insert_size() {
+        let insert = "TGCATGCAAGGCTTACCGATTACGGATCAGGCATTCAGGACGTACCAT";
+        let params = OverlapParams::default();
+        // insert shorter than the reads
+        let r1 = format!("{insert}{}", &TRUSEQ[..20]);
+        let r2 = format!("{}{}", reverse_complement(insert), "AGATCGGAAGAGCGTCGTGT");
+        assert_eq!(insert_size(&r1, &r2, &params), Some(insert.len()));
+
+        let (mut fq1, mut fq2) = (entry(&r1), entry(&r2));
+        trim_pair_by_overlap(&mut fq1, &mut fq2, &params);
+        assert_eq!(fq1.seq, insert);
+        assert_eq!(fq2.seq, reverse_complement(insert));
+
+        // long insert: reads overlap, nothing to trim
+        let long = format!("{insert}ACGTTGCAGGTCAATCGGATCCAT");
+        let r1 = &long[..60];
+        let r2 = reverse_complement(&long[long.len() - 60..]);
+        assert_eq!(insert_size(r1, &r2, &params), Some(long.len()));
+    }
+
+    #[test]
+    fn test_detect_adapter() {
+        let inserts = ["TGCATGCAAGGCTTACCGAT", "TACGGATCAGGCATTCAGGA", "CGTACCATGGTACCTTAGCA", "GGATTTACCAGTGCAAGTCT"];
+        let mut seqs: Vec<String> = (0..100).map(|i| format!("{}{}", inserts[i % 4], &TRUSEQ[..(i % 10) + 20])).collect();
+        seqs.extend((0..100).map(|i| inserts[i % 4].repeat(2)));
+        let adapter = detect_adapter_in(&seqs).unwrap();
+        assert_eq!(adapter.name, "truseq");
+        assert_eq!(adapter.sequence, TRUSEQ);
+    }
+}
@@ -0,0 +1,141 @@
+//! Sequence complexity scores, for filtering low-complexity reads (as prinseq's `-lc_method`),
+//! and poly-X tails, for trimming or filtering them.
+//!
+//! Both scores are on a 0-100 scale and based on the trinucleotides of the read:
+//! the DUST score is high for repetitive sequences, the entropy low
+use std::collections::HashMap;
+
+/// DUST scores are averaged over windows of this length
+pub const DUST_WINDOW: usize = 64;
+/// Default threshold: reads with a higher DUST score are low complexity
+pub const DEFAULT_MAX_DUST: f64 = 7.0;
+/// Default threshold: reads with a lower entropy are low complexity
+pub const DEFAULT_MIN_ENTROPY: f64 = 70.0;
+/// Default minimum length of a poly-X tail to trim
+pub const DEFAULT_POLY_X_MIN_LENGTH: usize = 10;
+/// a poly-X tail can have one mismatch per this many bases (as fastp)...
+const POLY_X_BASES_PER_MISMATCH: usize = 8;
+/// ...but no more than this
+const POLY_X_MAX_MISMATCHES: usize = 5;
+/// score of a mismatch in a poly-X tail, a match scores 1 (as cutadapt)
+const POLY_X_MISMATCH_SCORE: i64 = -2;
+
+fn triplet_counts(seq: &[u8]) -> HashMap<&[u8], usize> {
+    let mut counts = HashMap::new();
+    for triplet in seq.windows(3) {
+        *counts.entry(triplet).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// DUST score of a single window, scaled so a homopolymer scores 100
+fn dust_window(seq: &[u8]) -> f64 {
+    let l = seq.len().saturating_sub(2) as f64;
+    if l < 2.0 {
+        return 0.0;
+    }
+    let sum: usize = triplet_counts(seq).values().map(|&c| c * (c - 1) / 2).sum();
+    200.0 * sum as f64 / (l * (l - 1.0))
+}
+
+/// Mean DUST score over windows of [`DUST_WINDOW`] bases, overlapping by half
+pub fn dust_score(seq: &str) -> f64 {
+    let seq = seq.as_bytes();
+    if seq.len() <= DUST_WINDOW {
+        return dust_window(seq);
+    }
+    let step = DUST_WINDOW / 2;
+    let starts: Vec<usize> = (0..=seq.len() - DUST_WINDOW).step_by(step).collect();
+    starts.iter().map(|&s| dust_window(&seq[s..s + DUST_WINDOW])).sum::<f64>() / starts.len() as f64
+}
+
+/// Shannon entropy of the trinucleotides, relative to the maximum possible for the read length
+pub fn entropy(seq: &str) -> f64 {
+    let counts = triplet_counts(seq.as_bytes());
+    let total: usize = counts.values().sum();
+    // at most 64 distinct triplets, and no more than there are triplets
+    let max_entropy = (total.min(64) as f64).log2();
+    if max_entropy == 0.0 {
+        return 0.0;
+    }
+    let h: f64 = counts.values()
+        .map(|&c| {
+            let p = c as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum();
+    100.0 * h / max_entropy
+}
+
+/// Start of the 3' tail of `base` (e.g. poly-G from dark cycles of two-colour chemistry,
+/// or poly-A), allowing some mismatches; the read length if there's no tail of `min_length`.
+/// The tail is the suffix with the best score that doesn't have too many mismatches
+pub fn poly_x_tail_start(seq: &str, base: char, min_length: usize) -> usize {
+    let n = seq.len();
+    let (mut start, mut score, mut best_score, mut mismatches) = (n, 0, 0, 0);
+    for (i, b) in seq.bytes().enumerate().rev() {
+        if b as char == base {
+            score += 1;
+        } else {
+            score += POLY_X_MISMATCH_SCORE;
+            mismatches += 1;
+            if mismatches > POLY_X_MAX_MISMATCHES {
+                break;
+            }
+        }
+        if score > best_score && mismatches <= (n - i) / POLY_X_BASES_PER_MISMATCH {
+            best_score = score;
+            start = i;
+        }
+    }
+    if n - start >= min_length { start } else { n }
+}
+
+/// Parses `BASE[:MIN_LENGTH]` of a poly-X tail, e.g. `G` or `A:15`
+pub fn parse_poly_x_tail(s: &str) -> Result<(char, usize), String> {
+    let (base, min_length) = match s.split_once(':') {
+        Some((base, len)) => (base, len.parse().map_err(|_| format!("invalid length in {s}"))?),
+        None => (s, DEFAULT_POLY_X_MIN_LENGTH),
+    };
+    match base.to_uppercase().as_str() {
+        b @ ("A" | "C" | "G" | "T") => Ok((b.chars().next().unwrap(), min_length)),
+        _ => Err(format!("expected a base A, C, G or T, got {s}")),
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{dust_score, entropy, parse_poly_x_tail, poly_x_tail_start};
+
+    #[test]
+    fn test_dust() {
+        assert_eq!(dust_score(&"A".repeat(64)), 100.0);
+        assert_eq!(dust_score(&"A".repeat(150)), 100.0);
+        // dinucleotide repeat: two triplets
+        assert!(dust_score(&"CA".repeat(50)) > 40.0);
+        assert!(dust_score("TGCATGCAAGGCTTACCGATTACGGATCAGGCATTCAGGACGTACCAT") < 7.0);
+        assert_eq!(dust_score("AC"), 0.0);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&"A".repeat(64)), 0.0);
+        assert!(entropy(&"CA".repeat(50)) < 20.0);
+        assert!(entropy("TGCATGCAAGGCTTACCGATTACGGATCAGGCATTCAGGACGTACCAT") > 70.0);
+        assert_eq!(entropy(""), 0.0);
+    }
+
+    #[test]
+    fn test_poly_x_tail() {
+        assert_eq!(poly_x_tail_start("ACGTACGTGGGGGGGGGGGG", 'G', 10), 8);
+        // a mismatch per 8 bases is tolerated
+        assert_eq!(poly_x_tail_start("ACGTACGTGGGGAGGGGGGGG", 'G', 10), 8);
+        assert_eq!(poly_x_tail_start("ACGTACGTGGGGGGGGGAGGG", 'G', 10), 8);
+        assert_eq!(poly_x_tail_start("ACGTACGTGAGAGAGGG", 'G', 10), 17);
+        // too short
+        assert_eq!(poly_x_tail_start("ACGTACGTAAAAA", 'A', 10), 13);
+        assert_eq!(poly_x_tail_start("ACGTACGTAAAAA", 'A', 5), 8);
+        assert_eq!(parse_poly_x_tail("a:15").unwrap(), ('A', 15));
+        assert_eq!(parse_poly_x_tail("G").unwrap(), ('G', 10));
+    }
+}
@@ -0,0 +1,170 @@
+//! Base composition per position and GC content per read,
+//! e.g. to spot biased first bases (random hexamer priming), poly-G tails
+//! or GC shifts hinting at contamination
+use serde::{Deserialize, Serialize};
+
+use crate::accumulator::FastqAccumulator;
+use crate::histogram::Histogram;
+use crate::io::FastqEntry;
+
+/// Bases in the order they are counted; anything that's not ACGT is counted as N
+pub const BASES: [char; 5] = ['A', 'C', 'G', 'T', 'N'];
+
+fn base_index(base: u8) -> usize {
+    match base {
+        b'A' | b'a' => 0,
+        b'C' | b'c' => 1,
+        b'G' | b'g' => 2,
+        b'T' | b't' => 3,
+        _ => 4,
+    }
+}
+
+/// Per-position A/C/G/T/N counts and a histogram of the GC% of each read (bins of 1%)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseComposition {
+    counts: Vec<[u64; 5]>,
+    pub gc_content: Histogram,
+}
+
+impl Default for BaseComposition {
+    fn default() -> Self {
+        BaseComposition { counts: Vec::new(), gc_content: Histogram::new(1.0) }
+    }
+}
+
+/// Base fractions at a single position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionComposition {
+    /// 0-based position in the read
+    pub position: usize,
+    /// number of bases observed at this position
+    pub count: u64,
+    pub a: f64,
+    pub c: f64,
+    pub g: f64,
+    pub t: f64,
+    pub n: f64,
+    pub gc: f64,
+}
+
+impl BaseComposition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// number of positions (i.e. the length of the longest read)
+    pub fn n_positions(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// A/C/G/T/N counts at the position, in the order of [`BASES`]
+    pub fn position_counts(&self, position: usize) -> &[u64; 5] {
+        &self.counts[position]
+    }
+
+    /// Base fractions at each position
+    pub fn per_position(&self) -> Vec<PositionComposition> {
+        self.counts.iter().enumerate()
+            .map(|(position, c)| {
+                let total: u64 = c.iter().sum();
+                let f = |x: u64| x as f64 / total as f64;
+                PositionComposition {
+                    position,
+                    count: total,
+                    a: f(c[0]),
+                    c: f(c[1]),
+                    g: f(c[2]),
+                    t: f(c[3]),
+                    n: f(c[4]),
+                    gc: f(c[1] + c[2]),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the per-position fractions to `{prefix}.per_position.csv` and the
+    /// per-read GC histogram to `{prefix}.gc.csv`
+    pub fn write_csv(&self, prefix: &str) -> Result<(), csv::Error> {
+        let mut wtr = csv::Writer::from_path(format!("{prefix}.per_position.csv"))?;
+        for row in self.per_position() {
+            wtr.serialize(row)?;
+        }
+        wtr.flush()?;
+        self.gc_content.write_csv(&format!("{prefix}.gc.csv"))?;
+        Ok(())
+    }
+}
+
+/// GC content of the sequence in percent (NaN for empty sequences)
+pub fn gc_percent(seq: &str) -> f64 {
+    let gc = seq.bytes().filter(|b| matches!(b, b'G' | b'C' | b'g' | b'c')).count();
+    100.0 * gc as f64 / seq.len() as f64
+}
+
+impl FastqAccumulator for BaseComposition {
+    fn add(&mut self, fq: &FastqEntry) {
+        let seq = fq.seq.as_bytes();
+        if seq.len() > self.counts.len() {
+            self.counts.resize(seq.len(), [0; 5]);
+        }
+        for (row, &base) in self.counts.iter_mut().zip(seq) {
+            row[base_index(base)] += 1;
+        }
+        if !seq.is_empty() {
+            // rounding, so that 100% ends up in its own bin
+            self.gc_content.add(gc_percent(&fq.seq).round());
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), [0; 5]);
+        }
+        for (row, other_row) in self.counts.iter_mut().zip(other.counts) {
+            for (c, o) in row.iter_mut().zip(other_row) {
+                *c += o;
+            }
+        }
+        self.gc_content.merge(&other.gc_content);
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{gc_percent, BaseComposition};
+    use crate::accumulator::FastqAccumulator;
+    use crate::io::FastqEntry;
+
+    fn entry(seq: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: "I".repeat(seq.len()) }
+    }
+
+    #[test]
+    fn test_composition() {
+        let mut c = BaseComposition::new();
+        c.add(&entry("ACGG"));
+        c.add(&entry("GCN"));
+        c.add(&entry("Ggx"));
+
+        assert_eq!(c.position_counts(0), &[1, 0, 2, 0, 0]);
+        assert_eq!(c.position_counts(2), &[0, 0, 1, 0, 2]);
+        assert_eq!(c.position_counts(3), &[0, 0, 1, 0, 0]);
+
+        let p = c.per_position();
+        assert_eq!(p[1].count, 3);
+        assert_eq!(p[1].c, 2.0 / 3.0);
+        assert_eq!(p[1].gc, 1.0);
+
+        // 75%, 67%, 67%
+        assert_eq!(c.gc_content.counts()[75], 1);
+        assert_eq!(c.gc_content.counts()[67], 2);
+    }
+
+    #[test]
+    fn test_gc_percent() {
+        assert_eq!(gc_percent("GGCC"), 100.0);
+        assert_eq!(gc_percent("ATGC"), 50.0);
+        assert!(gc_percent("").is_nan());
+    }
+}
@@ -0,0 +1,297 @@
+//! Sequence duplication levels and library complexity
+//!
+//! Reads (or read pairs) are hashed and the number of copies of each hash is counted.
+//! To bound the memory, only hashes below a threshold are kept once the table grows
+//! too large: this subsamples *distinct sequences* (with all their copies), so the shape
+//! of the duplication histogram is preserved and counts are scaled back up by the sampling rate.
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::accumulator::FastqAccumulator;
+use crate::io::FastqEntry;
+
+/// Default maximum number of distinct hashes kept in memory
+pub const DEFAULT_MAX_ENTRIES: usize = 2_000_000;
+
+/// Upper bounds of the duplication level bins (as in FastQC); anything above the last goes into `>10k`
+const LEVEL_BINS: &[(u64, &str)] = &[
+    (1, "1"), (2, "2"), (3, "3"), (4, "4"), (5, "5"), (6, "6"), (7, "7"), (8, "8"), (9, "9"),
+    (50, "10-50"), (100, "51-100"), (500, "101-500"), (1000, "501-1k"), (5000, "1k-5k"),
+    (10000, "5k-10k"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicationCounter {
+    counts: HashMap<u64, u64>,
+    /// hashes are only kept if they have at least this many leading zeros,
+    /// i.e. a fraction 2^-shift of all distinct sequences is tracked
+    shift: u32,
+    max_entries: usize,
+    n_reads: u64,
+}
+
+impl Default for DuplicationCounter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+/// One bin of the duplication level histogram
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplicationLevel {
+    /// number of copies, e.g. "2" or "10-50"
+    pub level: String,
+    /// estimated number of distinct sequences with that many copies
+    pub distinct: f64,
+    pub percent_of_distinct: f64,
+    /// percentage of all reads that belong to sequences with that many copies
+    pub percent_of_reads: f64,
+}
+
+/// A point on the complexity curve: expected number of distinct sequences at a sequencing depth
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComplexityPoint {
+    pub reads: f64,
+    pub distinct: f64,
+    /// whether this point is beyond the observed depth
+    pub extrapolated: bool,
+}
+
+fn hash_seq<H: Hash>(x: H) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    x.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl DuplicationCounter {
+    /// Keeps at most `max_entries` distinct hashes in memory
+    pub fn new(max_entries: usize) -> Self {
+        DuplicationCounter { counts: HashMap::new(), shift: 0, max_entries, n_reads: 0 }
+    }
+
+    /// Adds a single sequence
+    pub fn add_sequence(&mut self, seq: &str) {
+        self.add_hash(hash_seq(seq))
+    }
+
+    /// Adds a read pair: duplicates have to agree in both reads
+    pub fn add_pair(&mut self, r1: &FastqEntry, r2: &FastqEntry) {
+        self.add_hash(hash_seq((&r1.seq, &r2.seq)))
+    }
+
+    fn add_hash(&mut self, h: u64) {
+        self.n_reads += 1;
+        if h.leading_zeros() >= self.shift {
+            *self.counts.entry(h).or_insert(0) += 1;
+            if self.counts.len() > self.max_entries {
+                self.increase_shift(self.shift + 1);
+            }
+        }
+    }
+
+    /// drops all hashes not passing the (stricter) sampling threshold
+    fn increase_shift(&mut self, shift: u32) {
+        self.shift = shift;
+        self.counts.retain(|h, _| h.leading_zeros() >= shift);
+    }
+
+    /// total number of reads (pairs) added
+    pub fn n_reads(&self) -> u64 {
+        self.n_reads
+    }
+
+    /// fraction of distinct sequences that are tracked
+    pub fn sampling_rate(&self) -> f64 {
+        0.5_f64.powi(self.shift as i32)
+    }
+
+    /// For each number of copies: estimated number of distinct sequences with that many copies
+    pub fn copy_number_histogram(&self) -> BTreeMap<u64, f64> {
+        let scale = 1.0 / self.sampling_rate();
+        let mut hist = BTreeMap::new();
+        for &count in self.counts.values() {
+            *hist.entry(count).or_insert(0.0) += scale;
+        }
+        hist
+    }
+
+    /// estimated number of distinct sequences
+    pub fn distinct(&self) -> f64 {
+        self.counts.len() as f64 / self.sampling_rate()
+    }
+
+    /// Estimated percentage of reads left after deduplication
+    pub fn percent_unique(&self) -> f64 {
+        100.0 * self.distinct() / self.n_reads as f64
+    }
+
+    /// Duplication level histogram, binned like FastQC's
+    pub fn duplication_levels(&self) -> Vec<DuplicationLevel> {
+        let distinct_total = self.distinct();
+        let mut bins: Vec<(String, f64, f64)> = LEVEL_BINS.iter().map(|(_, name)| (name.to_string(), 0.0, 0.0)).collect();
+        bins.push((">10k".to_string(), 0.0, 0.0));
+
+        for (copies, n) in self.copy_number_histogram() {
+            let i = LEVEL_BINS.iter().position(|(upper, _)| copies <= *upper).unwrap_or(LEVEL_BINS.len());
+            bins[i].1 += n;
+            bins[i].2 += n * copies as f64;
+        }
+        bins.into_iter()
+            .map(|(level, distinct, reads)| DuplicationLevel {
+                level,
+                distinct,
+                percent_of_distinct: 100.0 * distinct / distinct_total,
+                percent_of_reads: 100.0 * reads / self.n_reads as f64,
+            })
+            .collect()
+    }
+
+    /// Expected number of distinct sequences when sequencing `n_points` depths
+    /// up to `max_multiple` times the current depth.
+    ///
+    /// Up to the observed depth, this is the expected number of distinct sequences when
+    /// subsampling the reads (rarefaction). Beyond, it uses the extrapolation of
+    /// Chao et al. (2014), based on the number of sequences seen once and twice
+    pub fn complexity_curve(&self, max_multiple: f64, n_points: usize) -> Vec<ComplexityPoint> {
+        let n = self.n_reads as f64;
+        let hist = self.copy_number_histogram();
+        let s_obs = self.distinct();
+        let f1 = hist.get(&1).copied().unwrap_or(0.0);
+        let f2 = hist.get(&2).copied().unwrap_or(0.0);
+        // estimated number of unseen sequences
+        let f0 = if f2 > 0.0 {
+            (n - 1.0) / n * f1 * f1 / (2.0 * f2)
+        } else {
+            (n - 1.0) / n * f1 * (f1 - 1.0).max(0.0) / 2.0
+        };
+
+        (1..=n_points)
+            .map(|i| {
+                let t = max_multiple * i as f64 / n_points as f64;
+                let reads = t * n;
+                let distinct = if t <= 1.0 {
+                    hist.iter()
+                        .map(|(&copies, &n_seqs)| n_seqs * (1.0 - (1.0 - t).powi(copies as i32)))
+                        .sum()
+                } else if f1 == 0.0 || f0 == 0.0 {
+                    s_obs
+                } else {
+                    let m = reads - n;
+                    s_obs + f0 * (1.0 - (1.0 - f1 / (n * f0 + f1)).powf(m))
+                };
+                ComplexityPoint { reads, distinct, extrapolated: t > 1.0 }
+            })
+            .collect()
+    }
+}
+
+impl FastqAccumulator for DuplicationCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        self.add_sequence(&fq.seq)
+    }
+
+    fn merge(&mut self, other: Self) {
+        let shift = self.shift.max(other.shift);
+        self.increase_shift(shift);
+        for (h, count) in other.counts {
+            if h.leading_zeros() >= shift {
+                *self.counts.entry(h).or_insert(0) += count;
+            }
+        }
+        self.n_reads += other.n_reads;
+        while self.counts.len() > self.max_entries {
+            self.increase_shift(self.shift + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::DuplicationCounter;
+    use crate::accumulator::FastqAccumulator;
+    use crate::io::FastqEntry;
+
+    fn entry(seq: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: "I".repeat(seq.len()) }
+    }
+
+    #[test]
+    fn test_duplication() {
+        let mut c = DuplicationCounter::default();
+        // 1 sequence x3, 2 sequences x1
+        for seq in ["AAA", "AAA", "AAA", "CCC", "GGG"] {
+            c.add(&entry(seq));
+        }
+        assert_eq!(c.n_reads(), 5);
+        assert_eq!(c.distinct(), 3.0);
+        assert_eq!(c.percent_unique(), 60.0);
+
+        let levels = c.duplication_levels();
+        assert_eq!(levels[0].level, "1");
+        assert_eq!(levels[0].distinct, 2.0);
+        assert_eq!(levels[0].percent_of_reads, 40.0);
+        assert_eq!(levels[2].level, "3");
+        assert_eq!(levels[2].percent_of_reads, 60.0);
+    }
+
+    #[test]
+    fn test_pairs() {
+        let mut c = DuplicationCounter::default();
+        c.add_pair(&entry("AAA"), &entry("CCC"));
+        c.add_pair(&entry("AAA"), &entry("GGG"));
+        c.add_pair(&entry("AAA"), &entry("CCC"));
+        assert_eq!(c.distinct(), 2.0);
+    }
+
+    #[test]
+    fn test_bounded_memory() {
+        let mut c = DuplicationCounter::new(1000);
+        for i in 0..20_000 {
+            // every sequence twice
+            let seq = format!("{}", i / 2);
+            c.add_sequence(&seq);
+        }
+        assert!(c.sampling_rate() < 1.0);
+        // the estimate is approximate, but should be close to 10k
+        let distinct = c.distinct();
+        assert!((7_000.0..13_000.0).contains(&distinct), "{distinct}");
+        // all tracked sequences have two copies
+        assert_eq!(c.copy_number_histogram().keys().copied().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = DuplicationCounter::default();
+        a.add(&entry("AAA"));
+        let mut b = DuplicationCounter::default();
+        b.add(&entry("AAA"));
+        b.add(&entry("CCC"));
+        a.merge(b);
+        assert_eq!(a.n_reads(), 3);
+        assert_eq!(a.distinct(), 2.0);
+    }
+
+    #[test]
+    fn test_complexity_curve() {
+        let mut c = DuplicationCounter::default();
+        for i in 0..1000 {
+            // 500 sequences seen once, 250 seen twice
+            let seq = if i < 500 { format!("s{i}") } else { format!("d{}", i / 2) };
+            c.add_sequence(&seq);
+        }
+        let curve = c.complexity_curve(2.0, 4);
+        assert_eq!(curve.len(), 4);
+        // at the observed depth, all observed sequences
+        assert_eq!(curve[1].reads, 1000.0);
+        assert!((curve[1].distinct - 750.0).abs() < 1e-6);
+        assert!(!curve[1].extrapolated);
+        // half the reads: 500 * 0.5 + 250 * 0.75
+        assert!((curve[0].distinct - 437.5).abs() < 1e-6);
+        // extrapolating: more, but less than linear
+        assert!(curve[3].extrapolated);
+        assert!(curve[3].distinct > 750.0 && curve[3].distinct < 1500.0);
+        assert!(curve[2].distinct < curve[3].distinct);
+    }
+}
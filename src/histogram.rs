@@ -0,0 +1,139 @@
+//! Fixed-width histograms, e.g. for read lengths or mean read qualities
+use serde::{Deserialize, Serialize};
+
+/// Histogram with bins of equal width, the first bin starting at 0.
+/// Negative values end up in the first bin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    bin_width: f64,
+    counts: Vec<u64>,
+}
+
+/// A single bin `[bin_start, bin_end)`, as written to the histogram tables
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistogramRow {
+    pub bin_start: f64,
+    pub bin_end: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn new(bin_width: f64) -> Self {
+        assert!(bin_width > 0.0, "bin width must be positive");
+        Histogram { bin_width, counts: Vec::new() }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.add_n(value, 1)
+    }
+
+    /// adds `value` `n` times
+    pub fn add_n(&mut self, value: f64, n: u64) {
+        let bin = (value / self.bin_width).floor().max(0.0) as usize;
+        if bin >= self.counts.len() {
+            self.counts.resize(bin + 1, 0);
+        }
+        self.counts[bin] += n;
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        assert_eq!(self.bin_width, other.bin_width, "can't merge histograms with different bins");
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (c, o) in self.counts.iter_mut().zip(&other.counts) {
+            *c += o;
+        }
+    }
+
+    pub fn bin_width(&self) -> f64 {
+        self.bin_width
+    }
+
+    /// counts per bin, the i-th bin being `[i * bin_width, (i+1) * bin_width)`
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// start of the i-th bin, rounded so that e.g. bins of 0.1 don't show up as 0.30000000000000004
+    fn bin_start(&self, i: usize) -> f64 {
+        (i as f64 * self.bin_width * 1e9).round() / 1e9
+    }
+
+    /// total number of values added
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Mean, approximating each value by the start of its bin
+    pub fn mean(&self) -> f64 {
+        let weighted: f64 = self.counts.iter().enumerate()
+            .map(|(i, &c)| self.bin_start(i) * c as f64)
+            .sum();
+        weighted / self.total() as f64
+    }
+
+    /// Quantile (nearest rank), reported as the start of the bin it falls into.
+    /// NaN if the histogram is empty
+    pub fn quantile(&self, p: f64) -> f64 {
+        let bin = crate::phred_counter::quantile(&self.counts, p);
+        if bin.is_nan() {
+            return f64::NAN;
+        }
+        self.bin_start(bin as usize)
+    }
+
+    /// all non-empty bins
+    pub fn rows(&self) -> impl Iterator<Item = HistogramRow> + '_ {
+        self.counts.iter().enumerate()
+            .filter(|(_, &c)| c > 0)
+            .map(|(i, &count)| HistogramRow {
+                bin_start: self.bin_start(i),
+                bin_end: self.bin_start(i + 1),
+                count,
+            })
+    }
+
+    /// Writes all non-empty bins as csv: bin_start, bin_end, count
+    pub fn write_csv(&self, output_csv_file: &str) -> Result<(), csv::Error> {
+        let mut wtr = csv::Writer::from_path(output_csv_file)?;
+        for row in self.rows() {
+            wtr.serialize(row)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{Histogram, HistogramRow};
+
+    #[test]
+    fn test_histogram() {
+        let mut h = Histogram::new(0.5);
+        h.add(0.1);
+        h.add(0.4);
+        h.add(1.2);
+        h.add(-3.0);
+        assert_eq!(h.counts(), &[3, 0, 1]);
+        assert_eq!(h.total(), 4);
+        assert_eq!(h.quantile(0.5), 0.0);
+        assert_eq!(h.quantile(1.0), 1.0);
+
+        let rows: Vec<_> = h.rows().collect();
+        assert_eq!(rows[1], HistogramRow { bin_start: 1.0, bin_end: 1.5, count: 1 });
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = Histogram::new(1.0);
+        a.add(1.0);
+        let mut b = Histogram::new(1.0);
+        b.add(3.0);
+        b.add(1.0);
+        a.merge(&b);
+        assert_eq!(a.counts(), &[0, 2, 0, 1]);
+        assert_eq!(a.mean(), 5.0 / 3.0);
+    }
+}
@@ -0,0 +1,151 @@
+//! Parsing the information Illumina encodes in the read names
+//!
+//! Casava >= 1.8: `@<instrument>:<run>:<flowcell>:<lane>:<tile>:<x>:<y> <read>:<filtered>:<control>:<index>`
+//!
+//! older Illumina pipelines: `@<instrument>:<lane>:<tile>:<x>:<y>#<index>/<read>`
+use std::fmt;
+use std::str::FromStr;
+
+/// Location of a cluster on the flow cell, plus the instrument/run it was sequenced in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IlluminaHeader {
+    pub instrument: String,
+    /// run number on the instrument (not present in the old format)
+    pub run: Option<u32>,
+    /// flowcell ID (not present in the old format)
+    pub flowcell: Option<String>,
+    pub lane: u32,
+    pub tile: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl IlluminaHeader {
+    /// Parses a FastQ header (with or without leading '@', the description is ignored).
+    /// Returns `None` if it's not an Illumina read name
+    pub fn parse(header: &str) -> Option<Self> {
+        let header = header.strip_prefix('@').unwrap_or(header);
+        let name = header.split_whitespace().next()?;
+        let fields: Vec<&str> = name.split(':').collect();
+
+        match fields.len() {
+            7 => Some(IlluminaHeader {
+                instrument: fields[0].to_string(),
+                run: Some(fields[1].parse().ok()?),
+                flowcell: Some(fields[2].to_string()),
+                lane: fields[3].parse().ok()?,
+                tile: fields[4].parse().ok()?,
+                x: fields[5].parse().ok()?,
+                y: fields[6].parse().ok()?,
+            }),
+            5 => {
+                // the y-coordinate might have the index/read number attached: 1973#0/1
+                let y = fields[4].split(['#', '/']).next()?;
+                Some(IlluminaHeader {
+                    instrument: fields[0].to_string(),
+                    run: None,
+                    flowcell: None,
+                    lane: fields[1].parse().ok()?,
+                    tile: fields[2].parse().ok()?,
+                    x: fields[3].parse().ok()?,
+                    y: y.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn tile_id(&self) -> TileId {
+        TileId { lane: self.lane, tile: self.tile }
+    }
+}
+
+/// The sample index of the read (e.g. `ACGTACGT+TTGCATGC` for dual indices), if the header has one:
+/// the last field of the Casava 1.8 description, or the part after `#` in the old format.
+/// Numeric indices (e.g. `0` or the sample number) are not returned
+pub fn index_sequence(header: &str) -> Option<&str> {
+    let header = header.strip_prefix('@').unwrap_or(header);
+    let index = match header.split_once(' ') {
+        Some((_, description)) => description.split(':').nth(3)?,
+        None => header.split_once('#')?.1.split('/').next()?,
+    };
+    let is_sequence = !index.is_empty() && index.bytes().all(|b| b.is_ascii_alphabetic() || b == b'+');
+    is_sequence.then_some(index)
+}
+
+/// A tile on the flowcell, identified by lane and tile number. Written as `lane:tile`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TileId {
+    pub lane: u32,
+    pub tile: u32,
+}
+
+impl fmt::Display for TileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.lane, self.tile)
+    }
+}
+
+impl FromStr for TileId {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (lane, tile) = s.split_once(':').ok_or(format!("expected lane:tile, got {s}"))?;
+        Ok(TileId {
+            lane: lane.parse().map_err(|_| format!("invalid lane in {s}"))?,
+            tile: tile.parse().map_err(|_| format!("invalid tile in {s}"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{index_sequence, IlluminaHeader, TileId};
+
+    #[test]
+    fn test_parse_casava18() {
+        let h = IlluminaHeader::parse("@A00123:8:HVWMHDSX2:4:1101:10004:1000 1:N:0:ACGTACGT+TTGCATGC").unwrap();
+        assert_eq!(h, IlluminaHeader {
+            instrument: "A00123".to_string(),
+            run: Some(8),
+            flowcell: Some("HVWMHDSX2".to_string()),
+            lane: 4,
+            tile: 1101,
+            x: 10004,
+            y: 1000,
+        });
+        assert_eq!(h.tile_id(), TileId { lane: 4, tile: 1101 });
+    }
+
+    #[test]
+    fn test_parse_old_format() {
+        let h = IlluminaHeader::parse("@HWUSI-EAS100R:6:73:941:1973#0/1").unwrap();
+        assert_eq!(h.instrument, "HWUSI-EAS100R");
+        assert_eq!(h.run, None);
+        assert_eq!((h.lane, h.tile, h.x, h.y), (6, 73, 941, 1973));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(IlluminaHeader::parse("@read1"), None);
+        assert_eq!(IlluminaHeader::parse("@SRR123.1 1 length=100"), None);
+        assert_eq!(IlluminaHeader::parse("@A:1:FC:x:1101:1:1"), None);
+    }
+
+    #[test]
+    fn test_tile_id() {
+        let t: TileId = "2:1204".parse().unwrap();
+        assert_eq!(t, TileId { lane: 2, tile: 1204 });
+        assert_eq!(t.to_string(), "2:1204");
+        assert!("1204".parse::<TileId>().is_err());
+    }
+
+    #[test]
+    fn test_index_sequence() {
+        assert_eq!(index_sequence("@A00123:8:HVWMHDSX2:4:1101:10004:1000 1:N:0:ACGTACGT+TTGCATGC"), Some("ACGTACGT+TTGCATGC"));
+        assert_eq!(index_sequence("@HWUSI-EAS100R:6:73:941:1973#ACGT/1"), Some("ACGT"));
+        // sample numbers instead of sequences
+        assert_eq!(index_sequence("@HWUSI-EAS100R:6:73:941:1973#0/1"), None);
+        assert_eq!(index_sequence("@A00123:8:HVWMHDSX2:4:1101:10004:1000 1:N:0:2"), None);
+        assert_eq!(index_sequence("@read1"), None);
+    }
+}
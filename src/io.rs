@@ -4,7 +4,11 @@ use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::BufWriter;
-use std::io::Write;
+
+use itertools::{EitherOrBoth, Itertools};
+
+// moved to `names`, kept here for existing callers
+pub use crate::names::read_filter_whitelist;
 
 fn switch_base(base: char) -> char{
     match base {
@@ -49,6 +53,22 @@ impl FastqEntry {
         s.push('\n');
         s
     }
+    /// Keeps only the bases `start..end`, of both sequence and quality
+    pub fn keep_range(&mut self, start: usize, end: usize) {
+        assert!(start <= end && end <= self.seq.len(), "invalid range {start}..{end}");
+        self.seq.truncate(end);
+        self.seq.drain(..start);
+        self.phred.truncate(end);
+        self.phred.drain(..start);
+    }
+
+    pub fn len(&self) -> usize {
+        self.seq.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seq.is_empty()
+    }
 }
 
 // ==========================================================
@@ -117,13 +137,40 @@ pub struct FastIterator {
 
 impl FastIterator {
     pub fn new(fastqname: &str) -> Self {
-        let decoder = noodles_bgzf::reader::Builder.build_from_path(fastqname).unwrap();
+        Self::try_new(fastqname).unwrap()
+    }
+
+    /// Like [`FastIterator::new`], but returns the error instead of panicking
+    /// if the file can't be opened
+    pub fn try_new(fastqname: &str) -> std::io::Result<Self> {
+        let decoder = noodles_bgzf::reader::Builder.build_from_path(fastqname)?;
         let reader = fastq::io::Reader::new(decoder);
 
-        FastIterator { 
+        Ok(FastIterator { 
             reader , 
             // just a dummy
             buffer: fastq::Record::new(fastq::record::Definition::new("r0", ""), "AGCT", "NDLS")
+        })
+    }
+
+    /// Reads the next record, returning `Ok(None)` at the end of the file.
+    /// Unlike the [`Iterator`] impl, parsing/IO errors are returned to the caller
+    pub fn try_next(&mut self) -> std::io::Result<Option<FastqEntry>> {
+        let nread = self.reader.read_record(&mut self.buffer)?;
+        if nread == 0 {
+            Ok(None)
+        } else {
+            let seq = str::from_utf8(self.buffer.sequence())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            let phred = str::from_utf8(self.buffer.quality_scores())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            if seq.len() != phred.len() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("sequence and quality length differ for read {}", self.buffer.name()),
+                ));
+            }
+            Ok(Some(noodles_record_to_fastq_entry(&self.buffer)))
         }
     }
 }
@@ -135,10 +182,16 @@ fn noodles_record_to_fastq_entry(record: &fastq::Record) -> FastqEntry {
     let phred_bytes = record.quality_scores();
     let phred_string = str::from_utf8(phred_bytes).unwrap();
     let seq_string = str::from_utf8(seq_bytes).unwrap();
-    let mut name_string =  record.name().to_string();
+    // noodles strips the leading '@', but our headers keep it, so that
+    // `FastqEntry::to_string` yields a valid record
+    let mut name_string = String::with_capacity(record.name().len() + record.description().len() + 2);
+    name_string.push('@');
+    name_string.push_str(&record.name().to_string());
     let desc = record.description().to_string();
-    name_string.push(' ');
-    name_string.push_str(&desc);
+    if !desc.is_empty() {
+        name_string.push(' ');
+        name_string.push_str(&desc);
+    }
 
     FastqEntry {
         seq: seq_string.to_owned(),
@@ -219,7 +272,8 @@ pub struct PhredCache {
 impl PhredCache {
     pub fn new() -> Self {
         let mut cache: Vec<f32> = Vec::new();
-        for i in 33..76 {
+        // all printable Phred+33 symbols, '!' (Q0) to '~' (Q93), long reads can exceed Q41
+        for i in 33..127 {
             let c: char = i.into();
             let p = phred_symbol_to_prob(c);
             cache.push(p);
@@ -240,7 +294,7 @@ fn phred_symbol_to_prob(phred: char) -> f32 {
     10_f32.powf(-(q as f32) / 10_f32)
 }
 
-fn get_bgzf_writer(outname: &str) -> noodles_bgzf::Writer<BufWriter<File>> {
+pub(crate) fn get_bgzf_writer(outname: &str) -> noodles_bgzf::Writer<BufWriter<File>> {
     let inner = BufWriter::new(File::create(outname).unwrap());
     noodles_bgzf::Writer::new(inner)
 }
@@ -256,78 +310,6 @@ fn get_bgzf_writer(outname: &str) -> noodles_bgzf::Writer<BufWriter<File>> {
 //     "phred score to ascii"
 //     return str(chr(phred+33))
 
-/// Filters a fastq-file for all reads having an aggregated PhredScore of > `threshold_qc`
-/// # Parameters:
-/// * fastqname: File to be filtered
-/// * outname: File where to write the filtered records
-/// * threshold_qc: minimum  (aggreated) Phred Score a read needs to pass to get written
-pub fn quality_filter(fastqname: &str, outname: &str, threshold_qc: f32) {
-    // reading the fastq
-    let fastq_iter = FastIterator::new(fastqname);
-
-    let cache = PhredCache::new();
-
-    let mut writer = get_bgzf_writer(outname);
-
-    // let encoder = bgzf::Writer::from_path_with_level(outname, CompressionLevel::Fastest).unwrap();
-    // let mut writer = BufWriter::new(encoder);
-
-    let mut total_reads = 0;
-    let mut passing_reads = 0;
-
-    for fq in fastq_iter {
-        total_reads += 1;
-
-        let probs: f32 = fq.phred.chars().map(|c| cache.get_prob(c)).sum();
-        let avg_qual = probs / (fq.phred.len() as f32);
-
-        if avg_qual < threshold_qc {
-            write!(writer, "{}", fq.to_string()).unwrap();
-            passing_reads += 1;
-        }
-    }
-    println!(
-        "{}/{}({}) reads passed QC",
-        passing_reads,
-        total_reads,
-        (passing_reads as f32) / (total_reads as f32)
-    )
-}
-
-// zcat kraken_out.filtered.gz | awk '{ print $2}' | less
-pub fn read_filter_whitelist(fastqname: &str, outname: &str, whitelist: &str) {
-    let whitelist_reader = BufReader::new(File::open(whitelist).unwrap());
-    let whitelist_header: HashSet<String> = whitelist_reader.lines().map(|f| f.unwrap()).collect();
-
-    // reading the fastq
-    let fastq_iter = FastIterator::new(fastqname);
-
-    //writing the filtered
-    // let encoder = bgzf::Writer::from_path_with_level(outname, CompressionLevel::Fastest).unwrap();
-    // let mut writer = BufWriter::new(encoder);
-
-    let mut writer = get_bgzf_writer(outname);
-
-
-    let mut total_reads = 0;
-    let mut passing_reads = 0;
-
-    for fq in fastq_iter {
-        total_reads += 1;
-
-        if whitelist_header.contains(&fq.header) {
-            write!(writer, "{}", fq.to_string()).unwrap();
-            passing_reads += 1;
-        }
-    }
-    println!(
-        "{}/{}({}) reads were whitelisted",
-        passing_reads,
-        total_reads,
-        (passing_reads as f32) / (total_reads as f32)
-    )
-}
-
 /// Chaining many fastq files into a single iterator
 pub fn fastq_list_iter(fastq_list: &[String]) -> impl Iterator<Item = FastqEntry> + '_ {
     let my_iter = fastq_list
@@ -336,12 +318,52 @@ pub fn fastq_list_iter(fastq_list: &[String]) -> impl Iterator<Item = FastqEntry
     my_iter
 }
 
+/// Iterating R1/R2 files together, yielding the pairs of reads.
+/// Panics if the files don't have the same number of reads
+pub fn paired_fastq_list_iter<'a>(r1_list: &'a [String], r2_list: &'a [String]) -> impl Iterator<Item = (FastqEntry, FastqEntry)> + 'a {
+    fastq_list_iter(r1_list)
+        .zip_longest(fastq_list_iter(r2_list))
+        .map(|pair| match pair {
+            EitherOrBoth::Both(fq1, fq2) => (fq1, fq2),
+            _ => panic!("R1 and R2 have a different number of reads"),
+        })
+}
+
 /// Chaining many fastq files into a single iterator
 pub fn fastq_phred_iter(fastq_list: &[String]) -> impl Iterator<Item = String> + '_ {
     // instead if yielding the sequence, this one yields the PHRED ASCII scores of the reads
     fastq_list_iter(fastq_list).map(|fq| fq.phred)
 }
 
+/// Lines of a plain or gzip (`.gz`, bgzf included) text file
+pub fn read_lines(fname: &str) -> Result<Box<dyn Iterator<Item = Result<String, String>>>, String> {
+    let file = File::open(fname).map_err(|e| format!("{fname}: {e}"))?;
+    let reader: Box<dyn BufRead> = if fname.ends_with(".gz") {
+        // a bgzf file is a series of gzip members
+        Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    let fname = fname.to_string();
+    Ok(Box::new(reader.lines().map(move |l| l.map_err(|e| format!("{fname}: {e}")))))
+}
+
+/// Records of a (plain or gzip) FASTA file as `(name, sequence)`, the sequence in upper case
+pub fn read_fasta(fname: &str) -> Result<Vec<(String, String)>, String> {
+    let mut records: Vec<(String, String)> = Vec::new();
+    for line in read_lines(fname)? {
+        let line = line?;
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix('>') {
+            records.push((name.trim().to_string(), String::new()));
+        } else if !line.is_empty() {
+            let (_, seq) = records.last_mut().ok_or(format!("{fname}: sequence before the first header"))?;
+            seq.push_str(&line.to_uppercase());
+        }
+    }
+    Ok(records)
+}
+
 /// Loading 10x CB whilelist from file
 /// Returns a HashSet of CBs
 pub fn parse_whitelist_gz(fname: &String) -> HashSet<String> {
@@ -363,7 +385,9 @@ mod testing {
     use crate::io::reverse_complement;
 
     // #[test]
-    use super::{fastq_list_iter, quality_filter, FastqEntry, PhredCache};
+    use super::{fastq_list_iter, FastqEntry, PhredCache};
+    use crate::qcfilter::{filter_fastq, QualityCriterion, QualityFilter};
+    use crate::rejected::RejectedReads;
     use rust_htslib::bgzf;
     use rust_htslib::bgzf::CompressionLevel;
     use std::io::BufWriter;
@@ -407,7 +431,8 @@ mod testing {
         println!("Filtering!");
         use std::time::Instant;
         let now = Instant::now();
-        quality_filter(file, out, 0.01);
+        let filter = QualityFilter::new(vec![QualityCriterion::MaxMeanErrorProbability(0.01)]);
+        println!("{}", filter_fastq(&[file.to_string()], out, &filter, &mut RejectedReads::new(None)));
         let elapsed_time = now.elapsed();
         println!("Running took {} sec.", elapsed_time.as_secs());
     }
@@ -0,0 +1,256 @@
+//! Selecting reads by their Kraken2 classification, e.g. to extract a pathogen or to deplete host reads.
+//!
+//! Kraken2 writes a line per read (`C/U`, read ID, taxid, length, k-mer hits) and a report,
+//! whose indentation gives the taxonomy; taxa can be selected with all their descendants from it.
+//! The per-read output is in the order of the fastq, so both are read in lockstep
+//! (no set of read IDs is kept, which would be huge when depleting host reads)
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use itertools::{EitherOrBoth, Itertools};
+
+use crate::io::{fastq_list_iter, get_bgzf_writer, paired_fastq_list_iter, read_lines, FastqEntry};
+use crate::names::{normalize_read_name, NameFilterMode};
+
+/// Parses a taxid, also as written with `--use-names`: `Homo sapiens (taxid 9606)`
+pub fn parse_taxid(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let id = match s.rsplit_once("(taxid ") {
+        Some((_, id)) => id.strip_suffix(')').ok_or(format!("invalid taxid {s}"))?,
+        None => s,
+    };
+    id.parse().map_err(|_| format!("invalid taxid {s}"))
+}
+
+/// A line of the per-read output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KrakenRead {
+    pub classified: bool,
+    pub read_id: String,
+    /// 0 if unclassified
+    pub taxid: u64,
+}
+
+impl FromStr for KrakenRead {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split('\t').collect();
+        if fields.len() < 3 {
+            return Err(format!("expected a tab-separated kraken line, got {s}"));
+        }
+        let classified = match fields[0] {
+            "C" => true,
+            "U" => false,
+            c => return Err(format!("expected C or U, got {c}")),
+        };
+        Ok(KrakenRead { classified, read_id: fields[1].to_string(), taxid: parse_taxid(fields[2])? })
+    }
+}
+
+/// The taxonomy of a Kraken2 report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Taxonomy {
+    pub names: HashMap<u64, String>,
+    children: HashMap<u64, Vec<u64>>,
+}
+
+impl Taxonomy {
+    /// Parses the lines of a report (`percent, clade reads, direct reads, [minimizers,] rank, taxid, name`);
+    /// the depth of a taxon is the indentation of its name, two spaces per level
+    pub fn from_report_lines(lines: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut taxonomy = Taxonomy::default();
+        // ancestors of the current line, as (depth, taxid)
+        let mut lineage: Vec<(usize, u64)> = Vec::new();
+        for line in lines.filter(|l| !l.trim().is_empty()) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 6 {
+                return Err(format!("expected a kraken report line, got {line}"));
+            }
+            let name = fields[fields.len() - 1];
+            let taxid = parse_taxid(fields[fields.len() - 2])?;
+            let trimmed = name.trim_start();
+            let depth = (name.len() - trimmed.len()) / 2;
+
+            while lineage.last().is_some_and(|&(d, _)| d >= depth) {
+                lineage.pop();
+            }
+            if let Some(&(_, parent)) = lineage.last() {
+                taxonomy.children.entry(parent).or_default().push(taxid);
+            }
+            lineage.push((depth, taxid));
+            taxonomy.names.insert(taxid, trimmed.to_string());
+        }
+        Ok(taxonomy)
+    }
+
+    pub fn from_report(fname: &str) -> Result<Self, String> {
+        let lines = read_lines(fname)?.collect::<Result<Vec<String>, String>>()?;
+        Self::from_report_lines(lines.into_iter()).map_err(|e| format!("{fname}: {e}"))
+    }
+
+    /// The taxon and everything below it
+    pub fn descendants(&self, taxid: u64) -> HashSet<u64> {
+        let mut taxa = HashSet::new();
+        let mut todo = vec![taxid];
+        while let Some(t) = todo.pop() {
+            if taxa.insert(t) {
+                todo.extend(self.children.get(&t).into_iter().flatten());
+            }
+        }
+        taxa
+    }
+}
+
+/// Lines of the per-read output (plain or gzip)
+fn kraken_reads(kraken_output: &str) -> impl Iterator<Item = KrakenRead> + '_ {
+    read_lines(kraken_output).unwrap_or_else(|e| panic!("{e}"))
+        .map(move |line| line.and_then(|line| line.parse::<KrakenRead>()).unwrap_or_else(|e| panic!("{kraken_output}: {e}")))
+}
+
+/// Pairs each read (or pair, named by `fq`) with its line of the per-read output.
+/// Panics if the read IDs or the number of reads differ, i.e. the output isn't of these reads
+fn with_classification<'a, T: 'a>(reads: impl Iterator<Item = T> + 'a, fq: impl Fn(&T) -> &FastqEntry + 'a, kraken_output: &'a str) -> impl Iterator<Item = (T, KrakenRead)> + 'a {
+    reads.zip_longest(kraken_reads(kraken_output)).map(move |pair| match pair {
+        EitherOrBoth::Both(read, kraken) => {
+            let name = normalize_read_name(&fq(&read).header);
+            assert_eq!(name, normalize_read_name(&kraken.read_id), "{kraken_output} is not in the order of the fastq");
+            (read, kraken)
+        }
+        _ => panic!("{kraken_output} and the fastq have a different number of reads"),
+    })
+}
+
+/// Reads (or pairs) classified as one of the taxa, and kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct KrakenSummary {
+    pub mode: NameFilterMode,
+    pub total: u64,
+    pub selected: u64,
+    pub kept: u64,
+}
+
+impl KrakenSummary {
+    fn new(mode: NameFilterMode) -> Self {
+        KrakenSummary { mode, total: 0, selected: 0, kept: 0 }
+    }
+
+    /// Counts a read, returns whether it is kept
+    fn add(&mut self, kraken: &KrakenRead, taxids: &HashSet<u64>) -> bool {
+        let selected = taxids.contains(&kraken.taxid);
+        self.total += 1;
+        self.selected += selected as u64;
+        let keep = match self.mode {
+            NameFilterMode::Include => selected,
+            NameFilterMode::Exclude => !selected,
+        };
+        self.kept += keep as u64;
+        keep
+    }
+}
+
+impl fmt::Display for KrakenSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}/{} reads classified as the selected taxa", self.selected, self.total)?;
+        writeln!(f, "{}/{}({}) reads kept ({} selected)", self.kept, self.total, self.kept as f32 / self.total as f32, self.mode)
+    }
+}
+
+/// Writes the reads classified as one of the taxa (or, excluding, all others) to `outname`
+pub fn extract_reads(fastq_list: &[String], kraken_output: &str, taxids: &HashSet<u64>, mode: NameFilterMode, outname: &str) -> KrakenSummary {
+    let mut writer = get_bgzf_writer(outname);
+    let mut summary = KrakenSummary::new(mode);
+    for (fq, kraken) in with_classification(fastq_list_iter(fastq_list), |fq| fq, kraken_output) {
+        if summary.add(&kraken, taxids) {
+            write!(writer, "{}", fq.to_string()).unwrap();
+        }
+    }
+    summary
+}
+
+/// As [`extract_reads`], for R1/R2 classified together (kraken2 `--paired`)
+pub fn extract_reads_paired(r1_list: &[String], r2_list: &[String], kraken_output: &str, taxids: &HashSet<u64>, mode: NameFilterMode, outname_r1: &str, outname_r2: &str) -> KrakenSummary {
+    let mut writer_r1 = get_bgzf_writer(outname_r1);
+    let mut writer_r2 = get_bgzf_writer(outname_r2);
+    let mut summary = KrakenSummary::new(mode);
+    for ((fq1, fq2), kraken) in with_classification(paired_fastq_list_iter(r1_list, r2_list), |(fq1, _)| fq1, kraken_output) {
+        if summary.add(&kraken, taxids) {
+            write!(writer_r1, "{}", fq1.to_string()).unwrap();
+            write!(writer_r2, "{}", fq2.to_string()).unwrap();
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{extract_reads, extract_reads_paired, parse_taxid, KrakenRead, Taxonomy};
+    use crate::io::FastIterator;
+    use crate::names::NameFilterMode;
+    use crate::test_files::write_fastq_gz;
+    use std::collections::HashSet;
+    use std::io::Write;
+
+    const REPORT: &str = "\
+ 10.00\t10\t10\tU\t0\tunclassified
+ 90.00\t90\t0\tR\t1\troot
+ 60.00\t60\t5\tD\t2\t  Bacteria
+ 50.00\t50\t50\tS\t562\t    Escherichia coli
+  5.00\t5\t5\tS\t1280\t    Staphylococcus aureus
+ 30.00\t30\t30\tS\t9606\t  Homo sapiens
+";
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_taxid("9606").unwrap(), 9606);
+        assert_eq!(parse_taxid("Homo sapiens (taxid 9606)").unwrap(), 9606);
+        assert!(parse_taxid("Homo sapiens").is_err());
+        let read: KrakenRead = "C\tread1\t562\t150|150\t562:10 |:| 0:5".parse().unwrap();
+        assert_eq!(read, KrakenRead { classified: true, read_id: "read1".to_string(), taxid: 562 });
+        assert!("X\tread1\t562".parse::<KrakenRead>().is_err());
+    }
+
+    #[test]
+    fn test_taxonomy() {
+        let taxonomy = Taxonomy::from_report_lines(REPORT.lines().map(String::from)).unwrap();
+        assert_eq!(taxonomy.descendants(2), HashSet::from([2, 562, 1280]));
+        assert_eq!(taxonomy.descendants(1).len(), 5);
+        assert_eq!(taxonomy.descendants(9606), HashSet::from([9606]));
+        assert_eq!(taxonomy.names[&9606], "Homo sapiens");
+    }
+
+    #[test]
+    fn test_extract_reads() {
+        let dir = "/tmp/rustfastq_kraken";
+        std::fs::create_dir_all(dir).unwrap();
+        let f = |name: &str| format!("{dir}/{name}");
+        // gzip, as `kraken2 ... | gzip`
+        let mut gz = flate2::write::GzEncoder::new(std::fs::File::create(f("kraken.txt.gz")).unwrap(), flate2::Compression::default());
+        write!(gz, "C\tr1\t562\t100\t\nU\tr2\t0\t100\t\nC\tr3/1\tHomo sapiens (taxid 9606)\t100\t\n").unwrap();
+        gz.finish().unwrap();
+        write_fastq_gz(&f("r1.fastq.gz"), &[("r1/1", "ACGT", "IIII"), ("r2/1", "ACGT", "IIII"), ("r3/1", "ACGT", "IIII")]);
+        write_fastq_gz(&f("r2.fastq.gz"), &[("r1/2", "ACGT", "IIII"), ("r2/2", "ACGT", "IIII"), ("r3/2", "ACGT", "IIII")]);
+        let headers = |fname: &str| -> Vec<String> { FastIterator::new(fname).map(|fq| fq.header).collect() };
+
+        let summary = extract_reads(&[f("r1.fastq.gz")], &f("kraken.txt.gz"), &HashSet::from([562, 1280]), NameFilterMode::Include, &f("out.fastq.gz"));
+        assert_eq!((summary.total, summary.selected, summary.kept), (3, 1, 1));
+        assert_eq!(headers(&f("out.fastq.gz")), vec!["@r1/1"]);
+
+        let summary = extract_reads_paired(&[f("r1.fastq.gz")], &[f("r2.fastq.gz")], &f("kraken.txt.gz"), &HashSet::from([9606]),
+            NameFilterMode::Exclude, &f("out_r1.fastq.gz"), &f("out_r2.fastq.gz"));
+        assert_eq!((summary.selected, summary.kept), (1, 2));
+        assert_eq!(headers(&f("out_r2.fastq.gz")), vec!["@r1/2", "@r2/2"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not in the order of the fastq")]
+    fn test_extract_reads_out_of_order() {
+        let dir = "/tmp/rustfastq_kraken_order";
+        std::fs::create_dir_all(dir).unwrap();
+        let f = |name: &str| format!("{dir}/{name}");
+        std::fs::write(f("kraken.txt"), "C\tr2\t562\t100\t\nC\tr1\t562\t100\t\n").unwrap();
+        write_fastq_gz(&f("r1.fastq.gz"), &[("r1", "ACGT", "IIII"), ("r2", "ACGT", "IIII")]);
+        extract_reads(&[f("r1.fastq.gz")], &f("kraken.txt"), &HashSet::from([562]), NameFilterMode::Include, &f("out.fastq.gz"));
+    }
+}
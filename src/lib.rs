@@ -1,5 +1,31 @@
 pub mod io;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod accumulator;
+pub mod histogram;
+pub mod composition;
+pub mod overrepresented;
+pub mod duplication;
+pub mod plot;
+pub mod qc;
+pub mod qc_diff;
 pub mod phred_counter;
+pub mod read_stats;
+pub mod stats;
+pub mod qcfilter;
+pub mod trim;
+pub mod adapter;
+pub mod complexity;
+pub mod pipeline;
+pub mod rejected;
+pub mod names;
+pub mod kraken;
+pub mod screen;
+pub mod merge;
+pub mod sampling;
+pub mod table;
+pub mod illumina;
+pub mod tiles;
 pub mod test_files;
 // pub mod demultiplex;
 pub mod utils;
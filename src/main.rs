@@ -5,7 +5,7 @@ use std::fs::File;
 use std::path::Path;
 use std::path::PathBuf;
 use std::time::Instant;
-use clap::{self, Parser, Subcommand, Args};
+use clap::{self, ArgGroup, CommandFactory, Parser, Subcommand, Args, ValueEnum};
 // use rustfastq::demultiplex;
 // use rustfastq::demultiplex::demux_dual_index;
 // use rustfastq::demultiplex::samplesheet_to_hashmap;
@@ -13,16 +13,45 @@ use clap::{self, Parser, Subcommand, Args};
 // use rustfastq::demultiplex::DualIndex;
 // use rustfastq::demultiplex::Samplename;
 // use rustfastq::demultiplex::Samplesheet;
-use rustfastq::utils::get_spinner;
-use rustfastq::{phred_counter, io::quality_filter};
+use rustfastq::utils::{get_spinner, write_csv};
+use rustfastq::io::{paired_fastq_list_iter, FastqEntry};
+use rustfastq::phred_counter;
+use rustfastq::qcfilter::{self, PairRule, PairedOutputs, QualityCriterion, QualityFilter};
+use rustfastq::trim::{self, TrimOptions};
+use rustfastq::complexity;
+use rustfastq::adapter::{self, AdapterOptions, OverlapParams};
+use rustfastq::pipeline::PipelineConfig;
+use rustfastq::rejected::RejectedReads;
+use rustfastq::names::{self, read_name_list, NameFilter, NameFilterMode};
+use rustfastq::kraken::{self, Taxonomy};
+use rustfastq::screen::{self, KmerIndex, Screener};
+use rustfastq::merge;
+use rustfastq::illumina::TileId;
+use rustfastq::tiles::{self, TileQualityCounter};
+use rustfastq::stats;
+use rustfastq::table::{write_table, ArrowFormat};
+use rustfastq::accumulator::{accumulate_files_sampled, merge_all};
+use rustfastq::sampling::{estimate_read_count, sample_files, Sampling};
+use rustfastq::overrepresented::{self, AdapterContent, OverrepresentedCounter};
+use rustfastq::duplication::{self, DuplicationCounter};
+use rustfastq::qc::{self, QcCounter, QcThresholds};
+use rustfastq::qc_diff::{self, QcProfile};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Cli {  
-    /// Path to output file
+    /// Path to output file. For phred and count-sampleix, a .parquet or .arrow extension
+    /// writes Parquet/Arrow IPC instead of csv (requires the `parquet` feature)
     #[clap(short ='o', long = "output")] 
     output: String,    
 
+    /// Only look at a sample of the reads, for a quick approximate QC (phred, count, stats, overrepresented,
+    /// duplication, qc, qc-diff, tiles, count-sampleix): first:N, fraction:F[:SEED] or
+    /// blocks:N[:READS_PER_BLOCK] (reads from N BGZF blocks spread across each file;
+    /// not for paired files, i.e. count-sampleix and duplication with --r2)
+    #[clap(long = "sample", global = true, default_value_t = Sampling::All)]
+    sample: Sampling,
+
     #[clap(subcommand)]
     command: MyCommand
 }
@@ -32,19 +61,361 @@ struct Cli {
 enum MyCommand {
     phred(PhredArgs),
     count(CountArgs),
+    stats(StatsArgs),
+    overrepresented(OverrepresentedArgs),
+    duplication(DuplicationArgs),
+    qc(QcArgs),
+    qc_diff(QcDiffArgs),
     qcfilter(QCFilterArgs),
+    trim(TrimArgs),
+    pipeline(PipelineArgs),
+    filter_names(FilterNamesArgs),
+    kraken_extract(KrakenExtractArgs),
+    screen(ScreenArgs),
+    merge_pairs(MergePairsArgs),
     count_sampleix(SampleIxArgs),
     demux_dual(DemuxDualArgs),
+    tiles(TilesArgs),
+    filter_tiles(FilterTilesArgs),
 }
 
 #[derive(Args)]
+#[clap(group(ArgGroup::new("criteria").required(true).multiple(true)
+    .args(["qcscore", "maxee", "min_mean_q", "min_base_q", "max_frac_below", "max_dust", "min_entropy", "poly_x"])))]
 struct QCFilterArgs{
-    /// List of fastq files
+    /// List of fastq files (R1 if paired)
     #[clap()]
-    fastq_file: String,
-    /// QC score threhold: any read with less will be dropped
+    fastq_list: Vec<String>,
+    /// Keep reads whose error probability, averaged over the bases, is below this.
+    /// Note: this is not a Phred score (kept for compatibility, prefer --maxee)
     #[clap(short = 'q', long= "qcscore")] 
-    qcscore: f32, 
+    qcscore: Option<f64>, 
+
+    /// Maximum number of expected errors (sum of the error probabilities), as usearch's -fastq_maxee
+    #[clap(long= "maxee")]
+    maxee: Option<f64>,
+
+    /// Minimum mean Phred score of the read
+    #[clap(long= "min-mean-q")]
+    min_mean_q: Option<f64>,
+
+    /// Minimum Phred score of every base
+    #[clap(long= "min-base-q")]
+    min_base_q: Option<u8>,
+
+    /// Maximum fraction of bases below a quality, as QUALITY:FRACTION (e.g. 20:0.1)
+    #[clap(long= "max-frac-below", value_parser = qcfilter::parse_fraction_below)]
+    max_frac_below: Option<QualityCriterion>,
+
+    /// Maximum DUST score (0-100), to drop low-complexity reads (7 is a common choice)
+    #[clap(long= "max-dust")]
+    max_dust: Option<f64>,
+
+    /// Minimum trinucleotide entropy (0-100), to drop low-complexity reads (70 is a common choice)
+    #[clap(long= "min-entropy")]
+    min_entropy: Option<f64>,
+
+    /// Drop reads with a 3' poly-X tail, as BASE[:MIN_LENGTH] (e.g. G or A:15; can be repeated)
+    #[clap(long= "poly-x", value_parser = qcfilter::parse_poly_x)]
+    poly_x: Vec<QualityCriterion>,
+
+    /// Also write the number of reads failing each criterion as csv
+    #[clap(long= "summary")]
+    summary: Option<String>,
+
+    /// R2 files: filter pairs together, writing R1 to --output
+    #[clap(long= "r2", requires = "out_r2")]
+    r2_list: Vec<String>,
+    /// Index files, kept/dropped with their pair (not filtered themselves)
+    #[clap(long= "i1", requires_all = ["out_i1", "r2_list"])]
+    i1_list: Vec<String>,
+    #[clap(long= "i2", requires_all = ["out_i2", "r2_list"])]
+    i2_list: Vec<String>,
+
+    #[clap(long= "out-r2", requires = "r2_list")]
+    out_r2: Option<String>,
+    #[clap(long= "out-i1", requires = "i1_list")]
+    out_i1: Option<String>,
+    #[clap(long= "out-i2", requires = "i2_list")]
+    out_i2: Option<String>,
+
+    /// When to keep a pair: both reads pass, or either passes
+    #[clap(long= "pair-rule", default_value_t = PairRule::Both)]
+    pair_rule: PairRule,
+
+    /// R1 reads whose R2 failed (with --pair-rule both); dropped if not given
+    #[clap(long= "singletons-r1")]
+    singletons_r1: Option<String>,
+    /// R2 reads whose R1 failed
+    #[clap(long= "singletons-r2")]
+    singletons_r2: Option<String>,
+
+    /// Write the dropped reads here, tagged with the reason (reject=... in the header comment)
+    #[clap(long= "failed-out")]
+    failed_out: Option<String>,
+    /// Dropped R2 reads
+    #[clap(long= "failed-out-r2")]
+    failed_out_r2: Option<String>,
+
+    /// Also write the number of dropped reads per reason as csv
+    #[clap(long= "failed-summary")]
+    failed_summary: Option<String>,
+}
+
+#[derive(Args)]
+struct TrimArgs{
+    /// List of fastq files
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Remove this many bases from the 5' end, before any other trimming
+    #[clap(long= "head-crop")]
+    head_crop: Option<usize>,
+
+    /// Remove this many bases from the 5' end of R2, instead of --head-crop
+    #[clap(long= "head-crop-r2")]
+    head_crop_r2: Option<usize>,
+
+    /// Cut reads to this length, before quality trimming
+    #[clap(long= "crop")]
+    crop: Option<usize>,
+
+    /// Replace bases below this quality by N (after trimming), instead of trimming them
+    #[clap(long= "mask-below")]
+    mask_below: Option<u8>,
+
+    /// Remove bases below this quality from the 5' end
+    #[clap(long= "leading")]
+    leading: Option<u8>,
+
+    /// Remove bases below this quality from the 3' end
+    #[clap(long= "trailing")]
+    trailing: Option<u8>,
+
+    /// BWA-style 3' quality trimming with this cutoff (as cutadapt -q)
+    #[clap(long= "quality-cutoff")]
+    quality_cutoff: Option<u8>,
+
+    /// Trimmomatic-style sliding window, as WINDOW:QUALITY (e.g. 4:20)
+    #[clap(long= "sliding-window", value_parser = |s: &str| trim::parse_sliding_window(s).map(|_| s.to_string()))]
+    sliding_window: Option<String>,
+
+    /// Drop reads shorter than this after trimming
+    #[clap(long= "min-length", default_value_t = 1)]
+    min_length: usize,
+
+    /// Trim 3' poly-X tails (after the adapters), as BASE[:MIN_LENGTH], e.g. G for the poly-G
+    /// of two-colour chemistry or A:15 (can be repeated)
+    #[clap(long= "poly-x", value_parser = |s: &str| complexity::parse_poly_x_tail(s).map(|_| s.to_string()))]
+    poly_x: Vec<String>,
+
+    /// Drop trimmed reads with a higher DUST score (0-100)
+    #[clap(long= "max-dust")]
+    max_dust: Option<f64>,
+
+    /// Drop trimmed reads with a lower trinucleotide entropy (0-100)
+    #[clap(long= "min-entropy")]
+    min_entropy: Option<f64>,
+
+    /// 3' adapter, a sequence or one of truseq, truseq_r2, nextera, smallrna (can be repeated)
+    #[clap(short = 'a', long= "adapter")]
+    adapters: Vec<String>,
+
+    /// 5' adapter (can be repeated)
+    #[clap(short = 'g', long= "front")]
+    front: Vec<String>,
+
+    /// FASTA file of 3' adapters
+    #[clap(long= "adapter-fasta")]
+    adapter_fasta: Option<String>,
+
+    /// 3' adapter for R2 only, instead of --adapter (can be repeated)
+    #[clap(short = 'A', long= "adapter-r2")]
+    adapters_r2: Vec<String>,
+
+    /// Detect the 3' adapter from k-mers frequent in the first reads
+    #[clap(long= "detect-adapter")]
+    detect_adapter: bool,
+
+    /// Maximum errors per aligned adapter base
+    #[clap(long= "error-rate", default_value_t = adapter::DEFAULT_MAX_ERROR_RATE)]
+    error_rate: f64,
+
+    /// Minimum number of adapter bases to trim
+    #[clap(long= "min-overlap", default_value_t = adapter::DEFAULT_MIN_OVERLAP)]
+    min_overlap: usize,
+
+    /// R2 files: trim pairs together, writing R1 to --output
+    #[clap(long= "r2", requires = "out_r2")]
+    r2_list: Vec<String>,
+
+    #[clap(long= "out-r2", requires = "r2_list")]
+    out_r2: Option<String>,
+
+    /// For pairs, cut both reads to the insert size where R1 and R2 overlap
+    #[clap(long= "overlap")]
+    overlap: bool,
+
+    /// Write the dropped reads here, tagged with the reason (reject=... in the header comment)
+    #[clap(long= "failed-out")]
+    failed_out: Option<String>,
+    /// Dropped R2 reads
+    #[clap(long= "failed-out-r2")]
+    failed_out_r2: Option<String>,
+
+    /// Also write the number of dropped reads per reason as csv
+    #[clap(long= "failed-summary")]
+    failed_summary: Option<String>,
+}
+
+#[derive(Args)]
+struct PipelineArgs{
+    /// List of fastq files (R1 if paired)
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Steps to run, as .toml or .yaml (see the `pipeline` module docs)
+    #[clap(long= "config")]
+    config: String,
+
+    /// R2 files: process pairs, writing R1 to --output
+    #[clap(long= "r2", requires = "out_r2")]
+    r2_list: Vec<String>,
+
+    #[clap(long= "out-r2", requires = "r2_list")]
+    out_r2: Option<String>,
+}
+
+#[derive(Args)]
+struct FilterNamesArgs{
+    /// List of fastq files (R1 if paired)
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Read IDs, one per line (.gz if bgzf compressed); `@`, descriptions and /1 /2 are ignored
+    #[clap(long= "names")]
+    names: String,
+
+    /// Keep the listed reads (include) or all others (exclude)
+    #[clap(long= "mode", default_value_t = NameFilterMode::Include)]
+    mode: NameFilterMode,
+
+    /// R2 files: filter pairs by the R1 name, writing R1 to --output
+    #[clap(long= "r2", requires = "out_r2")]
+    r2_list: Vec<String>,
+
+    #[clap(long= "out-r2", requires = "r2_list")]
+    out_r2: Option<String>,
+
+    /// Write the listed IDs that aren't in the reads here
+    #[clap(long= "missing")]
+    missing: Option<String>,
+}
+
+#[derive(Args)]
+struct KrakenExtractArgs{
+    /// List of fastq files (R1 if paired)
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Kraken2 per-read output (--output), plain or gzip; in the order of the fastq, as kraken2 writes it
+    #[clap(long= "kraken")]
+    kraken: String,
+
+    /// Kraken2 report (--report), for --include-children
+    #[clap(long= "report")]
+    report: Option<String>,
+
+    /// Taxids to select (can be repeated); 0 selects the unclassified reads
+    #[clap(short = 't', long= "taxid", value_parser = kraken::parse_taxid, required = true)]
+    taxids: Vec<u64>,
+
+    /// Also select all taxa below the given ones, from the report
+    #[clap(long= "include-children", requires = "report")]
+    include_children: bool,
+
+    /// Keep all reads except the selected ones, e.g. to remove host reads
+    #[clap(long= "exclude")]
+    exclude: bool,
+
+    /// R2 files: extract pairs, writing R1 to --output
+    #[clap(long= "r2", requires = "out_r2")]
+    r2_list: Vec<String>,
+
+    #[clap(long= "out-r2", requires = "r2_list")]
+    out_r2: Option<String>,
+}
+
+#[derive(Args)]
+struct ScreenArgs{
+    /// List of fastq files (R1 if paired); clean reads are written to --output
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// FASTA file (plain or gzip) of a contaminant (e.g. phix.fa.gz), named after the file (can be repeated).
+    /// At most 50Mb of references in total: fine for bacteria or PhiX, but not for a host genome
+    #[clap(short = 'r', long= "reference", required = true)]
+    references: Vec<String>,
+
+    /// k-mer length (up to 32)
+    #[clap(short = 'k', long= "kmer", default_value_t = screen::DEFAULT_K,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=32))]
+    k: usize,
+
+    /// Minimum fraction of a read's (pair's) k-mers in a reference to call it contaminated
+    #[clap(long= "min-fraction", default_value_t = screen::DEFAULT_MIN_FRACTION)]
+    min_fraction: f64,
+
+    /// Write the contaminated reads here, tagged with the reference (reject=... in the header comment)
+    #[clap(long= "contaminated")]
+    contaminated: Option<String>,
+    /// Contaminated R2 reads
+    #[clap(long= "contaminated-r2")]
+    contaminated_r2: Option<String>,
+
+    /// Also write the reads per reference as csv
+    #[clap(long= "summary")]
+    summary: Option<String>,
+
+    /// R2 files: screen pairs (by the k-mers of both reads), writing R1 to --output
+    #[clap(long= "r2", requires = "out_r2")]
+    r2_list: Vec<String>,
+
+    #[clap(long= "out-r2", requires = "r2_list")]
+    out_r2: Option<String>,
+}
+
+#[derive(Args)]
+struct MergePairsArgs{
+    /// List of R1 fastq files; merged reads are written to --output
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// R2 files
+    #[clap(long= "r2", required = true)]
+    r2_list: Vec<String>,
+
+    /// Minimum overlap of R1 and R2
+    #[clap(long= "min-overlap", default_value_t = OverlapParams::default().min_overlap)]
+    min_overlap: usize,
+
+    /// Maximum number of mismatches in the overlap
+    #[clap(long= "max-mismatches", default_value_t = OverlapParams::default().max_mismatches)]
+    max_mismatches: usize,
+
+    /// Maximum fraction of mismatches in the overlap
+    #[clap(long= "max-mismatch-rate", default_value_t = OverlapParams::default().max_mismatch_rate)]
+    max_mismatch_rate: f64,
+
+    /// Write the pairs that couldn't be merged here
+    #[clap(long= "unmerged-r1")]
+    unmerged_r1: Option<String>,
+    #[clap(long= "unmerged-r2")]
+    unmerged_r2: Option<String>,
+
+    /// Write the insert sizes of the merged pairs as csv histogram
+    #[clap(long= "histogram")]
+    histogram: Option<String>,
 }
 
 #[derive(Args)]
@@ -80,11 +451,162 @@ struct CountArgs{
     fastq_list: Vec<String>,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum TableFormat {
+    Tsv,
+    Json,
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Arrow IPC file
+    #[cfg(feature = "parquet")]
+    Ipc,
+}
+
+#[derive(Args)]
+struct StatsArgs{
+    /// List of fastq files
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Output format
+    #[clap(long= "format", value_enum, default_value_t = TableFormat::Tsv)]
+    format: TableFormat,
+
+    /// Only count the reads (fast: counts lines instead of parsing records)
+    #[clap(long= "reads-only")]
+    reads_only: bool,
+}
+
+#[derive(Args)]
+struct OverrepresentedArgs{
+    /// List of fastq files
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Report sequences making up more than this fraction of the reads
+    #[clap(long= "min-fraction", default_value_t = overrepresented::DEFAULT_MIN_FRACTION)]
+    min_fraction: f64,
+
+    /// Also write the cumulative adapter content per position as csv
+    #[clap(long= "adapter-content")]
+    adapter_content: Option<String>,
+}
+
+#[derive(Args)]
+struct DuplicationArgs{
+    /// List of fastq files (R1)
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// R2 files: if given, read pairs are considered duplicates only if both reads agree
+    #[clap(long= "r2")]
+    r2_list: Vec<String>,
+
+    /// Maximum number of distinct sequences kept in memory (beyond that, they get subsampled)
+    #[clap(long= "max-entries", default_value_t = duplication::DEFAULT_MAX_ENTRIES)]
+    max_entries: usize,
+
+    /// Also write the complexity curve (distinct sequences vs. sequencing depth) as csv
+    #[clap(long= "curve")]
+    curve: Option<String>,
+
+    /// Extrapolate the complexity curve up to this multiple of the current depth
+    #[clap(long= "curve-max", default_value_t = 10.0)]
+    curve_max: f64,
+}
+
+#[derive(Args)]
+struct QcArgs{
+    /// List of fastq files (treated as one sample)
+    #[clap(required = true)]
+    fastq_list: Vec<String>,
+
+    /// Sample name in the report (default: derived from the first file)
+    #[clap(long= "name")]
+    name: Option<String>,
+
+    /// Also write the results as MultiQC custom content (name it *_mqc.json so MultiQC finds it)
+    #[clap(long= "json")]
+    json: Option<String>,
+
+    /// JSON file with pass/warn/fail thresholds per module, e.g. {"duplication": {"warn": 70, "fail": 50}}
+    #[clap(long= "thresholds")]
+    thresholds: Option<String>,
+
+    /// Also save the per-cycle profile as JSON, to compare runs with qc-diff
+    #[clap(long= "profile")]
+    profile: Option<String>,
+}
+
+#[derive(Args)]
+struct QcDiffArgs{
+    /// Baseline: a profile from `qc --profile`, a `phred --summary-json` output or a fastq file
+    #[clap()]
+    baseline: String,
+
+    /// The run to compare against the baseline (same kinds of input)
+    #[clap()]
+    other: String,
+
+    /// Flag cycles whose mean quality differs by more than this
+    #[clap(long= "max-quality-delta", default_value_t = qc_diff::DEFAULT_MAX_QUALITY_DELTA)]
+    max_quality_delta: f64,
+
+    /// Flag cycles where the percentage of any base differs by more than this
+    #[clap(long= "max-composition-shift", default_value_t = qc_diff::DEFAULT_MAX_COMPOSITION_SHIFT)]
+    max_composition_shift: f64,
+}
+
 #[derive(Args)]
 struct PhredArgs{
     /// List of fastq files
     #[clap()]
     fastq_list: Vec<String>,
+
+    /// Also write per-position quality summaries (mean, quantiles, >=Q20/Q30) as csv
+    #[clap(long= "summary-csv")]
+    summary_csv: Option<String>,
+
+    /// Also write per-position quality summaries as JSON
+    #[clap(long= "summary-json")]
+    summary_json: Option<String>,
+
+    /// Also write histograms of mean read quality, expected errors and read length,
+    /// to <PREFIX>.mean_quality.csv, <PREFIX>.expected_errors.csv, <PREFIX>.length.csv
+    #[clap(long= "read-stats")]
+    read_stats: Option<String>,
+
+    /// Also write per-position A/C/G/T/N fractions and the per-read GC% histogram,
+    /// to <PREFIX>.per_position.csv, <PREFIX>.gc.csv
+    #[clap(long= "composition")]
+    composition: Option<String>,
+}
+
+#[derive(Args)]
+struct TilesArgs{
+    /// List of fastq files
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Report tiles whose mean quality drops more than this below the cycle mean
+    #[clap(long= "max-deviation", default_value_t = rustfastq::tiles::DEFAULT_TILE_DEVIATION)]
+    max_deviation: f64,
+}
+
+#[derive(Args)]
+struct FilterTilesArgs{
+    /// List of fastq files
+    #[clap()]
+    fastq_list: Vec<String>,
+
+    /// Tiles to drop, as lane:tile (e.g. 1:1101)
+    #[clap(long= "tiles", value_delimiter = ',')]
+    tiles: Vec<TileId>,
+
+    /// Also drop tiles whose mean quality drops more than this below the cycle mean
+    /// (requires an extra pass over the files)
+    #[clap(long= "max-deviation")]
+    max_deviation: Option<f64>,
 }
 
 #[derive(Args)]
@@ -102,23 +624,56 @@ struct FastqArgs{
     fastq_list: Vec<String>,
 }
 
+/// Exits with a usage error, for invalid input that clap can't check while parsing
+fn exit_invalid(message: impl std::fmt::Display) -> ! {
+    Cli::command().error(clap::error::ErrorKind::InvalidValue, message).exit()
+}
+
+/// Exits if the output is Parquet/Arrow IPC (by its extension) without the `parquet` feature
+fn check_table_output(output: &str) {
+    if !cfg!(feature = "parquet") && ArrowFormat::from_path(output).is_some() {
+        exit_invalid(format!("can't write {output}: rustfastq was built without the `parquet` feature"));
+    }
+}
+
+/// Exits if block sampling is asked for paired files, see [`sample_paired_files`]
+fn check_paired_sampling(sampling: &Sampling) {
+    if matches!(sampling, Sampling::Blocks { .. }) {
+        exit_invalid("block sampling isn't supported for paired files");
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
 
     match cli.command{
         MyCommand::phred(args) => {
+            check_table_output(&cli.output);
             println!("Doing Phred Counter");
-            phred_counter::run(&args.fastq_list, cli.output)
+            let outputs = phred_counter::PhredOutputs { 
+                summary_csv: args.summary_csv, 
+                summary_json: args.summary_json,
+                read_stats_prefix: args.read_stats,
+                composition_prefix: args.composition,
+            };
+            print_sampling_note(&cli.sample);
+            phred_counter::run(&args.fastq_list, cli.output, &outputs, &cli.sample)
         }
         MyCommand::count(args) => {
             println!("Doing counting");
             let mut file_handle = File::create(cli.output).unwrap();
 
+            print_sampling_note(&cli.sample);
+
             for filename in args.fastq_list{
                 println!("Counting {}", filename.clone());
 
                 let now = Instant::now();
-                let c = count_fastq_reads(filename.clone());
+                let c = if cli.sample.is_all() {
+                    count_fastq_reads(filename.clone())
+                } else {
+                    estimate_read_count(&filename, &cli.sample).unwrap().round() as usize
+                };
                 let elapsed_time = now.elapsed();
                 println!("Counted {}, took {} minutes.", filename.clone(), elapsed_time.as_secs()/60);
 
@@ -127,18 +682,307 @@ fn main() {
             }
         }
 
+        MyCommand::stats(args) => {
+            print_sampling_note(&cli.sample);
+            if args.reads_only {
+                let counts = stats::count_reads_files(&args.fastq_list, &cli.sample);
+                match args.format {
+                    TableFormat::Tsv => stats::write_tsv(&counts, &cli.output).unwrap(),
+                    TableFormat::Json => stats::write_json(&counts, &cli.output).unwrap(),
+                    #[cfg(feature = "parquet")]
+                    TableFormat::Parquet => write_table(&counts[..], &cli.output, ArrowFormat::Parquet),
+                    #[cfg(feature = "parquet")]
+                    TableFormat::Ipc => write_table(&counts[..], &cli.output, ArrowFormat::Ipc),
+                }
+            } else {
+                let file_stats = stats::stats_files(&args.fastq_list, &cli.sample);
+                match args.format {
+                    TableFormat::Tsv => stats::write_tsv(&file_stats, &cli.output).unwrap(),
+                    TableFormat::Json => stats::write_json(&file_stats, &cli.output).unwrap(),
+                    #[cfg(feature = "parquet")]
+                    TableFormat::Parquet => write_table(&file_stats[..], &cli.output, ArrowFormat::Parquet),
+                    #[cfg(feature = "parquet")]
+                    TableFormat::Ipc => write_table(&file_stats[..], &cli.output, ArrowFormat::Ipc),
+                }
+            }
+        },
+
+        MyCommand::overrepresented(args) => {
+            print_sampling_note(&cli.sample);
+            let (counter, adapters): (OverrepresentedCounter, AdapterContent) = 
+                merge_all(accumulate_files_sampled(&args.fastq_list, &cli.sample));
+            let over = counter.overrepresented(args.min_fraction);
+            println!("{} overrepresented sequences", over.len());
+            write_csv(&over, &cli.output).unwrap();
+
+            if let Some(fname) = args.adapter_content {
+                write_csv(&adapters.rows(), &fname).unwrap();
+            }
+        },
+
+        MyCommand::duplication(args) => {
+            if !args.r2_list.is_empty() {
+                check_paired_sampling(&cli.sample);
+            }
+            print_sampling_note(&cli.sample);
+            let mut counter = DuplicationCounter::new(args.max_entries);
+            if args.r2_list.is_empty() {
+                for fq in sample_files(&args.fastq_list, &cli.sample) {
+                    counter.add_sequence(&fq.seq);
+                }
+            } else {
+                for (fq1, fq2) in sample_pairs(&args.fastq_list, &args.r2_list, &cli.sample) {
+                    counter.add_pair(&fq1, &fq2);
+                }
+            }
+            println!("{:.2}% unique reads (estimated)", counter.percent_unique());
+            write_csv(&counter.duplication_levels(), &cli.output).unwrap();
+
+            if let Some(fname) = args.curve {
+                // 10 points per multiple of the current depth
+                let n_points = (args.curve_max * 10.0).ceil() as usize;
+                write_csv(&counter.complexity_curve(args.curve_max, n_points), &fname).unwrap();
+            }
+        },
+
+        MyCommand::qc(args) => {
+            let thresholds = match args.thresholds {
+                Some(fname) => QcThresholds::from_json(&fname).unwrap(),
+                None => QcThresholds::default(),
+            };
+            let name = args.name.unwrap_or_else(|| qc::sample_name(&args.fastq_list[0]));
+
+            print_sampling_note(&cli.sample);
+            let counter: QcCounter = merge_all(accumulate_files_sampled(&args.fastq_list, &cli.sample));
+            let report = counter.report(&name, &args.fastq_list, &thresholds, &cli.sample);
+            for m in &report.modules {
+                println!("{}\t{}\t{}", m.status, m.module, m.message);
+            }
+            qc::write_html(&report, &counter, &cli.output).unwrap();
+            if let Some(fname) = args.json {
+                qc::write_multiqc_json(&report, &fname).unwrap();
+            }
+            if let Some(fname) = args.profile {
+                QcProfile::from_qc(&counter).write_json(&fname).unwrap();
+            }
+        },
+
+        MyCommand::qc_diff(args) => {
+            let thresholds = qc_diff::DiffThresholds {
+                max_quality_delta: args.max_quality_delta,
+                max_composition_shift: args.max_composition_shift,
+            };
+            print_sampling_note(&cli.sample);
+            let baseline = QcProfile::load(&args.baseline, &cli.sample);
+            let other = QcProfile::load(&args.other, &cli.sample);
+            let diff = qc_diff::diff_profiles(&baseline, &other, &thresholds);
+            write_csv(&diff.cycles, &cli.output).unwrap();
+
+            println!("mean read length: {:.1} vs {:.1}, KS distance {:.3}", diff.mean_length_a, diff.mean_length_b, diff.length_distance);
+            let flagged: Vec<String> = diff.flagged_cycles().map(|c| (c.position + 1).to_string()).collect();
+            println!("{} deviating cycles: {}", flagged.len(), flagged.join(","));
+        },
+
+        MyCommand::tiles(args) => {
+            println!("Doing per-tile quality");
+            print_sampling_note(&cli.sample);
+            let counter: TileQualityCounter = merge_all(accumulate_files_sampled(&args.fastq_list, &cli.sample));
+            if counter.n_unparsed() > 0 {
+                println!("{} reads without Illumina header were skipped", counter.n_unparsed());
+            }
+            write_csv(&counter.table(), &cli.output).unwrap();
+
+            for tile in counter.flagged_tiles(args.max_deviation) {
+                println!("flagged tile {}", tile);
+            }
+        },
+
+        MyCommand::filter_tiles(args) => {
+            let mut exclude: HashSet<TileId> = args.tiles.into_iter().collect();
+            if let Some(max_deviation) = args.max_deviation {
+                // only the detection of bad tiles is sampled, the filtering goes through all reads
+                let counter: TileQualityCounter = merge_all(accumulate_files_sampled(&args.fastq_list, &cli.sample));
+                for tile in counter.flagged_tiles(max_deviation) {
+                    println!("flagged tile {}", tile);
+                    exclude.insert(tile);
+                }
+            }
+            tiles::filter_tiles(&args.fastq_list, &cli.output, &exclude);
+        },
+
         MyCommand::qcfilter(args) => {
-            quality_filter(&args.fastq_file, &cli.output, args.qcscore)
+            let criteria: Vec<QualityCriterion> = [
+                args.qcscore.map(QualityCriterion::MaxMeanErrorProbability),
+                args.maxee.map(QualityCriterion::MaxExpectedErrors),
+                args.min_mean_q.map(QualityCriterion::MinMeanQuality),
+                args.min_base_q.map(QualityCriterion::MinBaseQuality),
+                args.max_frac_below,
+                args.max_dust.map(QualityCriterion::MaxDust),
+                args.min_entropy.map(QualityCriterion::MinEntropy),
+            ].into_iter().flatten().chain(args.poly_x).collect();
+
+            let filter = QualityFilter::new(criteria);
+
+            let mut rejected = [RejectedReads::new(args.failed_out.as_deref()), RejectedReads::new(args.failed_out_r2.as_deref())];
+            let rows = if args.r2_list.is_empty() {
+                let summary = qcfilter::filter_fastq(&args.fastq_list, &cli.output, &filter, &mut rejected[0]);
+                print!("{summary}");
+                summary.rows()
+            } else {
+                let outputs = PairedOutputs {
+                    r1: cli.output.clone(),
+                    r2: args.out_r2.expect("clap requires --out-r2 with --r2"),
+                    i1: args.out_i1,
+                    i2: args.out_i2,
+                    singletons_r1: args.singletons_r1,
+                    singletons_r2: args.singletons_r2,
+                };
+                let summary = qcfilter::filter_fastq_paired(
+                    &args.fastq_list, &args.r2_list, &args.i1_list, &args.i2_list, &outputs, &filter, args.pair_rule, &mut rejected);
+                print!("{summary}");
+                summary.rows()
+            };
+            if let Some(fname) = args.summary {
+                write_csv(&rows, &fname).unwrap();
+            }
+            print_rejected(&rejected, !args.r2_list.is_empty(), args.failed_summary.as_deref());
+        },
+
+        MyCommand::trim(args) => {
+            let options = TrimOptions {
+                head_crop: args.head_crop,
+                head_crop_r2: args.head_crop_r2,
+                crop: args.crop,
+                leading: args.leading,
+                trailing: args.trailing,
+                quality_cutoff: args.quality_cutoff,
+                sliding_window: args.sliding_window,
+                poly_x: args.poly_x,
+                min_length: args.min_length,
+                max_dust: args.max_dust,
+                min_entropy: args.min_entropy,
+                mask_below: args.mask_below,
+                adapters: AdapterOptions {
+                    adapters: args.adapters,
+                    front: args.front,
+                    adapter_fasta: args.adapter_fasta,
+                    adapters_r2: args.adapters_r2,
+                    detect_adapter: args.detect_adapter,
+                    error_rate: args.error_rate,
+                    min_overlap: args.min_overlap,
+                },
+                overlap: args.overlap,
+                ..TrimOptions::default()
+            };
+            let (trimmer_r1, trimmer_r2) = options.trimmers(&args.fastq_list, &args.r2_list).unwrap_or_else(|e| exit_invalid(e));
+
+            let mut rejected = [RejectedReads::new(args.failed_out.as_deref()), RejectedReads::new(args.failed_out_r2.as_deref())];
+            if args.r2_list.is_empty() {
+                let summary = trim::trim_fastq(&args.fastq_list, &cli.output, &trimmer_r1, &mut rejected[0]);
+                print!("{summary}");
+            } else {
+                let out_r2 = args.out_r2.as_ref().expect("clap requires --out-r2 with --r2");
+                let (summary_r1, summary_r2) = trim::trim_fastq_paired(
+                    &args.fastq_list, &args.r2_list, &cli.output, out_r2,
+                    (&trimmer_r1, &trimmer_r2), options.overlap_params().as_ref(), &mut rejected);
+                print!("R1: {summary_r1}R2: {summary_r2}");
+            }
+            print_rejected(&rejected, !args.r2_list.is_empty(), args.failed_summary.as_deref());
+        },
+
+        MyCommand::pipeline(args) => {
+            let config = PipelineConfig::from_file(&args.config).unwrap_or_else(|e| exit_invalid(e));
+            let pipeline = config.build(&args.fastq_list, &args.r2_list).unwrap_or_else(|e| exit_invalid(e));
+            let outputs: Vec<String> = std::iter::once(cli.output.clone()).chain(args.out_r2).collect();
+            let summary = pipeline.run(&args.fastq_list, &args.r2_list, &outputs).unwrap_or_else(|e| exit_invalid(e));
+            for step in summary {
+                println!("{}: {} in, {} out", step.name, step.fragments_in, step.fragments_out);
+                print!("{}", step.details);
+            }
+        },
+
+        MyCommand::filter_names(args) => {
+            let mut filter = NameFilter::new(read_name_list(&args.names), args.mode);
+            let summary = if args.r2_list.is_empty() {
+                names::filter_names(&args.fastq_list, &cli.output, &mut filter)
+            } else {
+                let out_r2 = args.out_r2.expect("clap requires --out-r2 with --r2");
+                names::filter_names_paired(&args.fastq_list, &args.r2_list, &cli.output, &out_r2, &mut filter)
+            };
+            print!("{summary}");
+            if let Some(fname) = args.missing {
+                summary.write_missing(&fname);
+            }
+        },
+
+        MyCommand::kraken_extract(args) => {
+            let mut taxids: HashSet<u64> = args.taxids.iter().copied().collect();
+            if args.include_children {
+                let report = args.report.expect("clap requires --report with --include-children");
+                let taxonomy = Taxonomy::from_report(&report).unwrap_or_else(|e| exit_invalid(e));
+                taxids = taxids.iter().flat_map(|&t| taxonomy.descendants(t)).collect();
+            }
+            println!("selecting {} taxa", taxids.len());
+
+            let mode = if args.exclude { NameFilterMode::Exclude } else { NameFilterMode::Include };
+            let summary = if args.r2_list.is_empty() {
+                kraken::extract_reads(&args.fastq_list, &args.kraken, &taxids, mode, &cli.output)
+            } else {
+                let out_r2 = args.out_r2.expect("clap requires --out-r2 with --r2");
+                kraken::extract_reads_paired(&args.fastq_list, &args.r2_list, &args.kraken, &taxids, mode, &cli.output, &out_r2)
+            };
+            print!("{summary}");
+        },
+
+        MyCommand::screen(args) => {
+            let index = KmerIndex::from_fasta(&args.references, args.k).unwrap_or_else(|e| exit_invalid(e));
+            println!("{} distinct {}-mers in {} references", index.len(), index.k, index.references.len());
+            let screener = Screener::new(index, args.min_fraction);
+
+            let mut contaminated = [RejectedReads::new(args.contaminated.as_deref()), RejectedReads::new(args.contaminated_r2.as_deref())];
+            let summary = if args.r2_list.is_empty() {
+                screen::screen_fastq(&args.fastq_list, &cli.output, &screener, &mut contaminated[0])
+            } else {
+                let out_r2 = args.out_r2.expect("clap requires --out-r2 with --r2");
+                screen::screen_fastq_paired(&args.fastq_list, &args.r2_list, &cli.output, &out_r2, &screener, &mut contaminated)
+            };
+            print!("{summary}");
+            if let Some(fname) = args.summary {
+                write_csv(&summary.rows(), &fname).unwrap();
+            }
+        },
+
+        MyCommand::merge_pairs(args) => {
+            let params = OverlapParams {
+                min_overlap: args.min_overlap,
+                max_mismatches: args.max_mismatches,
+                max_mismatch_rate: args.max_mismatch_rate,
+            };
+            let summary = merge::merge_fastq_pairs(
+                &args.fastq_list, &args.r2_list, &cli.output,
+                args.unmerged_r1.as_deref(), args.unmerged_r2.as_deref(), &params);
+            print!("{summary}");
+            if let Some(fname) = args.histogram {
+                summary.insert_sizes.write_csv(&fname).unwrap();
+            }
         },
 
         /*
         cargo run --release -- -o /tmp/six.txt  count-sampleix  --i1 /home/michi/tuba_mount/sequencing_data/IR-BL-001/fastq/01.RawData/Undetermined/Undetermined_Undetermined_22C7YHLT4_S0_L004_I1_001.fastq.gz  --i2 /home/michi/tuba_mount/sequencing_data/IR-BL-001/fastq/01.RawData/Undetermined/Undetermined_Undetermined_22C7YHLT4_S0_L004_I2_001.fastq.gz 
          */
         MyCommand::count_sampleix(args) => {
-            let count_map = paired_index_counter(args.i1_list, args.i2_list);
+            check_table_output(&cli.output);
+            check_paired_sampling(&cli.sample);
+            print_sampling_note(&cli.sample);
+            let count_map = paired_index_counter(args.i1_list, args.i2_list, &cli.sample);
             let mut count_vec: Vec<((String, String), usize)> = count_map.into_iter().collect();
             count_vec.sort_by(|a,b| b.1.cmp(&a.1));
 
+            if let Some(format) = ArrowFormat::from_path(&cli.output) {
+                write_table(&count_vec[..], &cli.output, format);
+                return;
+            }
+
             // write it to the file
             let mut fh = BufWriter::new(File::create(cli.output).unwrap());
 
@@ -176,20 +1020,59 @@ fn main() {
     };
 }
 
+/// Tells the user that the results are estimates, when sampling
+fn print_sampling_note(sampling: &Sampling) {
+    if !sampling.is_all() {
+        println!("Note: only looking at a sample of the reads ({sampling}), results are estimates");
+    }
+}
+
+/// Prints the table of dropped reads per reason (R1, and R2 if `paired`), optionally writing it as csv
+fn print_rejected(rejected: &[RejectedReads; 2], paired: bool, csv: Option<&str>) {
+    let rows = if paired {
+        print!("R1 rejected:\n{}R2 rejected:\n{}", rejected[0], rejected[1]);
+        RejectedReads::paired_rows(&rejected[0], &rejected[1])
+    } else {
+        print!("rejected:\n{}", rejected[0]);
+        rejected[0].rows()
+    };
+    if let Some(fname) = csv {
+        write_csv(&rows, fname).unwrap();
+    }
+}
+
+/// Sampled reads of R1/R2 (or I1/I2) files: both get the same reads, as long as the files are in sync.
+/// Block sampling can't guarantee that
+fn sample_paired_files<'a>(fastq_list: &'a [String], sampling: &'a Sampling) -> impl Iterator<Item = rustfastq::io::FastqEntry> + 'a {
+    assert!(!matches!(sampling, Sampling::Blocks { .. }), "block sampling isn't supported for paired files");
+    sample_files(fastq_list, sampling)
+}
+
+/// Sampled pairs of R1/R2 files, see [`sample_paired_files`]; panics if the files have a different number of reads
+fn sample_pairs<'a>(r1_list: &'a [String], r2_list: &'a [String], sampling: &'a Sampling) -> Box<dyn Iterator<Item = (FastqEntry, FastqEntry)> + 'a> {
+    if sampling.is_all() {
+        return Box::new(paired_fastq_list_iter(r1_list, r2_list));
+    }
+    Box::new(sample_paired_files(r1_list, sampling).zip_longest(sample_paired_files(r2_list, sampling)).map(|pair| match pair {
+        EitherOrBoth::Both(fq1, fq2) => (fq1, fq2),
+        _ => panic!("R1 and R2 have a different number of reads"),
+    }))
+}
+
 pub fn count_fastq_reads(filename: String) -> usize{
     // count the nubmer of entries (not lines!) in the fastq
     let count = rustfastq::io::fastq_list_iter(&[filename]).count();
     count
 }
 
-use std::collections::HashMap;
-use itertools::izip;
+use std::collections::{HashMap, HashSet};
+use itertools::{izip, EitherOrBoth, Itertools};
 
 /// iterate through the index1/index2 reads and count the frequency of sample-barcode-pairs
-pub fn paired_index_counter(i1_list: Vec<String>, i2_list: Vec<String>) -> HashMap<(String, String), usize> {
+pub fn paired_index_counter(i1_list: Vec<String>, i2_list: Vec<String>, sampling: &Sampling) -> HashMap<(String, String), usize> {
    
-    let i1 = rustfastq::io::fastq_list_iter(&i1_list);
-    let i2 = rustfastq::io::fastq_list_iter(&i2_list);
+    let i1 = sample_paired_files(&i1_list, sampling);
+    let i2 = sample_paired_files(&i2_list, sampling);
 
     let mut counter: HashMap<(String, String), usize> = HashMap::new();
 
@@ -211,6 +1094,7 @@ fn test_paired() {
     let count_map = paired_index_counter(
         vec!["/home/michi/mounts/myDrive/230601_VH00715_118_AACVG5JM5_fastq/Undetermined_S0_L001_I1_001.fastq.gz".to_string()], 
         vec!["/home/michi/mounts/myDrive/230601_VH00715_118_AACVG5JM5_fastq/Undetermined_S0_L001_I2_001.fastq.gz".to_string()], 
+        &Sampling::All,
     );
 
         // Get a sorted (by field 0 ("count") in reversed order) list of the
@@ -0,0 +1,203 @@
+//! Merging overlapping R1/R2 into a single read (as FLASH/PEAR), for amplicons and short inserts.
+//!
+//! The overlap is found as for adapter trimming ([`crate::adapter::insert_size`]); in the overlap
+//! the base with the lower error probability is called, and its quality recomputed as the posterior
+//! of both calls (Edgar & Flyvbjerg 2015, as usearch's `-fastq_mergepairs`)
+use std::fmt;
+use std::io::Write;
+
+use crate::adapter::{insert_size, OverlapParams};
+use crate::histogram::Histogram;
+use crate::io::{get_bgzf_writer, paired_fastq_list_iter, reverse_complement, FastqEntry, PHRED_LOOKUP};
+
+/// Merged qualities are capped at this (as usearch)
+pub const MAX_MERGED_QUALITY: u8 = 41;
+
+/// Consensus of two calls of the same base, with their error probabilities;
+/// returns the base and its posterior error probability. An `N` leaves the other call
+pub fn consensus_base(b1: u8, p1: f64, b2: u8, p2: f64) -> (u8, f64) {
+    if b2 == b'N' {
+        return (b1, p1);
+    }
+    if b1 == b'N' {
+        return (b2, p2);
+    }
+    if b1 == b2 {
+        (b1, (p1 * p2 / 3.0) / (1.0 - p1 - p2 + 4.0 * p1 * p2 / 3.0))
+    } else {
+        // the better call wins
+        let (base, p_best, p_other) = if p1 <= p2 { (b1, p1, p2) } else { (b2, p2, p1) };
+        (base, p_best * (1.0 - p_other / 3.0) / (p_best + p_other - 4.0 * p_best * p_other / 3.0))
+    }
+}
+
+/// Phred+33 symbol of an error probability, capped at [`MAX_MERGED_QUALITY`]
+fn phred_symbol(p: f64) -> u8 {
+    let q = (-10.0 * p.log10()).round().clamp(0.0, MAX_MERGED_QUALITY as f64);
+    q as u8 + 33
+}
+
+fn error_probability(symbol: u8) -> f64 {
+    PHRED_LOOKUP.get_prob(symbol as char) as f64
+}
+
+/// Merges R1 and R2 into a read of the insert size: R1, then the consensus where the reverse
+/// complement of R2 overlaps it, then the rest of R2. The header is R1's
+pub fn merge_pair(fq1: &FastqEntry, fq2: &FastqEntry, insert: usize) -> FastqEntry {
+    let (r1, q1) = (fq1.seq.as_bytes(), fq1.phred.as_bytes());
+    let r2 = reverse_complement(&fq2.seq);
+    let r2 = r2.as_bytes();
+    let q2: Vec<u8> = fq2.phred.bytes().rev().collect();
+    // R2 (reverse complemented) ends with the insert
+    let r2_start = insert as isize - r2.len() as isize;
+
+    let mut seq = String::with_capacity(insert);
+    let mut phred = String::with_capacity(insert);
+    for pos in 0..insert {
+        let from_r1 = (pos < r1.len()).then(|| (r1[pos], q1[pos]));
+        let j = pos as isize - r2_start;
+        let from_r2 = (0..r2.len() as isize).contains(&j).then(|| (r2[j as usize], q2[j as usize]));
+        let (base, symbol) = match (from_r1, from_r2) {
+            (Some((b1, s1)), Some((b2, s2))) => {
+                let (base, p) = consensus_base(b1, error_probability(s1), b2, error_probability(s2));
+                (base, phred_symbol(p))
+            }
+            (Some(call), None) | (None, Some(call)) => call,
+            (None, None) => panic!("insert size {insert} is longer than both reads together"),
+        };
+        seq.push(base as char);
+        phred.push(symbol as char);
+    }
+    FastqEntry { header: fq1.header.clone(), seq, phred }
+}
+
+/// Merges the pair if R1 and R2 overlap enough
+pub fn try_merge(fq1: &FastqEntry, fq2: &FastqEntry, params: &OverlapParams) -> Option<FastqEntry> {
+    let insert = insert_size(&fq1.seq, &fq2.seq, params)?;
+    Some(merge_pair(fq1, fq2, insert))
+}
+
+/// Pairs merged, and the sizes of their inserts
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeSummary {
+    pub pairs: u64,
+    pub merged: u64,
+    pub insert_sizes: Histogram,
+}
+
+impl Default for MergeSummary {
+    fn default() -> Self {
+        MergeSummary { pairs: 0, merged: 0, insert_sizes: Histogram::new(1.0) }
+    }
+}
+
+impl fmt::Display for MergeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}/{}({}) pairs merged", self.merged, self.pairs, self.merged as f32 / self.pairs as f32)?;
+        writeln!(f, "insert size: mean {:.1}, median {}", self.insert_sizes.mean(), self.insert_sizes.quantile(0.5))
+    }
+}
+
+/// Writes the merged pairs to `outname`, the others (unchanged) to `unmerged_r1`/`unmerged_r2` if given
+pub fn merge_fastq_pairs(
+    r1_list: &[String],
+    r2_list: &[String],
+    outname: &str,
+    unmerged_r1: Option<&str>,
+    unmerged_r2: Option<&str>,
+    params: &OverlapParams,
+) -> MergeSummary {
+    let mut writer = get_bgzf_writer(outname);
+    let mut unmerged_r1 = unmerged_r1.map(get_bgzf_writer);
+    let mut unmerged_r2 = unmerged_r2.map(get_bgzf_writer);
+    let mut summary = MergeSummary::default();
+    for (fq1, fq2) in paired_fastq_list_iter(r1_list, r2_list) {
+        summary.pairs += 1;
+        match try_merge(&fq1, &fq2, params) {
+            Some(merged) => {
+                summary.merged += 1;
+                summary.insert_sizes.add(merged.len() as f64);
+                write!(writer, "{}", merged.to_string()).unwrap();
+            }
+            None => {
+                for (writer, fq) in [(&mut unmerged_r1, &fq1), (&mut unmerged_r2, &fq2)] {
+                    if let Some(writer) = writer {
+                        write!(writer, "{}", fq.to_string()).unwrap();
+                    }
+                }
+            }
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{consensus_base, merge_fastq_pairs, merge_pair, try_merge};
+    use crate::adapter::OverlapParams;
+    use crate::io::{reverse_complement, FastIterator, FastqEntry};
+    use crate::test_files::write_fastq_gz;
+
+    const INSERT: &str = "TGCATGCAAGGCTTACCGATTACGGATCAGGCATTCAGGACGTACCATAGGCTTACCAGT";
+
+    fn entry(seq: &str, phred: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: phred.to_string() }
+    }
+
+    #[test]
+    fn test_consensus_base() {
+        // two Q20 calls agreeing: ~Q44.7
+        let (base, p) = consensus_base(b'A', 0.01, b'A', 0.01);
+        assert_eq!(base, b'A');
+        assert!((p - 3.401e-5).abs() < 1e-8);
+        // disagreeing: the Q30 call wins, with a lower quality
+        let (base, p) = consensus_base(b'A', 0.01, b'C', 0.001);
+        assert_eq!(base, b'C');
+        assert!(p > 0.001 && p < 0.1);
+        assert_eq!(consensus_base(b'N', 0.75, b'G', 0.01), (b'G', 0.01));
+    }
+
+    #[test]
+    fn test_merge_pair() {
+        // 60bp insert, 40bp reads: 20bp overlap
+        let r1 = entry(&INSERT[..40], &"5".repeat(40));
+        let r2 = entry(&reverse_complement(&INSERT[20..]), &"5".repeat(40));
+        let merged = merge_pair(&r1, &r2, 60);
+        assert_eq!(merged.seq, INSERT);
+        // Q20 outside the overlap, capped Q41 inside
+        assert_eq!(merged.phred, format!("{}{}{}", "5".repeat(20), "J".repeat(20), "5".repeat(20)));
+
+        // a mismatch in the overlap: the better call wins
+        let mut seq = INSERT[..40].to_string();
+        seq.replace_range(30..31, "A");
+        let r1 = entry(&seq, &format!("{}+{}", "5".repeat(30), "5".repeat(9)));
+        let merged = merge_pair(&r1, &r2, 60);
+        assert_eq!(merged.seq, INSERT);
+        // Q20 against Q10: Q11
+        assert_eq!(merged.phred.as_bytes()[30], b',');
+
+        // insert shorter than the reads: the adapter is dropped
+        let r1 = entry(&format!("{}AGATCGGAAG", &INSERT[..30]), &"I".repeat(40));
+        let r2 = entry(&format!("{}AGATCGGAAG", reverse_complement(&INSERT[..30])), &"I".repeat(40));
+        let params = OverlapParams { min_overlap: 10, ..OverlapParams::default() };
+        assert_eq!(try_merge(&r1, &r2, &params).unwrap().seq, &INSERT[..30]);
+    }
+
+    #[test]
+    fn test_merge_fastq_pairs() {
+        let dir = "/tmp/rustfastq_merge";
+        std::fs::create_dir_all(dir).unwrap();
+        let f = |name: &str| format!("{dir}/{name}.fastq.gz");
+        let q = "I".repeat(40);
+        let other = "ACCGTTAGCCATGGATCCTTAAGCGCTAGCTAGGCTATCG";
+        write_fastq_gz(&f("r1"), &[("a", &INSERT[..40], &q), ("b", other, &q)]);
+        write_fastq_gz(&f("r2"), &[("a", &reverse_complement(&INSERT[20..]), &q), ("b", other, &q)]);
+        let params = OverlapParams { min_overlap: 15, ..OverlapParams::default() };
+        let summary = merge_fastq_pairs(&[f("r1")], &[f("r2")], &f("merged"), Some(&f("un_r1")), None, &params);
+        assert_eq!((summary.pairs, summary.merged), (2, 1));
+        assert_eq!(summary.insert_sizes.quantile(0.5), 60.0);
+        let seqs = |fname: &str| -> Vec<String> { FastIterator::new(fname).map(|fq| fq.seq).collect() };
+        assert_eq!(seqs(&f("merged")), vec![INSERT]);
+        assert_eq!(seqs(&f("un_r1")), vec![other]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Filtering reads by name, against a list of read IDs (e.g. from kraken or a mapper).
+//!
+//! Names are compared normalized ([`normalize_read_name`]): without the leading `@`,
+//! the description and a `/1` or `/2` mate suffix, so IDs from other tools match our headers
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::str::FromStr;
+
+use crate::io::{fastq_list_iter, get_bgzf_writer, paired_fastq_list_iter, parse_whitelist_gz, FastqEntry};
+
+/// The read ID of a header: `@read1/1 1:N:0:ACGT` -> `read1`
+pub fn normalize_read_name(header: &str) -> &str {
+    let name = header.strip_prefix('@').unwrap_or(header);
+    let name = name.split_whitespace().next().unwrap_or("");
+    name.strip_suffix("/1").or_else(|| name.strip_suffix("/2")).unwrap_or(name)
+}
+
+/// Reads a list of read IDs, one per line (plain, or bgzf if it ends in `.gz`), normalized
+pub fn read_name_list(fname: &str) -> HashSet<String> {
+    let lines: Vec<String> = if fname.ends_with(".gz") {
+        parse_whitelist_gz(&fname.to_string()).into_iter().collect()
+    } else {
+        BufReader::new(File::open(fname).unwrap()).lines().map(|l| l.unwrap()).collect()
+    };
+    lines.iter()
+        .map(|line| normalize_read_name(line))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Whether the listed reads are kept or dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameFilterMode {
+    #[default]
+    Include,
+    Exclude,
+}
+
+impl fmt::Display for NameFilterMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameFilterMode::Include => write!(f, "include"),
+            NameFilterMode::Exclude => write!(f, "exclude"),
+        }
+    }
+}
+
+impl FromStr for NameFilterMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(NameFilterMode::Include),
+            "exclude" => Ok(NameFilterMode::Exclude),
+            _ => Err(format!("expected include or exclude, got {s}")),
+        }
+    }
+}
+
+/// Keeps (or drops) the reads in the list, remembering which listed names were seen
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameFilter {
+    pub names: HashSet<String>,
+    pub mode: NameFilterMode,
+    found: HashSet<String>,
+}
+
+impl NameFilter {
+    pub fn new(names: HashSet<String>, mode: NameFilterMode) -> Self {
+        NameFilter { names, mode, found: HashSet::new() }
+    }
+
+    /// Whether the read is kept
+    pub fn keep(&mut self, fq: &FastqEntry) -> bool {
+        let name = normalize_read_name(&fq.header);
+        let listed = self.names.contains(name);
+        if listed && !self.found.contains(name) {
+            self.found.insert(name.to_string());
+        }
+        match self.mode {
+            NameFilterMode::Include => listed,
+            NameFilterMode::Exclude => !listed,
+        }
+    }
+
+    /// Listed names that weren't seen in the reads (so far), sorted
+    pub fn missing(&self) -> Vec<String> {
+        let mut missing: Vec<String> = self.names.difference(&self.found).cloned().collect();
+        missing.sort();
+        missing
+    }
+}
+
+/// Reads (or pairs) kept, and the listed IDs not found
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameFilterSummary {
+    pub mode: NameFilterMode,
+    pub total: u64,
+    pub kept: u64,
+    pub listed: usize,
+    pub missing: Vec<String>,
+}
+
+impl NameFilterSummary {
+    fn new(filter: &NameFilter, total: u64, kept: u64) -> Self {
+        NameFilterSummary { mode: filter.mode, total, kept, listed: filter.names.len(), missing: filter.missing() }
+    }
+
+    /// Writes the missing IDs, one per line
+    pub fn write_missing(&self, fname: &str) {
+        let mut writer = File::create(fname).unwrap();
+        for name in &self.missing {
+            writeln!(writer, "{name}").unwrap();
+        }
+    }
+}
+
+impl fmt::Display for NameFilterSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}/{}({}) reads kept ({} {} listed IDs)", self.kept, self.total, self.kept as f32 / self.total as f32, self.mode, self.listed)?;
+        writeln!(f, "{}/{} listed IDs not found in the reads", self.missing.len(), self.listed)
+    }
+}
+
+/// Writes the reads kept by the filter to `outname`
+pub fn filter_names(fastq_list: &[String], outname: &str, filter: &mut NameFilter) -> NameFilterSummary {
+    let mut writer = get_bgzf_writer(outname);
+    let (mut total, mut kept) = (0, 0);
+    for fq in fastq_list_iter(fastq_list) {
+        total += 1;
+        if filter.keep(&fq) {
+            kept += 1;
+            write!(writer, "{}", fq.to_string()).unwrap();
+        }
+    }
+    NameFilterSummary::new(filter, total, kept)
+}
+
+/// Filters R1/R2 together, by the name of R1. Panics if the mates' names differ
+pub fn filter_names_paired(r1_list: &[String], r2_list: &[String], outname_r1: &str, outname_r2: &str, filter: &mut NameFilter) -> NameFilterSummary {
+    let mut writer_r1 = get_bgzf_writer(outname_r1);
+    let mut writer_r2 = get_bgzf_writer(outname_r2);
+    let (mut total, mut kept) = (0, 0);
+    for (fq1, fq2) in paired_fastq_list_iter(r1_list, r2_list) {
+        assert_eq!(normalize_read_name(&fq1.header), normalize_read_name(&fq2.header), "R1 and R2 are out of sync");
+        total += 1;
+        if filter.keep(&fq1) {
+            kept += 1;
+            write!(writer_r1, "{}", fq1.to_string()).unwrap();
+            write!(writer_r2, "{}", fq2.to_string()).unwrap();
+        }
+    }
+    NameFilterSummary::new(filter, total, kept)
+}
+
+// zcat kraken_out.filtered.gz | awk '{ print $2}' | less
+/// Writes the reads listed in `whitelist` to `outname`; names are normalized
+pub fn read_filter_whitelist(fastqname: &str, outname: &str, whitelist: &str) {
+    let mut filter = NameFilter::new(read_name_list(whitelist), NameFilterMode::Include);
+    let summary = filter_names(&[fastqname.to_string()], outname, &mut filter);
+    println!(
+        "{}/{}({}) reads were whitelisted",
+        summary.kept,
+        summary.total,
+        (summary.kept as f32) / (summary.total as f32)
+    )
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{filter_names, filter_names_paired, normalize_read_name, read_name_list, NameFilter, NameFilterMode};
+    use crate::io::FastIterator;
+    use crate::test_files::write_fastq_gz;
+
+    #[test]
+    fn test_normalize_read_name() {
+        assert_eq!(normalize_read_name("@read1 1:N:0:ACGT"), "read1");
+        assert_eq!(normalize_read_name("@read1/2"), "read1");
+        assert_eq!(normalize_read_name("read1/1\tkraken"), "read1");
+        assert_eq!(normalize_read_name("@read/12"), "read/12");
+        assert_eq!(normalize_read_name(""), "");
+    }
+
+    #[test]
+    fn test_filter_names() {
+        let dir = "/tmp/rustfastq_filter_names";
+        std::fs::create_dir_all(dir).unwrap();
+        let f = |name: &str| format!("{dir}/{name}");
+        std::fs::write(f("names.txt"), "@a/1\nc 1:N:0\nx\n\n").unwrap();
+        write_fastq_gz(&f("r1.fastq.gz"), &[("a/1", "ACGT", "IIII"), ("b/1", "ACGT", "IIII"), ("c/1", "ACGT", "IIII")]);
+        write_fastq_gz(&f("r2.fastq.gz"), &[("a/2", "ACGT", "IIII"), ("b/2", "ACGT", "IIII"), ("c/2", "ACGT", "IIII")]);
+        let names = read_name_list(&f("names.txt"));
+        assert_eq!(names.len(), 3);
+        let headers = |fname: &str| -> Vec<String> { FastIterator::new(fname).map(|fq| fq.header).collect() };
+
+        let mut filter = NameFilter::new(names.clone(), NameFilterMode::Include);
+        let summary = filter_names(&[f("r1.fastq.gz")], &f("out.fastq.gz"), &mut filter);
+        assert_eq!((summary.total, summary.kept), (3, 2));
+        assert_eq!(summary.missing, vec!["x"]);
+        assert_eq!(headers(&f("out.fastq.gz")), vec!["@a/1", "@c/1"]);
+
+        let mut filter = NameFilter::new(names, NameFilterMode::Exclude);
+        let summary = filter_names_paired(&[f("r1.fastq.gz")], &[f("r2.fastq.gz")], &f("out_r1.fastq.gz"), &f("out_r2.fastq.gz"), &mut filter);
+        assert_eq!(summary.kept, 1);
+        assert_eq!(headers(&f("out_r2.fastq.gz")), vec!["@b/2"]);
+    }
+}
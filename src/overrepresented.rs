@@ -0,0 +1,296 @@
+//! Overrepresented sequences and adapter content (similar to the FastQC modules)
+//!
+//! Overrepresented sequences: the first [`TRACKED_READS`] reads are counted exactly, after that
+//! only sequences already seen are counted. Since anything overrepresented will show up early,
+//! this keeps memory bounded while the counts of frequent sequences stay accurate.
+//! Sequences are matched against a list of known contaminants/adapters ([`CONTAMINANTS`]).
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::accumulator::FastqAccumulator;
+use crate::io::{reverse_complement, FastqEntry};
+
+/// number of reads whose sequences are all tracked
+pub const TRACKED_READS: u64 = 100_000;
+/// reads longer than this are truncated to [`TRUNCATE_TO`] before counting
+/// (otherwise sequencing errors would spread the counts over many sequences)
+const TRUNCATE_LONGER_THAN: usize = 75;
+const TRUNCATE_TO: usize = 50;
+/// Sequences making up more than this fraction of all reads are reported
+pub const DEFAULT_MIN_FRACTION: f64 = 0.001;
+/// minimum length of an exact match between a sequence and a contaminant
+const MIN_CONTAMINANT_MATCH: usize = 20;
+
+/// Known contaminants: adapters, primers and homopolymers
+pub const CONTAMINANTS: &[(&str, &str)] = &[
+    ("Illumina Universal Adapter", "AGATCGGAAGAGC"),
+    ("TruSeq Adapter, Read 1", "AGATCGGAAGAGCACACGTCTGAACTCCAGTCAC"),
+    ("TruSeq Adapter, Read 2", "AGATCGGAAGAGCGTCGTGTAGGGAAAGAGTGT"),
+    ("TruSeq Universal Adapter", "AATGATACGGCGACCACCGAGATCTACACTCTTTCCCTACACGACGCTCTTCCGATCT"),
+    ("Nextera Transposase Sequence", "CTGTCTCTTATACACATCT"),
+    ("Nextera Read 1 Adapter", "TCGTCGGCAGCGTCAGATGTGTATAAGAGACAG"),
+    ("Nextera Read 2 Adapter", "GTCTCGTGGGCTCGGAGATGTGTATAAGAGACAG"),
+    ("Illumina Small RNA 3' Adapter", "TGGAATTCTCGGGTGCCAAGG"),
+    ("Illumina Small RNA 5' Adapter", "GTTCAGAGTTCTACAGTCCGACGATC"),
+    ("polyA", "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"),
+    ("polyG", "GGGGGGGGGGGGGGGGGGGGGGGGGGGGGG"),
+];
+
+/// The 12bp adapter prefixes searched for by [`AdapterContent`]
+pub const ADAPTER_KMERS: &[(&str, &str)] = &[
+    ("Illumina Universal Adapter", "AGATCGGAAGAG"),
+    ("Illumina Small RNA 3' Adapter", "TGGAATTCTCGG"),
+    ("Illumina Small RNA 5' Adapter", "GATCGTCGGACT"),
+    ("Nextera Transposase Sequence", "CTGTCTCTTATA"),
+    ("polyA", "AAAAAAAAAAAA"),
+    ("polyG", "GGGGGGGGGGGG"),
+];
+
+/// Counts of the most frequent sequences
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OverrepresentedCounter {
+    counts: HashMap<String, u64>,
+    n_reads: u64,
+}
+
+/// A sequence making up a large fraction of the reads
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverrepresentedSequence {
+    pub sequence: String,
+    pub count: u64,
+    pub percent: f64,
+    /// the matching contaminant, if any
+    pub possible_source: Option<String>,
+}
+
+impl OverrepresentedCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn n_reads(&self) -> u64 {
+        self.n_reads
+    }
+
+    /// All sequences above `min_fraction` of the reads, most frequent first
+    pub fn overrepresented(&self, min_fraction: f64) -> Vec<OverrepresentedSequence> {
+        let mut result: Vec<OverrepresentedSequence> = self.counts.iter()
+            .filter(|(_, &count)| count as f64 > min_fraction * self.n_reads as f64)
+            .map(|(seq, &count)| OverrepresentedSequence {
+                sequence: seq.clone(),
+                count,
+                percent: 100.0 * count as f64 / self.n_reads as f64,
+                possible_source: find_contaminant(seq).map(|s| s.to_string()),
+            })
+            .collect();
+        result.sort_by(|a, b| b.count.cmp(&a.count).then(a.sequence.cmp(&b.sequence)));
+        result
+    }
+}
+
+impl FastqAccumulator for OverrepresentedCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        let seq = if fq.seq.len() > TRUNCATE_LONGER_THAN { &fq.seq[..TRUNCATE_TO] } else { &fq.seq[..] };
+        if self.n_reads < TRACKED_READS {
+            *self.counts.entry(seq.to_string()).or_insert(0) += 1;
+        } else if let Some(count) = self.counts.get_mut(seq) {
+            *count += 1;
+        }
+        self.n_reads += 1;
+    }
+
+    fn merge(&mut self, other: Self) {
+        for (seq, count) in other.counts {
+            *self.counts.entry(seq).or_insert(0) += count;
+        }
+        self.n_reads += other.n_reads;
+    }
+}
+
+/// Name of the contaminant matching the sequence (in either orientation), if any:
+/// either one contains the other, or they share an exact match of at least 20bp
+pub fn find_contaminant(seq: &str) -> Option<&'static str> {
+    for (name, contaminant) in CONTAMINANTS {
+        let rc = reverse_complement(contaminant);
+        for c in [*contaminant, &rc[..]] {
+            if seq.contains(c) || c.contains(seq) {
+                return Some(name);
+            }
+            if seq.len() >= MIN_CONTAMINANT_MATCH
+                && seq.as_bytes().windows(MIN_CONTAMINANT_MATCH).any(|w| c.contains(std::str::from_utf8(w).unwrap())) {
+                return Some(name);
+            }
+        }
+    }
+    None
+}
+
+/// For each position and adapter: the fraction of reads containing the adapter at or
+/// before that position, i.e. cumulative over the read.
+/// Only the first occurrence of an adapter in a read is counted
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterContent {
+    adapters: Vec<(String, String)>,
+    /// per adapter, number of reads where the adapter starts at a position
+    starts: Vec<Vec<u64>>,
+    n_reads: u64,
+    max_len: usize,
+}
+
+impl Default for AdapterContent {
+    fn default() -> Self {
+        Self::new(ADAPTER_KMERS.iter().map(|(n, s)| (n.to_string(), s.to_string())).collect())
+    }
+}
+
+/// Cumulative adapter content at one position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdapterContentRow {
+    pub position: usize,
+    pub adapter: String,
+    pub percent: f64,
+}
+
+impl AdapterContent {
+    /// Searches for the given (name, sequence) adapters
+    pub fn new(adapters: Vec<(String, String)>) -> Self {
+        let starts = vec![Vec::new(); adapters.len()];
+        AdapterContent { adapters, starts, n_reads: 0, max_len: 0 }
+    }
+
+    pub fn adapter_names(&self) -> impl Iterator<Item = &str> {
+        self.adapters.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Percentage of reads with the adapter at or before each position, per adapter
+    pub fn cumulative_percent(&self) -> Vec<Vec<f64>> {
+        self.starts.iter()
+            .map(|starts| {
+                let mut cumulative = 0;
+                (0..self.max_len)
+                    .map(|pos| {
+                        cumulative += starts.get(pos).copied().unwrap_or(0);
+                        100.0 * cumulative as f64 / self.n_reads as f64
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Long format table: position, adapter, percent
+    pub fn rows(&self) -> Vec<AdapterContentRow> {
+        let percent = self.cumulative_percent();
+        let mut rows = Vec::new();
+        for position in 0..self.max_len {
+            for ((name, _), p) in self.adapters.iter().zip(&percent) {
+                rows.push(AdapterContentRow { position, adapter: name.clone(), percent: p[position] });
+            }
+        }
+        rows
+    }
+}
+
+impl FastqAccumulator for AdapterContent {
+    fn add(&mut self, fq: &FastqEntry) {
+        self.n_reads += 1;
+        self.max_len = self.max_len.max(fq.seq.len());
+        for ((_, adapter), starts) in self.adapters.iter().zip(self.starts.iter_mut()) {
+            if let Some(pos) = fq.seq.find(adapter.as_str()) {
+                if pos >= starts.len() {
+                    starts.resize(pos + 1, 0);
+                }
+                starts[pos] += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        assert_eq!(self.adapters, other.adapters, "can't merge different adapter sets");
+        for (starts, other_starts) in self.starts.iter_mut().zip(other.starts) {
+            if other_starts.len() > starts.len() {
+                starts.resize(other_starts.len(), 0);
+            }
+            for (s, o) in starts.iter_mut().zip(other_starts) {
+                *s += o;
+            }
+        }
+        self.n_reads += other.n_reads;
+        self.max_len = self.max_len.max(other.max_len);
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{find_contaminant, AdapterContent, OverrepresentedCounter, TRACKED_READS};
+    use crate::accumulator::FastqAccumulator;
+    use crate::io::FastqEntry;
+
+    fn entry(seq: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: "I".repeat(seq.len()) }
+    }
+
+    #[test]
+    fn test_overrepresented() {
+        let mut c = OverrepresentedCounter::new();
+        for i in 0..1000 {
+            c.add(&entry(&format!("ACGT{i:05}")));
+        }
+        for _ in 0..10 {
+            c.add(&entry("AGATCGGAAGAGCACACGTCTGAACT"));
+        }
+        let over = c.overrepresented(0.005);
+        assert_eq!(over.len(), 1);
+        assert_eq!(over[0].count, 10);
+        assert_eq!(over[0].possible_source.as_deref(), Some("Illumina Universal Adapter"));
+    }
+
+    #[test]
+    fn test_untracked_after_limit() {
+        let mut c = OverrepresentedCounter::new();
+        for _ in 0..TRACKED_READS {
+            c.add(&entry("AAAA"));
+        }
+        c.add(&entry("CCCC"));
+        c.add(&entry("AAAA"));
+        let over = c.overrepresented(0.0);
+        assert_eq!(over.len(), 1);
+        assert_eq!(over[0].count, TRACKED_READS + 1);
+    }
+
+    #[test]
+    fn test_truncation() {
+        let mut c = OverrepresentedCounter::new();
+        c.add(&entry(&format!("{}{}", "A".repeat(50), "C".repeat(30))));
+        c.add(&entry(&format!("{}{}", "A".repeat(50), "G".repeat(30))));
+        assert_eq!(c.overrepresented(0.0)[0].count, 2);
+    }
+
+    #[test]
+    fn test_find_contaminant() {
+        // reverse complement of the Nextera transposase sequence, within a longer read
+        assert_eq!(find_contaminant("TTTTAGATGTGTATAAGAGACAGTTTT"), Some("Nextera Transposase Sequence"));
+        // shares 20bp with TruSeq read 2
+        assert_eq!(find_contaminant("CGTCGTGTAGGGAAAGAGTGTCCCC"), Some("TruSeq Adapter, Read 2"));
+        assert_eq!(find_contaminant("ACGTTGCAACGTTGCAACGT"), None);
+    }
+
+    #[test]
+    fn test_adapter_content() {
+        let mut a = AdapterContent::default();
+        a.add(&entry("CCAGATCGGAAGAGCC"));  // universal adapter at position 2
+        a.add(&entry("CCCCAGATCGGAAGAG"));  // at 4
+        a.add(&entry("CCCCCCCCCCCCCCCC"));
+        a.add(&entry("CCCCCCCCCCCCCCCC"));
+
+        let percent = a.cumulative_percent();
+        assert_eq!(percent[0][1], 0.0);
+        assert_eq!(percent[0][2], 25.0);
+        assert_eq!(percent[0][15], 50.0);
+        assert!(percent[4].iter().all(|&p| p == 0.0));
+
+        let rows = a.rows();
+        assert_eq!(rows.len(), 16 * 6);
+        assert_eq!(rows[0].adapter, "Illumina Universal Adapter");
+    }
+}
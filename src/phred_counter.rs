@@ -1,63 +1,281 @@
-use counter::Counter;
 use itertools::izip;
-use crate::io::fastq_phred_iter;
-use indicatif::{ProgressBar, ProgressStyle, };
+use crate::accumulator::{accumulate_files, accumulate_files_parallel, accumulate_files_sampled, merge_all, FastqAccumulator};
+use crate::io::FastqEntry;
+use crate::composition::BaseComposition;
+use crate::read_stats::ReadStatsCounter;
+use crate::sampling::Sampling;
+use crate::table::{write_table, ArrowFormat};
+use crate::utils::write_csv;
+use std::fs::File;
+use std::io::BufWriter;
+use serde::{Deserialize, Serialize};
 
+/// number of distinct Phred symbols: '!' (Q0) up to '~' (Q93)
+pub const N_PHRED_SYMBOLS: usize = 94;
+const PHRED_OFFSET: u8 = 33;
 
 #[cfg(test)]
 #[test]
 fn main(){
     use crate::test_files::TEST_FASTQ_R1;
-    run(&vec![TEST_FASTQ_R1.to_string()],"/tmp/phred.csv".to_string())
+    run(&vec![TEST_FASTQ_R1.to_string()],"/tmp/phred.csv".to_string(), &PhredOutputs::default(), &Sampling::All)
 }
 
+/// Counts the Phred symbols per read position (position x quality).
+/// 
+/// Stored densely, one row of [`N_PHRED_SYMBOLS`] counters per position, which
+/// avoids hashing a (char, position) tuple for every base. Counters of different files/lanes
+/// can be combined via [`PhredCounter::merge`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PhredCounter {
+    counts: Vec<[u64; N_PHRED_SYMBOLS]>,
+    n_reads: u64,
+}
+
+impl PhredCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the quality string of a single read
+    pub fn add(&mut self, fq: &FastqEntry) {
+        self.add_phred(&fq.phred)
+    }
 
-pub fn run(fastq_files: &[String], output_csv_file:String){
+    /// Adds a single quality string (Phred+33 encoded)
+    pub fn add_phred(&mut self, phred: &str) {
+        let phred = phred.as_bytes();
+        if phred.len() > self.counts.len() {
+            self.counts.resize(phred.len(), [0; N_PHRED_SYMBOLS]);
+        }
+        for (row, &symbol) in self.counts.iter_mut().zip(phred) {
+            let q = symbol.wrapping_sub(PHRED_OFFSET) as usize;
+            assert!(q < N_PHRED_SYMBOLS, "{} unknown", symbol as char);
+            row[q] += 1;
+        }
+        self.n_reads += 1;
+    }
+
+    /// Adds the counts of `other` into this counter
+    pub fn merge(&mut self, other: PhredCounter) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), [0; N_PHRED_SYMBOLS]);
+        }
+        for (row, other_row) in self.counts.iter_mut().zip(other.counts) {
+            for (c, o) in row.iter_mut().zip(other_row) {
+                *c += o;
+            }
+        }
+        self.n_reads += other.n_reads;
+    }
 
-    let mut phred_counter: Counter<(char, usize), u64> = Counter::new();  // phred, position -> #counts
+    /// Counts all reads of the given fastq files (one after the other)
+    pub fn from_files(fastq_files: &[String]) -> Self {
+        accumulate_files(fastq_files)
+    }
 
-    let my_iter = fastq_phred_iter(fastq_files);
+    /// number of reads added so far
+    pub fn n_reads(&self) -> u64 {
+        self.n_reads
+    }
 
-    let bar = ProgressBar::new_spinner();
-    bar.set_style(ProgressStyle::default_bar()
-        .template("[{elapsed_precise}] {pos} {per_sec}").unwrap()
-        .progress_chars("##-"));
+    /// number of positions (i.e. the length of the longest read)
+    pub fn n_positions(&self) -> usize {
+        self.counts.len()
+    }
 
-    for phred_string in my_iter{  //.take(1_000_0)
-        for (position, phred_score) in phred_string.chars().enumerate(){
-            let counter = phred_counter.entry((phred_score, position)).or_insert(0);
-            *counter += 1;      
+    /// How often the Phred `symbol` was observed at `position` (0-based)
+    pub fn get(&self, position: usize, symbol: char) -> u64 {
+        let q = (symbol as u32).wrapping_sub(PHRED_OFFSET as u32) as usize;
+        match self.counts.get(position) {
+            Some(row) if q < N_PHRED_SYMBOLS => row[q],
+            _ => 0
         }
-        bar.inc(1);
     }
-    bar.finish();
 
-    // unwrap the whole thing int a dataframe with three cols: phred-char, position, freq
-    let mut phred_scores: Vec<String> = Vec::new();
-    let mut positions: Vec<u64> = Vec::new();
-    let mut freqs: Vec<u64> = Vec::new();
-    for ((phred_char, pos ), freq) in phred_counter.into_iter(){
-        phred_scores.push(phred_char.to_string());
-        positions.push(pos as u64);
-        freqs.push(freq);
+    /// The counts at a single position, indexed by numeric quality score (Q0, Q1, ...)
+    pub fn position_counts(&self, position: usize) -> &[u64; N_PHRED_SYMBOLS] {
+        &self.counts[position]
+    }
+
+    /// Quality summary statistics (mean, quantiles, fraction >=Q20/Q30) for each position,
+    /// i.e. the data behind a FastQC-style per-base quality boxplot
+    pub fn position_summary(&self) -> Vec<PositionQualitySummary> {
+        self.counts.iter().enumerate()
+            .map(|(position, row)| PositionQualitySummary::from_counts(position, row))
+            .collect()
+    }
+
+    /// Iterates over all observed (phred symbol, position, frequency), skipping zero counts
+    pub fn iter(&self) -> impl Iterator<Item = (char, usize, u64)> + '_ {
+        self.counts.iter().enumerate().flat_map(|(pos, row)| {
+            row.iter().enumerate()
+                .filter(|(_, &freq)| freq > 0)
+                .map(move |(q, &freq)| (((q as u8) + PHRED_OFFSET) as char, pos, freq))
+        })
+    }
+
+    /// Writes the counts as a csv with three columns: phred-char, frequency, position
+    pub fn write_csv(&self, output_csv_file: String) -> Result<(), csv::Error> {
+        // unwrap the whole thing int a dataframe with three cols: phred-char, position, freq
+        let mut phred_scores: Vec<String> = Vec::new();
+        let mut positions: Vec<u64> = Vec::new();
+        let mut freqs: Vec<u64> = Vec::new();
+        for (phred_char, pos, freq) in self.iter(){
+            phred_scores.push(phred_char.to_string());
+            positions.push(pos as u64);
+            freqs.push(freq);
+        }
+
+        // let df_cb = Series::new("PHRED", phred_scores);
+        // let series_phred = Series::new("phred", phred_scores);
+        // let series_freq = Series::new("frequency", freqs);
+        // let series_pos = Series::new("position", positions);
+
+
+        // let mut df = df!("phred" => phred_scores, "frequency" => freqs, "position" => positions).unwrap();
+        // write_to_csv_polars(&mut df, output_csv_file);    
+
+        write_to_csv_simple(phred_scores,positions, freqs, output_csv_file)
     }
+}
+
+/// Summary of the (numeric) Phred scores observed at a single position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionQualitySummary {
+    /// 0-based position in the read
+    pub position: usize,
+    /// number of bases observed at this position
+    pub count: u64,
+    pub mean: f64,
+    pub median: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub p75: f64,
+    pub p90: f64,
+    /// fraction of bases with quality >= Q20
+    pub frac_q20: f64,
+    /// fraction of bases with quality >= Q30
+    pub frac_q30: f64,
+}
+
+impl PositionQualitySummary {
+    /// From the counts of each quality score (index = Q) at `position`
+    pub fn from_counts(position: usize, counts: &[u64]) -> Self {
+        let total: u64 = counts.iter().sum();
+        let weighted: u64 = counts.iter().enumerate().map(|(q, &c)| q as u64 * c).sum();
+        let at_least = |min_q: usize| counts.iter().skip(min_q).sum::<u64>();
+        let fraction = |x: u64| if total > 0 { x as f64 / total as f64 } else { f64::NAN };
 
-    // let df_cb = Series::new("PHRED", phred_scores);
-    // let series_phred = Series::new("phred", phred_scores);
-    // let series_freq = Series::new("frequency", freqs);
-    // let series_pos = Series::new("position", positions);
+        PositionQualitySummary {
+            position,
+            count: total,
+            mean: fraction(weighted),
+            median: quantile(counts, 0.5),
+            p10: quantile(counts, 0.1),
+            p25: quantile(counts, 0.25),
+            p75: quantile(counts, 0.75),
+            p90: quantile(counts, 0.9),
+            frac_q20: fraction(at_least(20)),
+            frac_q30: fraction(at_least(30)),
+        }
+    }
+}
 
+/// Quantile of a distribution given as counts per value (index = value), 
+/// using the nearest-rank method: the smallest value such that 
+/// at least a fraction `p` of all observations are <= value. NaN if there are no observations
+pub fn quantile(counts: &[u64], p: f64) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return f64::NAN;
+    }
+    let rank = ((p * total as f64).ceil() as u64).max(1);
+    let mut cumulative = 0;
+    for (value, &c) in counts.iter().enumerate() {
+        cumulative += c;
+        if cumulative >= rank {
+            return value as f64;
+        }
+    }
+    unreachable!("rank can't exceed the total")
+}
 
-    // let mut df = df!("phred" => phred_scores, "frequency" => freqs, "position" => positions).unwrap();
-    // write_to_csv_polars(&mut df, output_csv_file);    
+/// Writes the per-position summaries as a JSON array
+pub fn write_summary_json(summary: &[PositionQualitySummary], output_json_file: &str) -> std::io::Result<()> {
+    let writer = BufWriter::new(File::create(output_json_file)?);
+    serde_json::to_writer_pretty(writer, summary)?;
+    Ok(())
+}
 
-    write_to_csv_simple(phred_scores,positions, freqs, output_csv_file).unwrap();
+/// Optional outputs of [`run`], besides the raw phred counts
+#[derive(Debug, Default)]
+pub struct PhredOutputs {
+    /// csv file for the per-position quality summary
+    pub summary_csv: Option<String>,
+    /// JSON file for the per-position quality summary
+    pub summary_json: Option<String>,
+    /// prefix for the read-level histograms (mean quality, expected errors, length),
+    /// see [`ReadStatsCounter::write_csv`]
+    pub read_stats_prefix: Option<String>,
+    /// prefix for the base composition tables, see [`BaseComposition::write_csv`]
+    pub composition_prefix: Option<String>,
+}
+
+impl FastqAccumulator for PhredCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        PhredCounter::add(self, fq)
+    }
+    fn merge(&mut self, other: Self) {
+        PhredCounter::merge(self, other)
+    }
+}
+
+/// Counts each fastq file separately, processing several files in parallel.
+/// Returns one [`PhredCounter`] per file (in the same order as `fastq_files`), 
+/// which can be combined via [`PhredCounter::merge`], e.g. to aggregate lanes
+pub fn count_files_parallel(fastq_files: &[String]) -> Vec<PhredCounter> {
+    accumulate_files_parallel(fastq_files)
+}
+
+pub fn run(fastq_files: &[String], output_csv_file:String, outputs: &PhredOutputs, sampling: &Sampling){
+
+    // read-level stats and composition are collected in the same pass, if requested
+    let phred_counter = if outputs.read_stats_prefix.is_some() || outputs.composition_prefix.is_some() {
+        let (phred_counter, (read_stats, composition)): (PhredCounter, (ReadStatsCounter, BaseComposition)) = 
+            merge_all(accumulate_files_sampled(fastq_files, sampling));
+        if let Some(prefix) = &outputs.read_stats_prefix {
+            read_stats.write_csv(prefix).unwrap();
+        }
+        if let Some(prefix) = &outputs.composition_prefix {
+            composition.write_csv(prefix).unwrap();
+        }
+        phred_counter
+    } else {
+        merge_all(accumulate_files_sampled::<PhredCounter>(fastq_files, sampling))
+    };
+
+    // .parquet/.arrow outputs need the `parquet` feature
+    match ArrowFormat::from_path(&output_csv_file) {
+        Some(format) => write_table(&phred_counter, &output_csv_file, format),
+        None => phred_counter.write_csv(output_csv_file).unwrap(),
+    }
+
+    if outputs.summary_csv.is_some() || outputs.summary_json.is_some() {
+        let summary = phred_counter.position_summary();
+        if let Some(fname) = &outputs.summary_csv {
+            write_csv(&summary, fname).unwrap();
+        }
+        if let Some(fname) = &outputs.summary_json {
+            write_summary_json(&summary, fname).unwrap();
+        }
+    }
 }
 
 pub fn write_to_csv_simple(phred_scores: Vec<String>, positions: Vec<u64>, freqs: Vec<u64>, output_csv_file: String) -> Result<(), csv::Error>{
     let mut wtr = csv::Writer::from_path(output_csv_file)?;
 
-    wtr.write_record(["phred","frequency","position"])?;
+    wtr.write_record(["phred","position","frequency"])?;
     for (ph, pos, freq) in izip!(phred_scores, positions, freqs) {
         wtr.write_record(&[ph, pos.to_string(), freq.to_string()])?;
     }
@@ -72,3 +290,103 @@ pub fn write_to_csv_simple(phred_scores: Vec<String>, positions: Vec<u64>, freqs
 //         .finish(df_final)
 //         .unwrap();    
 // }
+
+#[cfg(test)]
+mod testing {
+    use super::{count_files_parallel, quantile, PhredCounter};
+    use crate::accumulator::merge_all;
+    use crate::io::FastqEntry;
+    use crate::test_files::write_fastq_gz;
+
+    fn entry(phred: &str) -> FastqEntry {
+        FastqEntry { header: "r".to_string(), seq: "A".repeat(phred.len()), phred: phred.to_string() }
+    }
+
+    #[test]
+    fn test_add() {
+        let mut c = PhredCounter::new();
+        c.add(&entry("II#"));
+        c.add(&entry("I5"));
+        assert_eq!(c.n_reads(), 2);
+        assert_eq!(c.n_positions(), 3);
+        assert_eq!(c.get(0, 'I'), 2);
+        assert_eq!(c.get(1, 'I'), 1);
+        assert_eq!(c.get(1, '5'), 1);
+        assert_eq!(c.get(2, '#'), 1);
+        assert_eq!(c.get(3, '#'), 0);
+        assert_eq!(c.position_counts(0)[40], 2);
+
+        let mut observed: Vec<_> = c.iter().collect();
+        observed.sort();
+        assert_eq!(observed, vec![('#', 2, 1), ('5', 1, 1), ('I', 0, 2), ('I', 1, 1)]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = PhredCounter::new();
+        a.add(&entry("II"));
+        let mut b = PhredCounter::new();
+        b.add(&entry("I5F"));
+
+        let mut both = PhredCounter::new();
+        both.add(&entry("II"));
+        both.add(&entry("I5F"));
+
+        a.merge(b);
+        assert_eq!(a, both);
+    }
+
+    #[test]
+    fn test_parallel_equals_sequential() {
+        let f1 = "/tmp/rustfastq_phred_counter_1.fastq.gz".to_string();
+        let f2 = "/tmp/rustfastq_phred_counter_2.fastq.gz".to_string();
+        write_fastq_gz(&f1, &[("r1", "ACGT", "II#5"), ("r2", "ACG", "FFF")]);
+        write_fastq_gz(&f2, &[("r3", "ACGTAC", "IIIII#")]);
+
+        let files = vec![f1.clone(), f2.clone()];
+        let per_file = count_files_parallel(&files);
+        assert_eq!(per_file.len(), 2);
+        assert_eq!(per_file[0], PhredCounter::from_files(&[f1]));
+        assert_eq!(per_file[1], PhredCounter::from_files(&[f2]));
+
+        let merged = merge_all(per_file);
+        assert_eq!(merged, PhredCounter::from_files(&files));
+        assert_eq!(merged.n_reads(), 3);
+    }
+
+    #[test]
+    fn test_quantile() {
+        // values 0,1,1,2,2,2,3,3,3,3
+        let counts = [1, 2, 3, 4];
+        assert_eq!(quantile(&counts, 0.1), 0.0);
+        assert_eq!(quantile(&counts, 0.25), 1.0);
+        assert_eq!(quantile(&counts, 0.5), 2.0);
+        assert_eq!(quantile(&counts, 0.9), 3.0);
+        assert!(quantile(&[0, 0], 0.5).is_nan());
+    }
+
+    #[test]
+    fn test_position_summary() {
+        let mut c = PhredCounter::new();
+        // Q40, Q20, Q10, Q30 at position 0
+        for phred in ["I#", "5#", "+", "?"] {
+            c.add(&entry(phred));
+        }
+        let summary = c.position_summary();
+        assert_eq!(summary.len(), 2);
+
+        let s = &summary[0];
+        assert_eq!(s.count, 4);
+        assert_eq!(s.mean, 25.0);
+        assert_eq!(s.median, 20.0);
+        assert_eq!(s.p10, 10.0);
+        assert_eq!(s.p90, 40.0);
+        assert_eq!(s.frac_q20, 0.75);
+        assert_eq!(s.frac_q30, 0.5);
+
+        let s = &summary[1];
+        assert_eq!(s.count, 2);
+        assert_eq!(s.mean, 2.0);
+        assert_eq!(s.frac_q20, 0.0);
+    }
+}
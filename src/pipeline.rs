@@ -0,0 +1,651 @@
+//! Chaining read operations (filter, trim, rename, demux, stats) in a single streaming pass,
+//! instead of writing intermediate files between standalone commands.
+//!
+//! Each step is a [`ReadProcessor`], working on a fragment: a single read, or R1 and R2.
+//! The steps are listed in a TOML or YAML config ([`PipelineConfig`]), e.g.
+//! ```toml
+//! [[steps]]
+//! type = "trim"
+//! adapters = ["truseq"]
+//! sliding_window = "4:20"
+//! min_length = 36
+//!
+//! [[steps]]
+//! type = "filter"
+//! maxee = 2.0
+//!
+//! [[steps]]
+//! type = "stats"
+//! output = "after_filter.csv"
+//! ```
+//! The `filter` and `trim` steps write the fragments they drop to `failed_out` (and `failed_out_r2`),
+//! if given, tagged with the reason (see [`crate::rejected`])
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use serde::Deserialize;
+
+use crate::accumulator::FastqAccumulator;
+use crate::adapter::{trim_pair_by_overlap, OverlapParams};
+use crate::illumina::index_sequence;
+use crate::io::{fastq_list_iter, get_bgzf_writer, paired_fastq_list_iter, FastqEntry};
+use crate::qcfilter::{parse_fraction_below, parse_poly_x, FilterSummary, PairRule, QualityCriterion, QualityFilter};
+use crate::rejected::RejectedReads;
+use crate::stats::StatsCounter;
+use crate::trim::{TrimOptions, TrimResult, TrimSummary, Trimmer};
+use crate::utils::write_csv;
+
+/// Sample name of reads without a known index, in [`DemuxStep`]
+pub const UNDETERMINED: &str = "undetermined";
+
+/// A step of the pipeline
+pub trait ReadProcessor {
+    /// Name of the step in the summary
+    fn name(&self) -> String;
+    /// Transforms/annotates the reads of a fragment (one read, or R1 and R2) in place.
+    /// Returns `false` to drop the fragment
+    fn process(&mut self, reads: &mut [FastqEntry]) -> bool;
+    /// Called after the last fragment, e.g. to write results; returns a summary of the step
+    fn finish(&mut self) -> String {
+        String::new()
+    }
+}
+
+/// Dropped reads of a step, written to the given files (R1, R2)
+fn rejected_reads(failed_out: Option<&str>, failed_out_r2: Option<&str>) -> Vec<RejectedReads> {
+    vec![RejectedReads::new(failed_out), RejectedReads::new(failed_out_r2)]
+}
+
+/// Summary tables of the dropped reads, if there are any
+fn rejection_tables(rejected: &[RejectedReads]) -> String {
+    rejected.iter().enumerate()
+        .filter(|(_, r)| r.total() > 0)
+        .map(|(i, r)| format!("R{} rejected:\n{r}", i + 1))
+        .collect()
+}
+
+/// Drops fragments failing a [`QualityFilter`]; pairs are kept according to the [`PairRule`]
+pub struct FilterStep {
+    pub filter: QualityFilter,
+    pub rule: PairRule,
+    summaries: Vec<FilterSummary>,
+    rejected: Vec<RejectedReads>,
+}
+
+impl FilterStep {
+    pub fn new(filter: QualityFilter, rule: PairRule) -> Self {
+        FilterStep { filter, rule, summaries: Vec::new(), rejected: rejected_reads(None, None) }
+    }
+
+    /// Write the dropped reads to these files
+    pub fn with_failed_out(mut self, failed_out: Option<&str>, failed_out_r2: Option<&str>) -> Self {
+        self.rejected = rejected_reads(failed_out, failed_out_r2);
+        self
+    }
+}
+
+impl ReadProcessor for FilterStep {
+    fn name(&self) -> String {
+        "filter".to_string()
+    }
+
+    fn process(&mut self, reads: &mut [FastqEntry]) -> bool {
+        if self.summaries.len() < reads.len() {
+            self.summaries.resize(reads.len(), FilterSummary::new(&self.filter));
+        }
+        let failures: Vec<Vec<bool>> = reads.iter().map(|fq| self.filter.failures(fq)).collect();
+        let passed: Vec<bool> = failures.iter().zip(self.summaries.iter_mut())
+            .map(|(failures, summary)| summary.add(failures))
+            .collect();
+        let keep = match self.rule {
+            PairRule::Both => passed.iter().all(|&p| p),
+            PairRule::Either => passed.iter().any(|&p| p),
+        };
+        if !keep {
+            let reasons: Vec<Option<String>> = failures.iter().map(|f| self.filter.rejection_reason(f)).collect();
+            RejectedReads::add_fragment(&mut self.rejected, reads, &reasons);
+        }
+        keep
+    }
+
+    fn finish(&mut self) -> String {
+        let summaries: String = self.summaries.iter().enumerate().map(|(i, s)| format!("R{}: {s}", i + 1)).collect();
+        summaries + &rejection_tables(&self.rejected)
+    }
+}
+
+/// Trims the reads (R2 with its own trimmer); pairs optionally to the insert size first.
+/// A fragment is dropped if any read isn't kept
+pub struct TrimStep {
+    trimmers: [Trimmer; 2],
+    overlap: Option<OverlapParams>,
+    summaries: Vec<TrimSummary>,
+    rejected: Vec<RejectedReads>,
+}
+
+impl TrimStep {
+    pub fn new(trimmer_r1: Trimmer, trimmer_r2: Trimmer, overlap: Option<OverlapParams>) -> Self {
+        TrimStep { trimmers: [trimmer_r1, trimmer_r2], overlap, summaries: Vec::new(), rejected: rejected_reads(None, None) }
+    }
+
+    /// Write the dropped reads (as trimmed) to these files
+    pub fn with_failed_out(mut self, failed_out: Option<&str>, failed_out_r2: Option<&str>) -> Self {
+        self.rejected = rejected_reads(failed_out, failed_out_r2);
+        self
+    }
+}
+
+impl ReadProcessor for TrimStep {
+    fn name(&self) -> String {
+        "trim".to_string()
+    }
+
+    fn process(&mut self, reads: &mut [FastqEntry]) -> bool {
+        if self.summaries.len() < reads.len() {
+            self.summaries.resize(reads.len(), TrimSummary::default());
+        }
+        let lengths: Vec<usize> = reads.iter().map(|fq| fq.len()).collect();
+        let insert = match (self.overlap.as_ref(), &mut *reads) {
+            (Some(params), [fq1, fq2]) => trim_pair_by_overlap(fq1, fq2, params),
+            _ => None,
+        };
+        let adapters: Vec<bool> = reads.iter_mut().zip(&self.trimmers).zip(&lengths)
+            .map(|((fq, trimmer), &len)| trimmer.trim_read(fq).is_some() || insert.is_some_and(|l| l < len))
+            .collect();
+        // the first read not kept decides, for all
+        let result = reads.iter().zip(&self.trimmers)
+            .map(|(fq, trimmer)| trimmer.check(fq))
+            .find(|&r| r != TrimResult::Kept)
+            .unwrap_or(TrimResult::Kept);
+        for (i, summary) in self.summaries.iter_mut().take(reads.len()).enumerate() {
+            summary.add(lengths[i], reads[i].len(), adapters[i], result);
+        }
+        if result != TrimResult::Kept {
+            let reasons: Vec<Option<String>> = reads.iter().zip(&self.trimmers).map(|(fq, trimmer)| trimmer.rejection_reason(fq)).collect();
+            RejectedReads::add_fragment(&mut self.rejected, reads, &reasons);
+        }
+        result == TrimResult::Kept
+    }
+
+    fn finish(&mut self) -> String {
+        let summaries: String = self.summaries.iter().enumerate().map(|(i, s)| format!("R{}: {s}", i + 1)).collect();
+        summaries + &rejection_tables(&self.rejected)
+    }
+}
+
+/// Renames the reads: a prefix, or the prefix and a running number replacing the read id.
+/// Mates get the same name
+pub struct RenameStep {
+    pub prefix: String,
+    pub numbered: bool,
+    /// drop the description after the read id
+    pub strip_comment: bool,
+    count: u64,
+}
+
+impl RenameStep {
+    pub fn new(prefix: &str, numbered: bool, strip_comment: bool) -> Self {
+        RenameStep { prefix: prefix.to_string(), numbered, strip_comment, count: 0 }
+    }
+
+    fn rename(&self, header: &str) -> String {
+        let header = header.strip_prefix('@').unwrap_or(header);
+        let (id, comment) = match header.split_once(' ') {
+            Some((id, comment)) => (id, Some(comment)),
+            None => (header, None),
+        };
+        let id = if self.numbered { format!("{}{}", self.prefix, self.count) } else { format!("{}{id}", self.prefix) };
+        match comment {
+            Some(comment) if !self.strip_comment => format!("@{id} {comment}"),
+            _ => format!("@{id}"),
+        }
+    }
+}
+
+impl ReadProcessor for RenameStep {
+    fn name(&self) -> String {
+        "rename".to_string()
+    }
+
+    fn process(&mut self, reads: &mut [FastqEntry]) -> bool {
+        for fq in reads.iter_mut() {
+            fq.header = self.rename(&fq.header);
+        }
+        self.count += 1;
+        true
+    }
+}
+
+type FastqWriter = noodles::bgzf::Writer<BufWriter<File>>;
+
+/// Writes each fragment to the files of its sample (`{outdir}/{sample}_R1.fastq.gz`, ...),
+/// by the index sequence in the read header. Reads are passed on unchanged
+pub struct DemuxStep {
+    /// index sequence (as in the header, e.g. `ACGTACGT+TTGCATGC`) to sample
+    samples: HashMap<String, String>,
+    outdir: String,
+    writers: HashMap<String, Vec<FastqWriter>>,
+    counts: HashMap<String, u64>,
+}
+
+impl DemuxStep {
+    pub fn new(samples: HashMap<String, String>, outdir: &str) -> Self {
+        DemuxStep { samples, outdir: outdir.to_string(), writers: HashMap::new(), counts: HashMap::new() }
+    }
+
+    /// Sample of the read, [`UNDETERMINED`] if its index isn't known
+    pub fn sample(&self, fq: &FastqEntry) -> &str {
+        index_sequence(&fq.header)
+            .and_then(|index| self.samples.get(index))
+            .map_or(UNDETERMINED, |s| s.as_str())
+    }
+}
+
+impl ReadProcessor for DemuxStep {
+    fn name(&self) -> String {
+        "demux".to_string()
+    }
+
+    fn process(&mut self, reads: &mut [FastqEntry]) -> bool {
+        let sample = self.sample(&reads[0]).to_string();
+        let writers = self.writers.entry(sample.clone()).or_insert_with(|| {
+            (1..=reads.len()).map(|i| get_bgzf_writer(&format!("{}/{sample}_R{i}.fastq.gz", self.outdir))).collect()
+        });
+        for (writer, fq) in writers.iter_mut().zip(reads.iter()) {
+            write!(writer, "{}", fq.to_string()).unwrap();
+        }
+        *self.counts.entry(sample).or_insert(0) += 1;
+        true
+    }
+
+    fn finish(&mut self) -> String {
+        // dropping the writers finishes the files
+        self.writers.clear();
+        let mut counts: Vec<(&String, &u64)> = self.counts.iter().collect();
+        counts.sort();
+        counts.iter().map(|(sample, n)| format!("{sample}: {n}\n")).collect()
+    }
+}
+
+/// Statistics of the reads at this point of the pipeline, optionally written as csv
+pub struct StatsStep {
+    pub label: String,
+    pub output: Option<String>,
+    counters: Vec<StatsCounter>,
+}
+
+impl StatsStep {
+    pub fn new(label: &str, output: Option<String>) -> Self {
+        StatsStep { label: label.to_string(), output, counters: Vec::new() }
+    }
+}
+
+impl ReadProcessor for StatsStep {
+    fn name(&self) -> String {
+        format!("stats {}", self.label)
+    }
+
+    fn process(&mut self, reads: &mut [FastqEntry]) -> bool {
+        if self.counters.len() < reads.len() {
+            self.counters.resize(reads.len(), StatsCounter::default());
+        }
+        for (counter, fq) in self.counters.iter_mut().zip(reads.iter()) {
+            counter.add(fq);
+        }
+        true
+    }
+
+    fn finish(&mut self) -> String {
+        let stats: Vec<_> = self.counters.iter().enumerate()
+            .map(|(i, c)| c.summary(&format!("{}_R{}", self.label, i + 1)))
+            .collect();
+        if let Some(fname) = &self.output {
+            write_csv(&stats, fname).unwrap();
+        }
+        stats.iter()
+            .map(|s| format!("{}: {} reads, {} bases, mean length {:.1}, Q30 {:.1}%\n", s.file, s.reads, s.bases, s.mean_len, s.q30_percent))
+            .collect()
+    }
+}
+
+/// Criteria of a `filter` step, as for `qcfilter`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    pub maxee: Option<f64>,
+    pub min_mean_q: Option<f64>,
+    pub min_base_q: Option<u8>,
+    /// `QUALITY:FRACTION`
+    pub max_frac_below: Option<String>,
+    pub max_dust: Option<f64>,
+    pub min_entropy: Option<f64>,
+    /// `BASE[:MIN_LENGTH]`
+    pub poly_x: Vec<String>,
+    pub pair_rule: PairRule,
+    pub failed_out: Option<String>,
+    pub failed_out_r2: Option<String>,
+}
+
+impl FilterConfig {
+    pub fn filter(&self) -> Result<QualityFilter, String> {
+        let mut criteria: Vec<QualityCriterion> = [
+            self.maxee.map(QualityCriterion::MaxExpectedErrors),
+            self.min_mean_q.map(QualityCriterion::MinMeanQuality),
+            self.min_base_q.map(QualityCriterion::MinBaseQuality),
+            self.max_frac_below.as_deref().map(parse_fraction_below).transpose()?,
+            self.max_dust.map(QualityCriterion::MaxDust),
+            self.min_entropy.map(QualityCriterion::MinEntropy),
+        ].into_iter().flatten().collect();
+        for s in &self.poly_x {
+            criteria.push(parse_poly_x(s)?);
+        }
+        if criteria.is_empty() {
+            return Err("filter step without any criterion".to_string());
+        }
+        Ok(QualityFilter::new(criteria))
+    }
+}
+
+/// A `trim` step: the [`TrimOptions`], and where to write the dropped reads.
+/// Unknown keys end up in [`TrimOptions::unknown`]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TrimConfig {
+    #[serde(flatten)]
+    pub options: TrimOptions,
+    pub failed_out: Option<String>,
+    pub failed_out_r2: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenameConfig {
+    pub prefix: String,
+    pub numbered: bool,
+    pub strip_comment: bool,
+}
+
+/// One line of a demux samplesheet
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct SampleIndex {
+    sample: String,
+    index: String,
+}
+
+/// Samples of a `demux` step, inline (`index = "sample"`) and/or from a csv with `sample,index` columns
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DemuxConfig {
+    #[serde(default)]
+    pub samples: HashMap<String, String>,
+    pub samplesheet: Option<String>,
+    pub outdir: String,
+}
+
+impl DemuxConfig {
+    pub fn samples(&self) -> Result<HashMap<String, String>, String> {
+        let mut samples = self.samples.clone();
+        if let Some(fname) = &self.samplesheet {
+            let mut reader = csv::Reader::from_path(fname).map_err(|e| format!("{fname}: {e}"))?;
+            for row in reader.deserialize() {
+                let row: SampleIndex = row.map_err(|e| format!("{fname}: {e}"))?;
+                samples.insert(row.index, row.sample);
+            }
+        }
+        Ok(samples)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    pub label: Option<String>,
+    pub output: Option<String>,
+}
+
+/// A step in the config
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StepConfig {
+    Filter(FilterConfig),
+    Trim(TrimConfig),
+    Rename(RenameConfig),
+    Demux(DemuxConfig),
+    Stats(StatsConfig),
+}
+
+/// The steps of a pipeline, in order
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub steps: Vec<StepConfig>,
+}
+
+impl PipelineConfig {
+    pub fn from_toml(s: &str) -> Result<Self, String> {
+        toml::from_str::<Self>(s).map_err(|e| e.to_string())?.check_unknown_fields()
+    }
+
+    pub fn from_yaml(s: &str) -> Result<Self, String> {
+        serde_yaml::from_str::<Self>(s).map_err(|e| e.to_string())?.check_unknown_fields()
+    }
+
+    /// The other steps deny unknown fields while parsing, `trim` steps only collect them
+    fn check_unknown_fields(self) -> Result<Self, String> {
+        for step in &self.steps {
+            if let StepConfig::Trim(config) = step {
+                if let Some(key) = config.options.unknown.keys().next() {
+                    return Err(format!("unknown field `{key}` in a trim step"));
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Reads a `.toml` or `.yaml`/`.yml` config
+    pub fn from_file(fname: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(fname).map_err(|e| format!("{fname}: {e}"))?;
+        match std::path::Path::new(fname).extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("yaml" | "yml") => Self::from_yaml(&content),
+            _ => Err(format!("{fname}: expected a .toml or .yaml config")),
+        }
+    }
+
+    /// Sets up the steps; the input files are needed to detect adapters
+    pub fn build(&self, r1_list: &[String], r2_list: &[String]) -> Result<Pipeline, String> {
+        let mut steps: Vec<Box<dyn ReadProcessor>> = Vec::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            steps.push(match step {
+                StepConfig::Filter(config) => Box::new(
+                    FilterStep::new(config.filter()?, config.pair_rule)
+                        .with_failed_out(config.failed_out.as_deref(), config.failed_out_r2.as_deref())
+                ),
+                StepConfig::Trim(config) => {
+                    let (trimmer_r1, trimmer_r2) = config.options.trimmers(r1_list, r2_list)?;
+                    Box::new(
+                        TrimStep::new(trimmer_r1, trimmer_r2, config.options.overlap_params())
+                            .with_failed_out(config.failed_out.as_deref(), config.failed_out_r2.as_deref())
+                    )
+                }
+                StepConfig::Rename(config) => Box::new(RenameStep::new(&config.prefix, config.numbered, config.strip_comment)),
+                StepConfig::Demux(config) => {
+                    std::fs::create_dir_all(&config.outdir).map_err(|e| format!("{}: {e}", config.outdir))?;
+                    Box::new(DemuxStep::new(config.samples()?, &config.outdir))
+                }
+                StepConfig::Stats(config) => {
+                    let label = config.label.clone().unwrap_or(format!("step{}", i + 1));
+                    Box::new(StatsStep::new(&label, config.output.clone()))
+                }
+            });
+        }
+        Ok(Pipeline::new(steps))
+    }
+}
+
+/// Summary of a single step
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepSummary {
+    pub name: String,
+    pub fragments_in: u64,
+    pub fragments_out: u64,
+    pub details: String,
+}
+
+/// Runs each fragment through the steps in order, until one drops it
+pub struct Pipeline {
+    steps: Vec<Box<dyn ReadProcessor>>,
+    /// fragments entering each step, plus the ones coming out of the last
+    counts: Vec<u64>,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<Box<dyn ReadProcessor>>) -> Self {
+        let counts = vec![0; steps.len() + 1];
+        Pipeline { steps, counts }
+    }
+
+    /// Returns whether the fragment made it through all steps
+    pub fn process(&mut self, reads: &mut [FastqEntry]) -> bool {
+        for (i, step) in self.steps.iter_mut().enumerate() {
+            self.counts[i] += 1;
+            if !step.process(reads) {
+                return false;
+            }
+        }
+        *self.counts.last_mut().unwrap() += 1;
+        true
+    }
+
+    pub fn finish(mut self) -> Vec<StepSummary> {
+        self.steps.iter_mut().enumerate()
+            .map(|(i, step)| StepSummary {
+                name: step.name(),
+                fragments_in: self.counts[i],
+                fragments_out: self.counts[i + 1],
+                details: step.finish(),
+            })
+            .collect()
+    }
+
+    /// Runs the reads (pairs if `r2_list` isn't empty) through the pipeline,
+    /// writing what's left to `outnames` (one per mate)
+    pub fn run(mut self, r1_list: &[String], r2_list: &[String], outnames: &[String]) -> Result<Vec<StepSummary>, String> {
+        let mates = if r2_list.is_empty() { 1 } else { 2 };
+        if outnames.len() != mates {
+            return Err(format!("need {mates} outputs (one per mate), got {}", outnames.len()));
+        }
+        let mut writers: Vec<FastqWriter> = outnames.iter().map(|f| get_bgzf_writer(f)).collect();
+        let mut write = |reads: &[FastqEntry]| {
+            for (writer, fq) in writers.iter_mut().zip(reads) {
+                write!(writer, "{}", fq.to_string()).unwrap();
+            }
+        };
+        if r2_list.is_empty() {
+            for fq in fastq_list_iter(r1_list) {
+                let mut reads = [fq];
+                if self.process(&mut reads) {
+                    write(&reads);
+                }
+            }
+        } else {
+            for (fq1, fq2) in paired_fastq_list_iter(r1_list, r2_list) {
+                let mut reads = [fq1, fq2];
+                if self.process(&mut reads) {
+                    write(&reads);
+                }
+            }
+        }
+        Ok(self.finish())
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{PipelineConfig, RenameStep, ReadProcessor, StepConfig};
+    use crate::io::{FastIterator, FastqEntry};
+    use crate::test_files::write_fastq_gz;
+
+    const CONFIG: &str = r#"
+[[steps]]
+type = "trim"
+trailing = 3
+min_length = 4
+
+[[steps]]
+type = "filter"
+maxee = 1.0
+failed_out = "/tmp/rustfastq_pipeline_failed.fastq.gz"
+
+[[steps]]
+type = "rename"
+prefix = "s1_"
+numbered = true
+
+[[steps]]
+type = "stats"
+label = "final"
+"#;
+
+    #[test]
+    fn test_config() {
+        let config = PipelineConfig::from_toml(CONFIG).unwrap();
+        assert_eq!(config.steps.len(), 4);
+        assert!(matches!(&config.steps[0], StepConfig::Trim(config) if config.options.trailing == Some(3) && config.options.min_length == 4));
+
+        let yaml = "steps:\n  - type: filter\n    maxee: 1.0\n    pair_rule: either\n  - type: trim\n    adapters: [truseq]\n";
+        let config = PipelineConfig::from_yaml(yaml).unwrap();
+        assert!(matches!(&config.steps[1], StepConfig::Trim(config) if config.options.adapters.adapters == vec!["truseq"]));
+
+        assert!(PipelineConfig::from_toml("[[steps]]\ntype = \"filter\"\nmaxe = 1.0\n").is_err());
+        let err = PipelineConfig::from_toml("[[steps]]\ntype = \"trim\"\nmin_lenght = 36\nadapters = [\"truseq\"]\n").unwrap_err();
+        assert_eq!(err, "unknown field `min_lenght` in a trim step");
+        assert!(PipelineConfig::from_yaml("steps:\n  - type: trim\n    sliding_windo: 4:20\n").is_err());
+        assert!(PipelineConfig::from_toml("[[steps]]\ntype = \"filter\"\n").unwrap().build(&[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_demux() {
+        let outdir = "/tmp/rustfastq_pipeline_demux/new";
+        let _ = std::fs::remove_dir_all(outdir);
+        let config = format!("[[steps]]\ntype = \"demux\"\noutdir = \"{outdir}\"\nsamples = {{ ACGT = \"s1\" }}\n");
+        let mut pipeline = PipelineConfig::from_toml(&config).unwrap().build(&[], &[]).unwrap();
+        let mut reads = [FastqEntry { header: "@r 1:N:0:ACGT".to_string(), seq: "A".to_string(), phred: "I".to_string() }];
+        assert!(pipeline.process(&mut reads));
+        let summary = pipeline.finish();
+        assert_eq!(summary[0].details, "s1: 1\n");
+        assert_eq!(FastIterator::new(&format!("{outdir}/s1_R1.fastq.gz")).count(), 1);
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut step = RenameStep::new("x_", false, true);
+        let mut reads = [FastqEntry { header: "@read1 1:N:0:ACGT".to_string(), seq: "A".to_string(), phred: "I".to_string() }];
+        step.process(&mut reads);
+        assert_eq!(reads[0].header, "@x_read1");
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let fname = "/tmp/rustfastq_pipeline_in.fastq.gz";
+        let out = "/tmp/rustfastq_pipeline_out.fastq.gz";
+        // a: trimmed and kept, b: too short after trimming, c: fails the filter
+        write_fastq_gz(fname, &[("a", "ACGTAC", "IIII##"), ("b", "ACGTAC", "II####"), ("c", "ACGTAC", "I!!!!I")]);
+        let pipeline = PipelineConfig::from_toml(CONFIG).unwrap().build(&[fname.to_string()], &[]).unwrap();
+        let summary = pipeline.run(&[fname.to_string()], &[], &[out.to_string()]).unwrap();
+
+        let counts: Vec<(u64, u64)> = summary.iter().map(|s| (s.fragments_in, s.fragments_out)).collect();
+        assert_eq!(counts, vec![(3, 2), (2, 1), (1, 1), (1, 1)]);
+        assert!(summary[3].details.starts_with("final_R1: 1 reads"));
+        let kept: Vec<(String, String)> = FastIterator::new(out).map(|fq| (fq.header, fq.seq)).collect();
+        assert_eq!(kept, vec![("@s1_0".to_string(), "ACGT".to_string())]);
+        assert!(summary[0].details.contains("R1 rejected:\nreason"));
+        let failed: Vec<String> = FastIterator::new("/tmp/rustfastq_pipeline_failed.fastq.gz").map(|fq| fq.header).collect();
+        assert_eq!(failed, vec!["@c reject=maxee<=1"]);
+
+        // pairs need an output for R2, too
+        let pipeline = PipelineConfig::from_toml(CONFIG).unwrap().build(&[fname.to_string()], &[fname.to_string()]).unwrap();
+        assert!(pipeline.run(&[fname.to_string()], &[fname.to_string()], &[out.to_string()]).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Minimal SVG plots for the HTML reports.
+//! Plain SVG without javascript or external resources, so the reports are self-contained files
+use std::fmt::Write;
+
+const WIDTH: f64 = 720.0;
+const HEIGHT: f64 = 340.0;
+const MARGIN_LEFT: f64 = 60.0;
+const MARGIN_RIGHT: f64 = 180.0;
+const MARGIN_TOP: f64 = 30.0;
+const MARGIN_BOTTOM: f64 = 60.0;
+const COLORS: &[&str] = &["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f"];
+
+/// A named line in a [`line_plot`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Series {
+    pub fn new(name: &str, points: Vec<(f64, f64)>) -> Self {
+        Series { name: name.to_string(), points }
+    }
+}
+
+/// Escapes text for use in HTML/SVG
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Round step size (1, 2 or 5 times a power of ten) giving about `n` ticks over the range
+fn tick_step(min: f64, max: f64, n: usize) -> f64 {
+    let raw = (max - min) / n as f64;
+    let magnitude = 10_f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * magnitude).find(|&s| s >= raw).unwrap()
+}
+
+fn ticks(min: f64, max: f64) -> Vec<f64> {
+    let step = tick_step(min, max, 5);
+    let first = (min / step).ceil() as i64;
+    let last = (max / step).floor() as i64;
+    // rounded, to avoid ticks like 0.6000000000000001
+    (first..=last).map(|i| (i as f64 * step * 1e9).round() / 1e9).collect()
+}
+
+fn format_tick(x: f64) -> String {
+    if x.abs() >= 1e6 {
+        format!("{:.1e}", x)
+    } else if x.fract() == 0.0 {
+        format!("{}", x)
+    } else {
+        format!("{:.2}", x).trim_end_matches('0').to_string()
+    }
+}
+
+/// Range of the values, widened if all values are the same
+fn data_range(values: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    let (min, max) = values.filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if min > max {
+        return None;
+    }
+    if min == max {
+        return Some((min - 1.0, max + 1.0));
+    }
+    Some((min, max))
+}
+
+/// The svg element with title and axis labels
+fn svg_start(title: &str, x_label: &str, y_label: &str) -> String {
+    let mut svg = String::new();
+    write!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="11">"#).unwrap();
+    write!(svg, r#"<text x="{}" y="18" text-anchor="middle" font-size="14">{}</text>"#,
+        MARGIN_LEFT + (WIDTH - MARGIN_LEFT - MARGIN_RIGHT) / 2.0, escape(title)).unwrap();
+    write!(svg, r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+        MARGIN_LEFT + (WIDTH - MARGIN_LEFT - MARGIN_RIGHT) / 2.0, HEIGHT - 8.0, escape(x_label)).unwrap();
+    let y_mid = MARGIN_TOP + (HEIGHT - MARGIN_TOP - MARGIN_BOTTOM) / 2.0;
+    write!(svg, r#"<text x="14" y="{y_mid}" text-anchor="middle" transform="rotate(-90 14 {y_mid})">{}</text>"#,
+        escape(y_label)).unwrap();
+    svg
+}
+
+fn no_data(mut svg: String) -> String {
+    write!(svg, r##"<text x="{}" y="{}" text-anchor="middle" fill="#888">no data</text></svg>"##,
+        WIDTH / 2.0, HEIGHT / 2.0).unwrap();
+    svg
+}
+
+/// Maps data coordinates to pixels
+struct Frame {
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+}
+
+impl Frame {
+    fn x(&self, x: f64) -> f64 {
+        let (lo, hi) = self.x_range;
+        MARGIN_LEFT + (x - lo) / (hi - lo) * (WIDTH - MARGIN_LEFT - MARGIN_RIGHT)
+    }
+
+    fn y(&self, y: f64) -> f64 {
+        let (lo, hi) = self.y_range;
+        HEIGHT - MARGIN_BOTTOM - (y - lo) / (hi - lo) * (HEIGHT - MARGIN_TOP - MARGIN_BOTTOM)
+    }
+
+    /// the axes, y-ticks with grid lines and (if `x_ticks`) x-ticks
+    fn axes(&self, svg: &mut String, x_ticks: bool) {
+        let (x0, x1) = (self.x(self.x_range.0), self.x(self.x_range.1));
+        let (y0, y1) = (self.y(self.y_range.0), self.y(self.y_range.1));
+        for t in ticks(self.y_range.0, self.y_range.1) {
+            let y = self.y(t);
+            write!(svg, r##"<line x1="{x0}" x2="{x1}" y1="{y}" y2="{y}" stroke="#e5e5e5"/>"##).unwrap();
+            write!(svg, r#"<text x="{}" y="{}" text-anchor="end">{}</text>"#, x0 - 5.0, y + 4.0, format_tick(t)).unwrap();
+        }
+        if x_ticks {
+            for t in ticks(self.x_range.0, self.x_range.1) {
+                let x = self.x(t);
+                write!(svg, r##"<line x1="{x}" x2="{x}" y1="{y0}" y2="{}" stroke="#000"/>"##, y0 + 4.0).unwrap();
+                write!(svg, r#"<text x="{x}" y="{}" text-anchor="middle">{}</text>"#, y0 + 16.0, format_tick(t)).unwrap();
+            }
+        }
+        write!(svg, r##"<path d="M{x0},{y1} L{x0},{y0} L{x1},{y0}" fill="none" stroke="#000"/>"##).unwrap();
+    }
+}
+
+/// Line plot of one or more series, with a legend on the right.
+/// The y-axis spans `y_range` if given, otherwise the range of the data.
+/// Non-finite points are skipped
+pub fn line_plot(title: &str, x_label: &str, y_label: &str, series: &[Series], y_range: Option<(f64, f64)>) -> String {
+    let mut svg = svg_start(title, x_label, y_label);
+    let points = || series.iter().flat_map(|s| s.points.iter()).filter(|(x, y)| x.is_finite() && y.is_finite());
+    let (Some(x_range), Some(y_data)) = (data_range(points().map(|p| p.0)), data_range(points().map(|p| p.1))) else {
+        return no_data(svg);
+    };
+    let frame = Frame { x_range, y_range: y_range.unwrap_or(y_data) };
+    frame.axes(&mut svg, true);
+
+    for (i, s) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let coords: Vec<String> = s.points.iter()
+            .filter(|(x, y)| x.is_finite() && y.is_finite())
+            .map(|&(x, y)| format!("{:.1},{:.1}", frame.x(x), frame.y(y)))
+            .collect();
+        write!(svg, r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="1.5"/>"#, coords.join(" ")).unwrap();
+
+        let legend_y = MARGIN_TOP + 10.0 + 16.0 * i as f64;
+        let legend_x = WIDTH - MARGIN_RIGHT + 15.0;
+        write!(svg, r#"<line x1="{legend_x}" x2="{}" y1="{legend_y}" y2="{legend_y}" stroke="{color}" stroke-width="3"/>"#, legend_x + 20.0).unwrap();
+        write!(svg, r#"<text x="{}" y="{}">{}</text>"#, legend_x + 25.0, legend_y + 4.0, escape(&s.name)).unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Bar plot of labelled values (e.g. categories or histogram bins), y-axis starting at 0
+pub fn bar_plot(title: &str, x_label: &str, y_label: &str, bars: &[(String, f64)]) -> String {
+    let mut svg = svg_start(title, x_label, y_label);
+    let Some((_, y_max)) = data_range(bars.iter().map(|b| b.1)) else {
+        return no_data(svg);
+    };
+    let frame = Frame { x_range: (0.0, bars.len() as f64), y_range: (0.0, y_max.max(1e-9)) };
+    frame.axes(&mut svg, false);
+
+    let bar_width = frame.x(1.0) - frame.x(0.0);
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let x = frame.x(i as f64);
+        if value.is_finite() {
+            let y = frame.y(*value);
+            write!(svg, r#"<rect x="{:.1}" y="{y:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#,
+                x + 0.1 * bar_width, 0.8 * bar_width, frame.y(0.0) - y, COLORS[0]).unwrap();
+        }
+        let label_x = x + bar_width / 2.0;
+        let label_y = HEIGHT - MARGIN_BOTTOM + 12.0;
+        write!(svg, r#"<text x="{label_x:.1}" y="{label_y}" text-anchor="end" transform="rotate(-45 {label_x:.1} {label_y})">{}</text>"#,
+            escape(label)).unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{bar_plot, escape, line_plot, ticks, Series};
+
+    #[test]
+    fn test_ticks() {
+        assert_eq!(ticks(0.0, 40.0), vec![0.0, 10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(ticks(0.0, 1.0), vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0]);
+        assert_eq!(ticks(3.0, 148.0), vec![50.0, 100.0]);
+    }
+
+    #[test]
+    fn test_plots() {
+        let svg = line_plot("Quality <mean>", "position", "Q", &[Series::new("mean", vec![(0.0, 30.0), (1.0, f64::NAN), (2.0, 35.0)])], None);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert!(svg.contains("Quality &lt;mean&gt;"));
+        assert_eq!(svg.matches("<polyline").count(), 1);
+
+        let empty = line_plot("t", "x", "y", &[Series::new("mean", vec![])], None);
+        assert!(empty.contains("no data"));
+
+        let svg = bar_plot("t", "x", "y", &[("a".to_string(), 1.0), ("b".to_string(), 2.0)]);
+        assert_eq!(svg.matches("<rect").count(), 2);
+        assert_eq!(escape("a&b"), "a&amp;b");
+    }
+}
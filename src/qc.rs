@@ -0,0 +1,674 @@
+//! Single-pass QC: runs all QC accumulators over the reads at once and grades each
+//! module as pass/warn/fail (similar to FastQC).
+//!
+//! The results are written as a self-contained HTML report (inline SVG plots, see [`crate::plot`])
+//! and as JSON that MultiQC picks up as custom content (if the filename ends in `_mqc.json`)
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::accumulator::FastqAccumulator;
+use crate::composition::BaseComposition;
+use crate::duplication::DuplicationCounter;
+use crate::histogram::Histogram;
+use crate::illumina::index_sequence;
+use crate::io::FastqEntry;
+use crate::overrepresented::{AdapterContent, OverrepresentedCounter, OverrepresentedSequence, DEFAULT_MIN_FRACTION, TRACKED_READS};
+use crate::phred_counter::PhredCounter;
+use crate::plot::{bar_plot, escape, line_plot, Series};
+use crate::read_stats::ReadStatsCounter;
+use crate::sampling::Sampling;
+use crate::stats::{estimated_reads, FastqStats, StatsCounter};
+
+/// number of indices listed in the report
+const TOP_INDICES: usize = 20;
+
+/// Counts of the sample indices in the read headers (see [`index_sequence`]).
+/// As in [`OverrepresentedCounter`], indices not seen within the first [`TRACKED_READS`] reads aren't counted
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexCounter {
+    counts: HashMap<String, u64>,
+    n_reads: u64,
+}
+
+/// A sample index and how often it was seen
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexCount {
+    pub index: String,
+    pub count: u64,
+    pub percent: f64,
+}
+
+impl IndexCounter {
+    /// The `n` most frequent indices
+    pub fn top(&self, n: usize) -> Vec<IndexCount> {
+        let mut counts: Vec<(&String, &u64)> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        counts.into_iter()
+            .take(n)
+            .map(|(index, &count)| IndexCount {
+                index: index.clone(),
+                count,
+                percent: 100.0 * count as f64 / self.n_reads as f64,
+            })
+            .collect()
+    }
+}
+
+impl FastqAccumulator for IndexCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        if let Some(index) = index_sequence(&fq.header) {
+            if self.n_reads < TRACKED_READS {
+                *self.counts.entry(index.to_string()).or_insert(0) += 1;
+            } else if let Some(count) = self.counts.get_mut(index) {
+                *count += 1;
+            }
+        }
+        self.n_reads += 1;
+    }
+
+    fn merge(&mut self, other: Self) {
+        for (index, count) in other.counts {
+            *self.counts.entry(index).or_insert(0) += count;
+        }
+        self.n_reads += other.n_reads;
+    }
+}
+
+/// All the accumulators behind the QC report, filled in a single pass
+#[derive(Debug, Clone, Default)]
+pub struct QcCounter {
+    pub stats: StatsCounter,
+    pub phred: PhredCounter,
+    pub read_stats: ReadStatsCounter,
+    pub composition: BaseComposition,
+    pub duplication: DuplicationCounter,
+    pub overrepresented: OverrepresentedCounter,
+    pub adapters: AdapterContent,
+    pub indices: IndexCounter,
+}
+
+impl FastqAccumulator for QcCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        self.stats.add(fq);
+        self.phred.add(fq);
+        self.read_stats.add(fq);
+        self.composition.add(fq);
+        self.duplication.add(fq);
+        self.overrepresented.add(fq);
+        self.adapters.add(fq);
+        self.indices.add(fq);
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.stats.merge(other.stats);
+        self.phred.merge(other.phred);
+        self.read_stats.merge(other.read_stats);
+        self.composition.merge(other.composition);
+        self.duplication.merge(other.duplication);
+        self.overrepresented.merge(other.overrepresented);
+        self.adapters.merge(other.adapters);
+        self.indices.merge(other.indices);
+    }
+}
+
+/// Outcome of a QC module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Warn/fail limits of a module
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Threshold {
+    pub warn: f64,
+    pub fail: f64,
+}
+
+impl Threshold {
+    pub fn new(warn: f64, fail: f64) -> Self {
+        Threshold { warn, fail }
+    }
+
+    /// Status of a value where higher is worse
+    pub fn grade_above(&self, value: f64) -> Status {
+        if value > self.fail {
+            Status::Fail
+        } else if value > self.warn {
+            Status::Warn
+        } else {
+            Status::Pass
+        }
+    }
+
+    /// Status of a value where lower is worse
+    pub fn grade_below(&self, value: f64) -> Status {
+        if value < self.fail {
+            Status::Fail
+        } else if value < self.warn {
+            Status::Warn
+        } else {
+            Status::Pass
+        }
+    }
+}
+
+/// Thresholds of all modules; the defaults follow FastQC.
+/// When read from JSON, missing modules keep their default
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QcThresholds {
+    /// lowest median quality of any position
+    pub per_base_quality: Threshold,
+    /// most common mean read quality
+    pub per_sequence_quality: Threshold,
+    /// largest difference between A and T (or G and C) at any position, in percent
+    pub per_base_content: Threshold,
+    /// percentage of reads deviating from a normal distribution of the GC content
+    pub per_sequence_gc: Threshold,
+    /// largest percentage of N at any position
+    pub n_content: Threshold,
+    /// percentage of reads left after deduplication
+    pub duplication: Threshold,
+    /// percentage of reads made up by the most common sequence
+    pub overrepresented: Threshold,
+    /// largest percentage of reads containing any of the adapters
+    pub adapter_content: Threshold,
+}
+
+impl Default for QcThresholds {
+    fn default() -> Self {
+        QcThresholds {
+            per_base_quality: Threshold::new(25.0, 20.0),
+            per_sequence_quality: Threshold::new(27.0, 20.0),
+            per_base_content: Threshold::new(10.0, 20.0),
+            per_sequence_gc: Threshold::new(15.0, 30.0),
+            n_content: Threshold::new(5.0, 20.0),
+            duplication: Threshold::new(70.0, 50.0),
+            overrepresented: Threshold::new(0.1, 1.0),
+            adapter_content: Threshold::new(5.0, 10.0),
+        }
+    }
+}
+
+impl QcThresholds {
+    pub fn from_json(fname: &str) -> std::io::Result<Self> {
+        let reader = std::io::BufReader::new(File::open(fname)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+/// Result of a single QC module
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleResult {
+    pub module: String,
+    pub status: Status,
+    /// the value compared against the module's thresholds
+    pub value: f64,
+    pub message: String,
+}
+
+impl ModuleResult {
+    fn new(module: &str, status: Status, value: f64, message: String) -> Self {
+        ModuleResult { module: module.to_string(), status, value, message }
+    }
+}
+
+/// The summary of a QC run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QcReport {
+    pub sample: String,
+    pub files: Vec<String>,
+    pub stats: FastqStats,
+    pub percent_unique: f64,
+    pub modules: Vec<ModuleResult>,
+    /// how the reads were sampled (see [`Sampling`]), `None` if all reads were used
+    pub sampling: Option<String>,
+}
+
+impl QcReport {
+    /// the worst status of all modules
+    pub fn status(&self) -> Status {
+        self.modules.iter().map(|m| m.status).max().unwrap_or(Status::Pass)
+    }
+}
+
+/// Percentage of reads that would have to move to turn the GC histogram (bins of 1%) into
+/// a normal distribution with the same mean and standard deviation, as in FastQC
+pub fn gc_deviation(gc_content: &Histogram) -> f64 {
+    let counts = gc_content.counts();
+    let total = gc_content.total() as f64;
+    let mean = gc_content.mean();
+    let var = counts.iter().enumerate()
+        .map(|(i, &c)| c as f64 * (i as f64 - mean).powi(2))
+        .sum::<f64>() / total;
+    if var == 0.0 {
+        // all reads have the same GC content: that's a (degenerate) normal distribution
+        return if total > 0.0 { 0.0 } else { f64::NAN };
+    }
+    let sd = var.sqrt();
+    let deviation: f64 = (0..=100)
+        .map(|gc| {
+            let observed = counts.get(gc).copied().unwrap_or(0) as f64;
+            let z = (gc as f64 - mean) / sd;
+            let expected = total * (-0.5 * z * z).exp() / (sd * (2.0 * std::f64::consts::PI).sqrt());
+            (observed - expected).abs()
+        })
+        .sum();
+    100.0 * deviation / total
+}
+
+impl QcCounter {
+    /// Grades all modules
+    pub fn evaluate(&self, thresholds: &QcThresholds) -> Vec<ModuleResult> {
+        let mut modules = Vec::new();
+
+        let quality = self.phred.position_summary();
+        let (worst_pos, lowest_median) = quality.iter()
+            .map(|s| (s.position, s.median))
+            .fold((0, f64::NAN), |acc, x| if acc.1.is_nan() || x.1 < acc.1 { x } else { acc });
+        modules.push(ModuleResult::new(
+            "per_base_quality",
+            thresholds.per_base_quality.grade_below(lowest_median),
+            lowest_median,
+            format!("lowest median quality: Q{lowest_median} at position {}", worst_pos + 1),
+        ));
+
+        let mean_quality = self.read_stats.mean_quality.counts();
+        let mode = mean_quality.iter().enumerate().max_by_key(|(q, &c)| (c, std::cmp::Reverse(*q))).map(|(q, _)| q as f64).unwrap_or(f64::NAN);
+        modules.push(ModuleResult::new(
+            "per_sequence_quality",
+            thresholds.per_sequence_quality.grade_below(mode),
+            mode,
+            format!("most common mean read quality: Q{mode}"),
+        ));
+
+        let composition = self.composition.per_position();
+        let imbalance = composition.iter()
+            .map(|p| 100.0 * (p.a - p.t).abs().max((p.g - p.c).abs()))
+            .fold(0.0, f64::max);
+        modules.push(ModuleResult::new(
+            "per_base_content",
+            thresholds.per_base_content.grade_above(imbalance),
+            imbalance,
+            format!("largest A/T or G/C difference: {imbalance:.1}%"),
+        ));
+
+        let gc = gc_deviation(&self.composition.gc_content);
+        modules.push(ModuleResult::new(
+            "per_sequence_gc",
+            thresholds.per_sequence_gc.grade_above(gc),
+            gc,
+            format!("{gc:.1}% of reads deviate from a normal GC distribution"),
+        ));
+
+        let n_content = composition.iter().map(|p| 100.0 * p.n).fold(0.0, f64::max);
+        modules.push(ModuleResult::new(
+            "n_content",
+            thresholds.n_content.grade_above(n_content),
+            n_content,
+            format!("largest N content at any position: {n_content:.1}%"),
+        ));
+
+        // no thresholds: FastQC warns about reads of different length and fails on empty reads
+        let lengths = self.read_stats.length.counts();
+        let min_len = lengths.iter().position(|&c| c > 0).unwrap_or(0);
+        let max_len = lengths.len().saturating_sub(1);
+        let length_status = if lengths.first().is_some_and(|&c| c > 0) {
+            Status::Fail
+        } else if min_len != max_len {
+            Status::Warn
+        } else {
+            Status::Pass
+        };
+        modules.push(ModuleResult::new(
+            "sequence_length",
+            length_status,
+            min_len as f64,
+            format!("read lengths {min_len}-{max_len}"),
+        ));
+
+        let percent_unique = self.duplication.percent_unique();
+        modules.push(ModuleResult::new(
+            "duplication",
+            thresholds.duplication.grade_below(percent_unique),
+            percent_unique,
+            format!("{percent_unique:.1}% of reads remain after deduplication"),
+        ));
+
+        let top = self.overrepresented.overrepresented(DEFAULT_MIN_FRACTION).first().map(|s| s.percent).unwrap_or(0.0);
+        modules.push(ModuleResult::new(
+            "overrepresented",
+            thresholds.overrepresented.grade_above(top),
+            top,
+            format!("most common sequence: {top:.2}% of reads"),
+        ));
+
+        // cumulative, so the last position has the highest percentage
+        let (adapter, adapter_percent) = self.adapters.adapter_names()
+            .zip(self.adapters.cumulative_percent())
+            .map(|(name, percent)| (name, percent.last().copied().unwrap_or(0.0)))
+            .fold(("", 0.0), |acc, x| if x.1 > acc.1 { x } else { acc });
+        modules.push(ModuleResult::new(
+            "adapter_content",
+            thresholds.adapter_content.grade_above(adapter_percent),
+            adapter_percent,
+            if adapter.is_empty() { "no adapters found".to_string() } else { format!("{adapter}: {adapter_percent:.1}% of reads") },
+        ));
+
+        modules
+    }
+
+    /// With sampling, the stats are scaled to the estimated reads of all `files`
+    pub fn report(&self, sample: &str, files: &[String], thresholds: &QcThresholds, sampling: &Sampling) -> QcReport {
+        let mut stats = self.stats.summary(sample);
+        if !sampling.is_all() {
+            stats.scale_to(estimated_reads(files, sampling, stats.reads));
+        }
+        QcReport {
+            sample: sample.to_string(),
+            files: files.to_vec(),
+            stats,
+            percent_unique: self.duplication.percent_unique(),
+            modules: self.evaluate(thresholds),
+            sampling: (!sampling.is_all()).then(|| sampling.to_string()),
+        }
+    }
+}
+
+/// Sample name derived from a fastq filename: without directory and `.fastq.gz`/`.fq.gz` etc.
+pub fn sample_name(fastq_file: &str) -> String {
+    let name = std::path::Path::new(fastq_file).file_name().and_then(|f| f.to_str()).unwrap_or(fastq_file);
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    [".fastq", ".fq"].iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// The report as MultiQC custom content: a general statistics table with
+/// the summary statistics and the status of each module
+pub fn multiqc_json(report: &QcReport) -> serde_json::Value {
+    let mut data = serde_json::Map::new();
+    data.insert("reads".to_string(), json!(report.stats.reads));
+    data.insert("mean_length".to_string(), json!(report.stats.mean_len));
+    data.insert("percent_gc".to_string(), json!(report.stats.gc_percent));
+    data.insert("percent_q30".to_string(), json!(report.stats.q30_percent));
+    data.insert("percent_unique".to_string(), json!(report.percent_unique));
+    for m in &report.modules {
+        data.insert(m.module.clone(), json!(m.status));
+    }
+    if let Some(sampling) = &report.sampling {
+        data.insert("sampling".to_string(), json!(sampling));
+    }
+
+    json!({
+        "id": "rustfastq_qc",
+        "section_name": "rustfastq QC",
+        "plot_type": "generalstats",
+        "pconfig": [
+            {"reads": {"title": "Reads", "format": "{:,.0f}"}},
+            {"mean_length": {"title": "Length", "suffix": " bp", "format": "{:,.1f}"}},
+            {"percent_gc": {"title": "% GC", "max": 100, "min": 0, "suffix": "%"}},
+            {"percent_q30": {"title": "% >=Q30", "max": 100, "min": 0, "suffix": "%"}},
+            {"percent_unique": {"title": "% Unique", "max": 100, "min": 0, "suffix": "%"}},
+        ],
+        "data": { report.sample.clone(): data },
+    })
+}
+
+pub fn write_multiqc_json(report: &QcReport, output_json_file: &str) -> std::io::Result<()> {
+    let writer = BufWriter::new(File::create(output_json_file)?);
+    serde_json::to_writer_pretty(writer, &multiqc_json(report))?;
+    Ok(())
+}
+
+fn html_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut html = String::from("<table><tr>");
+    for h in header {
+        write!(html, "<th>{}</th>", escape(h)).unwrap();
+    }
+    html.push_str("</tr>");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            write!(html, "<td>{}</td>", escape(cell)).unwrap();
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn histogram_points(h: &Histogram) -> Vec<(f64, f64)> {
+    h.rows().map(|r| (r.bin_start, r.count as f64)).collect()
+}
+
+/// The plot/table of each module
+fn module_content(module: &str, counter: &QcCounter) -> String {
+    match module {
+        "per_base_quality" => {
+            let summary = counter.phred.position_summary();
+            let series = |name: &str, f: fn(&crate::phred_counter::PositionQualitySummary) -> f64| {
+                Series::new(name, summary.iter().map(|s| ((s.position + 1) as f64, f(s))).collect())
+            };
+            line_plot("Quality per position", "position (bp)", "Phred score", &[
+                series("mean", |s| s.mean),
+                series("median", |s| s.median),
+                series("10th percentile", |s| s.p10),
+                series("90th percentile", |s| s.p90),
+            ], Some((0.0, 42.0)))
+        }
+        "per_sequence_quality" => line_plot("Mean read quality", "mean Phred score", "reads",
+            &[Series::new("reads", histogram_points(&counter.read_stats.mean_quality))], None),
+        "per_base_content" | "n_content" => {
+            let composition = counter.composition.per_position();
+            let series = |name: &str, f: fn(&crate::composition::PositionComposition) -> f64| {
+                Series::new(name, composition.iter().map(|p| ((p.position + 1) as f64, 100.0 * f(p))).collect())
+            };
+            if module == "n_content" {
+                line_plot("N content per position", "position (bp)", "% N", &[series("N", |p| p.n)], Some((0.0, 100.0)))
+            } else {
+                line_plot("Base composition per position", "position (bp)", "% of bases",
+                    &[series("A", |p| p.a), series("C", |p| p.c), series("G", |p| p.g), series("T", |p| p.t)], Some((0.0, 100.0)))
+            }
+        }
+        "per_sequence_gc" => line_plot("GC content per read", "GC (%)", "reads",
+            &[Series::new("reads", histogram_points(&counter.composition.gc_content))], None),
+        "sequence_length" => line_plot("Read lengths", "length (bp)", "reads",
+            &[Series::new("reads", histogram_points(&counter.read_stats.length))], None),
+        "duplication" => {
+            let bars: Vec<(String, f64)> = counter.duplication.duplication_levels().into_iter()
+                .map(|l| (l.level, l.percent_of_reads))
+                .collect();
+            bar_plot("Duplication levels", "copies", "% of reads", &bars)
+        }
+        "overrepresented" => {
+            let over: Vec<OverrepresentedSequence> = counter.overrepresented.overrepresented(DEFAULT_MIN_FRACTION);
+            let rows: Vec<Vec<String>> = over.iter()
+                .map(|s| vec![s.sequence.clone(), s.count.to_string(), format!("{:.2}", s.percent), s.possible_source.clone().unwrap_or_default()])
+                .collect();
+            html_table(&["sequence", "count", "% of reads", "possible source"], &rows)
+        }
+        "adapter_content" => {
+            let series: Vec<Series> = counter.adapters.adapter_names()
+                .zip(counter.adapters.cumulative_percent())
+                .map(|(name, percent)| Series::new(name, percent.iter().enumerate().map(|(i, &p)| ((i + 1) as f64, p)).collect()))
+                .collect();
+            line_plot("Adapter content", "position (bp)", "% of reads", &series, Some((0.0, 100.0)))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Renders the self-contained HTML report
+pub fn render_html(report: &QcReport, counter: &QcCounter) -> String {
+    let mut html = String::new();
+    write!(html, r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>QC report: {sample}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; margin-bottom: 1em; }}
+th, td {{ border: 1px solid #ccc; padding: 3px 8px; text-align: left; }}
+.pass {{ background: #c8e6c9; }} .warn {{ background: #fff3b0; }} .fail {{ background: #f4b6b6; }}
+.status {{ padding: 2px 6px; border-radius: 3px; font-size: 0.8em; }}
+</style></head><body>
+<h1>QC report: {sample}</h1>
+"#, sample = escape(&report.sample)).unwrap();
+
+    if let Some(sampling) = &report.sampling {
+        write!(html, "<p><b>Note:</b> only a sample of the reads was analysed ({}), all results are estimates</p>",
+            escape(sampling)).unwrap();
+    }
+    html.push_str("<h2>Summary</h2>");
+    let s = &report.stats;
+    let summary = vec![
+        vec!["files".to_string(), report.files.join(", ")],
+        vec!["reads".to_string(), s.reads.to_string()],
+        vec!["bases".to_string(), s.bases.to_string()],
+        vec!["read length".to_string(), format!("{}-{} (mean {:.1})", s.min_len, s.max_len, s.mean_len)],
+        vec!["GC".to_string(), format!("{:.2}%", s.gc_percent)],
+        vec![">=Q30".to_string(), format!("{:.2}%", s.q30_percent)],
+        vec!["unique reads".to_string(), format!("{:.2}%", report.percent_unique)],
+    ];
+    html.push_str(&html_table(&["", ""], &summary));
+
+    html.push_str("<table><tr><th>module</th><th>status</th><th></th></tr>");
+    for m in &report.modules {
+        write!(html, r##"<tr><td><a href="#{0}">{0}</a></td><td class="{1}">{1}</td><td>{2}</td></tr>"##,
+            m.module, m.status, escape(&m.message)).unwrap();
+    }
+    html.push_str("</table>");
+
+    for m in &report.modules {
+        write!(html, r#"<h2 id="{0}">{0} <span class="status {1}">{1}</span></h2><p>{2}</p>"#,
+            m.module, m.status, escape(&m.message)).unwrap();
+        html.push_str(&module_content(&m.module, counter));
+    }
+
+    html.push_str("<h2>Sample indices</h2>");
+    let indices: Vec<Vec<String>> = counter.indices.top(TOP_INDICES).into_iter()
+        .map(|i| vec![i.index, i.count.to_string(), format!("{:.2}", i.percent)])
+        .collect();
+    if indices.is_empty() {
+        html.push_str("<p>no indices found in the read headers</p>");
+    } else {
+        html.push_str(&html_table(&["index", "count", "% of reads"], &indices));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+pub fn write_html(report: &QcReport, counter: &QcCounter, output_file: &str) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(output_file)?);
+    writer.write_all(render_html(report, counter).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{gc_deviation, multiqc_json, sample_name, QcCounter, QcThresholds, Status, Threshold};
+    use crate::accumulator::FastqAccumulator;
+    use crate::histogram::Histogram;
+    use crate::io::FastqEntry;
+    use crate::sampling::Sampling;
+    use crate::test_files::write_fastq_gz;
+
+    fn entry(header: &str, seq: &str, phred: &str) -> FastqEntry {
+        FastqEntry { header: header.to_string(), seq: seq.to_string(), phred: phred.to_string() }
+    }
+
+    #[test]
+    fn test_threshold() {
+        let t = Threshold::new(10.0, 20.0);
+        assert_eq!(t.grade_above(5.0), Status::Pass);
+        assert_eq!(t.grade_above(15.0), Status::Warn);
+        assert_eq!(t.grade_above(25.0), Status::Fail);
+        let t = Threshold::new(25.0, 20.0);
+        assert_eq!(t.grade_below(30.0), Status::Pass);
+        assert_eq!(t.grade_below(22.0), Status::Warn);
+        assert_eq!(t.grade_below(10.0), Status::Fail);
+    }
+
+    #[test]
+    fn test_thresholds_json() {
+        // missing modules keep the defaults
+        let t: QcThresholds = serde_json::from_str(r#"{"duplication": {"warn": 40, "fail": 20}}"#).unwrap();
+        assert_eq!(t.duplication, Threshold::new(40.0, 20.0));
+        assert_eq!(t.n_content, QcThresholds::default().n_content);
+    }
+
+    #[test]
+    fn test_gc_deviation() {
+        let mut h = Histogram::new(1.0);
+        h.add_n(50.0, 100);
+        assert_eq!(gc_deviation(&h), 0.0);
+        // two peaks far apart don't look normal
+        h.add_n(10.0, 100);
+        assert!(gc_deviation(&h) > 50.0);
+    }
+
+    #[test]
+    fn test_qc() {
+        let mut c = QcCounter::default();
+        for i in 0..100 {
+            let seq = if i % 2 == 0 { "ACGTACGTAC" } else { "GGCCAATTGC" };
+            c.add(&entry("@A:1:FC:1:1101:1:1 1:N:0:ACGT+TTAA", seq, "IIIIIIIIII"));
+        }
+        let report = c.report("sample", &["sample.fastq.gz".to_string()], &QcThresholds::default(), &Sampling::All);
+        assert_eq!(report.stats.reads, 100);
+
+        let status = |module: &str| report.modules.iter().find(|m| m.module == module).unwrap().status;
+        assert_eq!(status("per_base_quality"), Status::Pass);
+        assert_eq!(status("sequence_length"), Status::Pass);
+        // only two distinct sequences
+        assert_eq!(status("duplication"), Status::Fail);
+        assert_eq!(status("overrepresented"), Status::Fail);
+        assert_eq!(report.status(), Status::Fail);
+
+        assert_eq!(c.indices.top(5)[0].index, "ACGT+TTAA");
+        assert_eq!(c.indices.top(5)[0].percent, 100.0);
+
+        let mqc = multiqc_json(&report);
+        assert_eq!(mqc["data"]["sample"]["reads"], 100);
+        assert_eq!(mqc["data"]["sample"]["duplication"], "fail");
+
+        let html = super::render_html(&report, &c);
+        assert!(html.contains("<svg"));
+        assert!(html.contains("ACGT+TTAA"));
+        assert!(!html.contains("estimates"));
+
+        // scaled to the reads of the whole file (fewer than sampled here, so exact)
+        let fname = "/tmp/rustfastq_qc_sampled.fastq.gz";
+        write_fastq_gz(fname, &[("r1", "ACGTACGTAC", "IIIIIIIIII"); 50]);
+        let sampled = c.report("sample", &[fname.to_string()], &QcThresholds::default(), &Sampling::First(100));
+        assert_eq!(sampled.sampling.as_deref(), Some("first:100"));
+        assert_eq!((sampled.stats.reads, sampled.stats.bases, sampled.stats.sampled), (50, 500, true));
+        assert!(super::render_html(&sampled, &c).contains("all results are estimates"));
+    }
+
+    #[test]
+    fn test_sample_name() {
+        assert_eq!(sample_name("/data/run1/S1_R1_001.fastq.gz"), "S1_R1_001");
+        assert_eq!(sample_name("x.fq"), "x");
+        assert_eq!(sample_name("reads.txt"), "reads.txt");
+    }
+}
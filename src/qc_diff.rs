@@ -0,0 +1,258 @@
+//! Comparing the QC profiles of two runs/files, e.g. a suspicious run against a known-good baseline.
+//!
+//! A [`QcProfile`] holds the per-cycle quality and composition and the length distribution;
+//! it's written by `qc --profile`, or computed directly from fastq files.
+//! Per cycle, the quality and composition differences are compared against [`DiffThresholds`];
+//! the length distributions are compared via the Kolmogorov-Smirnov distance
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::accumulator::{accumulate_files_sampled, merge_all};
+use crate::composition::{BaseComposition, PositionComposition};
+use crate::histogram::Histogram;
+use crate::phred_counter::{PhredCounter, PositionQualitySummary};
+use crate::qc::QcCounter;
+use crate::read_stats::ReadStatsCounter;
+use crate::sampling::Sampling;
+
+/// Default for [`DiffThresholds::max_quality_delta`]
+pub const DEFAULT_MAX_QUALITY_DELTA: f64 = 2.0;
+/// Default for [`DiffThresholds::max_composition_shift`]
+pub const DEFAULT_MAX_COMPOSITION_SHIFT: f64 = 5.0;
+
+/// Per-cycle quality/composition and the read length distribution of a sample
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QcProfile {
+    pub quality: Vec<PositionQualitySummary>,
+    #[serde(default)]
+    pub composition: Vec<PositionComposition>,
+    #[serde(default)]
+    pub length: Option<Histogram>,
+}
+
+impl QcProfile {
+    pub fn from_counters(phred: &PhredCounter, composition: &BaseComposition, read_stats: &ReadStatsCounter) -> Self {
+        QcProfile {
+            quality: phred.position_summary(),
+            composition: composition.per_position(),
+            length: Some(read_stats.length.clone()),
+        }
+    }
+
+    pub fn from_qc(counter: &QcCounter) -> Self {
+        Self::from_counters(&counter.phred, &counter.composition, &counter.read_stats)
+    }
+
+    /// Profile of the (sampled) reads in the fastq files
+    pub fn from_fastq(fastq_files: &[String], sampling: &Sampling) -> Self {
+        let (phred, (composition, read_stats)): (PhredCounter, (BaseComposition, ReadStatsCounter)) =
+            merge_all(accumulate_files_sampled(fastq_files, sampling));
+        Self::from_counters(&phred, &composition, &read_stats)
+    }
+
+    /// Reads a profile written by `qc --profile`, or the per-position summary of `phred --summary-json`
+    /// (which only has the quality)
+    pub fn from_json(fname: &str) -> std::io::Result<Self> {
+        let value: serde_json::Value = serde_json::from_reader(BufReader::new(File::open(fname)?))?;
+        let profile = if value.is_array() {
+            QcProfile { quality: serde_json::from_value(value)?, composition: Vec::new(), length: None }
+        } else {
+            serde_json::from_value(value)?
+        };
+        Ok(profile)
+    }
+
+    /// From a JSON profile (`.json`) or a fastq file (reads sampled as given)
+    pub fn load(fname: &str, sampling: &Sampling) -> Self {
+        if fname.ends_with(".json") {
+            Self::from_json(fname).unwrap()
+        } else {
+            Self::from_fastq(&[fname.to_string()], sampling)
+        }
+    }
+
+    pub fn write_json(&self, output_json_file: &str) -> std::io::Result<()> {
+        let writer = BufWriter::new(File::create(output_json_file)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+/// When a cycle counts as deviating
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffThresholds {
+    /// largest tolerated difference of the mean quality
+    pub max_quality_delta: f64,
+    /// largest tolerated difference of any base's percentage
+    pub max_composition_shift: f64,
+}
+
+impl Default for DiffThresholds {
+    fn default() -> Self {
+        DiffThresholds {
+            max_quality_delta: DEFAULT_MAX_QUALITY_DELTA,
+            max_composition_shift: DEFAULT_MAX_COMPOSITION_SHIFT,
+        }
+    }
+}
+
+/// Differences at a single cycle, `b - a`. NaN where a profile lacks the position/data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CycleDiff {
+    /// 0-based position in the read
+    pub position: usize,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    pub mean_delta: f64,
+    pub median_delta: f64,
+    pub frac_q30_delta: f64,
+    /// largest difference in the percentage of A, C, G, T or N
+    pub composition_shift: f64,
+    pub flagged: bool,
+}
+
+/// Comparison of two profiles
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileDiff {
+    pub cycles: Vec<CycleDiff>,
+    pub mean_length_a: f64,
+    pub mean_length_b: f64,
+    /// Kolmogorov-Smirnov distance of the length distributions (NaN if a profile has none)
+    pub length_distance: f64,
+}
+
+impl ProfileDiff {
+    pub fn flagged_cycles(&self) -> impl Iterator<Item = &CycleDiff> {
+        self.cycles.iter().filter(|c| c.flagged)
+    }
+}
+
+/// Largest difference between the cumulative distributions of two histograms (with the same bins)
+pub fn ks_distance(a: &Histogram, b: &Histogram) -> f64 {
+    let (total_a, total_b) = (a.total() as f64, b.total() as f64);
+    let n_bins = a.counts().len().max(b.counts().len());
+    let (mut cdf_a, mut cdf_b, mut distance) = (0.0, 0.0, 0.0_f64);
+    for i in 0..n_bins {
+        cdf_a += a.counts().get(i).copied().unwrap_or(0) as f64 / total_a;
+        cdf_b += b.counts().get(i).copied().unwrap_or(0) as f64 / total_b;
+        distance = distance.max((cdf_a - cdf_b).abs());
+    }
+    distance
+}
+
+fn composition_shift(a: &PositionComposition, b: &PositionComposition) -> f64 {
+    [(a.a, b.a), (a.c, b.c), (a.g, b.g), (a.t, b.t), (a.n, b.n)].iter()
+        .map(|(x, y)| 100.0 * (y - x).abs())
+        .fold(0.0, f64::max)
+}
+
+/// Compares profile `b` against the baseline `a`
+pub fn diff_profiles(a: &QcProfile, b: &QcProfile, thresholds: &DiffThresholds) -> ProfileDiff {
+    let n_positions = a.quality.len().max(b.quality.len());
+    let cycles = (0..n_positions)
+        .map(|position| {
+            let qa = a.quality.get(position);
+            let qb = b.quality.get(position);
+            let delta = |f: fn(&PositionQualitySummary) -> f64| match (qa, qb) {
+                (Some(x), Some(y)) => f(y) - f(x),
+                _ => f64::NAN,
+            };
+            let shift = match (a.composition.get(position), b.composition.get(position)) {
+                (Some(x), Some(y)) => composition_shift(x, y),
+                _ => f64::NAN,
+            };
+            let mean_delta = delta(|s| s.mean);
+            // NaN never exceeds a threshold
+            let flagged = mean_delta.abs() > thresholds.max_quality_delta || shift > thresholds.max_composition_shift;
+            CycleDiff {
+                position,
+                mean_a: qa.map_or(f64::NAN, |s| s.mean),
+                mean_b: qb.map_or(f64::NAN, |s| s.mean),
+                mean_delta,
+                median_delta: delta(|s| s.median),
+                frac_q30_delta: delta(|s| s.frac_q30),
+                composition_shift: shift,
+                flagged,
+            }
+        })
+        .collect();
+
+    let (mean_length_a, mean_length_b, length_distance) = match (&a.length, &b.length) {
+        (Some(la), Some(lb)) => (la.mean(), lb.mean(), ks_distance(la, lb)),
+        (la, lb) => (
+            la.as_ref().map_or(f64::NAN, |h| h.mean()),
+            lb.as_ref().map_or(f64::NAN, |h| h.mean()),
+            f64::NAN,
+        ),
+    };
+    ProfileDiff { cycles, mean_length_a, mean_length_b, length_distance }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{diff_profiles, ks_distance, DiffThresholds, QcProfile};
+    use crate::accumulator::FastqAccumulator;
+    use crate::composition::BaseComposition;
+    use crate::histogram::Histogram;
+    use crate::io::FastqEntry;
+    use crate::phred_counter::PhredCounter;
+    use crate::read_stats::ReadStatsCounter;
+
+    fn profile(reads: &[(&str, &str)]) -> QcProfile {
+        let (mut phred, mut composition, mut read_stats) = (PhredCounter::new(), BaseComposition::new(), ReadStatsCounter::new());
+        for (seq, qual) in reads {
+            let fq = FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: qual.to_string() };
+            phred.add(&fq);
+            composition.add(&fq);
+            read_stats.add(&fq);
+        }
+        QcProfile::from_counters(&phred, &composition, &read_stats)
+    }
+
+    #[test]
+    fn test_ks_distance() {
+        let mut a = Histogram::new(1.0);
+        a.add_n(10.0, 2);
+        let mut b = Histogram::new(1.0);
+        b.add(10.0);
+        b.add(12.0);
+        assert_eq!(ks_distance(&a, &a), 0.0);
+        assert_eq!(ks_distance(&a, &b), 0.5);
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = profile(&[("ACGT", "IIII"), ("ACGT", "IIII")]);
+        // quality drop at the last cycle, T -> A at the third
+        let b = profile(&[("ACAT", "III5"), ("ACGT", "III5")]);
+        let diff = diff_profiles(&a, &b, &DiffThresholds::default());
+
+        assert_eq!(diff.cycles.len(), 4);
+        assert!(!diff.cycles[0].flagged);
+        assert!(diff.cycles[2].flagged);
+        assert_eq!(diff.cycles[2].composition_shift, 50.0);
+        assert_eq!(diff.cycles[3].mean_delta, -20.0);
+        assert_eq!(diff.flagged_cycles().count(), 2);
+        assert_eq!(diff.length_distance, 0.0);
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let a = profile(&[("ACGT", "IIII")]);
+        let fname = "/tmp/rustfastq_profile.json";
+        a.write_json(fname).unwrap();
+        assert_eq!(QcProfile::from_json(fname).unwrap(), a);
+
+        // phred --summary-json: only the quality
+        crate::phred_counter::write_summary_json(&a.quality, fname).unwrap();
+        let q = QcProfile::from_json(fname).unwrap();
+        assert_eq!(q.quality, a.quality);
+        assert!(q.length.is_none());
+
+        let diff = diff_profiles(&a, &q, &DiffThresholds::default());
+        assert!(diff.cycles[0].composition_shift.is_nan());
+        assert!(diff.length_distance.is_nan());
+    }
+}
@@ -0,0 +1,495 @@
+//! Quality filtering of reads by a combination of [`QualityCriterion`]s.
+//! A read passes if it passes all criteria; the [`FilterSummary`] counts how many
+//! reads failed each criterion (a read can fail several).
+//!
+//! Paired-end reads are filtered together ([`filter_fastq_paired`]) to keep R1/R2 in sync;
+//! the [`PairRule`] decides when a pair is kept.
+//!
+//! Dropped reads can be kept in [`RejectedReads`], tagged with the first criterion they failed
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::complexity::{dust_score, entropy, parse_poly_x_tail, poly_x_tail_start};
+use crate::io::{fastq_list_iter, get_bgzf_writer, paired_fastq_list_iter, FastqEntry, PHRED_LOOKUP};
+use crate::read_stats::{expected_errors, mean_quality};
+use crate::rejected::{RejectedReads, MATE_REJECTED};
+
+/// A single requirement a read has to meet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QualityCriterion {
+    /// at most this many expected errors, i.e. the sum of the error probabilities (usearch `-fastq_maxee`)
+    MaxExpectedErrors(f64),
+    /// mean Phred score at least this
+    MinMeanQuality(f64),
+    /// every base at least this quality
+    MinBaseQuality(u8),
+    /// at most `fraction` of the bases below `quality`
+    MaxFractionBelow { quality: u8, fraction: f64 },
+    /// mean error probability (averaged over the bases) below this;
+    /// what `qcfilter -q` always did, kept for compatibility
+    MaxMeanErrorProbability(f64),
+    /// low complexity: DUST score at most this (see [`crate::complexity`])
+    MaxDust(f64),
+    /// low complexity: trinucleotide entropy at least this
+    MinEntropy(f64),
+    /// no 3' tail of `base` of at least `min_length` (see [`poly_x_tail_start`])
+    NoPolyX { base: char, min_length: usize },
+}
+
+fn phred_scores(phred: &str) -> impl Iterator<Item = u8> + '_ {
+    phred.bytes().map(|c| c - 33)
+}
+
+impl QualityCriterion {
+    pub fn passes(&self, fq: &FastqEntry) -> bool {
+        match *self {
+            QualityCriterion::MaxExpectedErrors(max_ee) => expected_errors(&fq.phred) <= max_ee,
+            // empty reads have a NaN mean, and fail
+            QualityCriterion::MinMeanQuality(min_q) => mean_quality(&fq.phred) >= min_q,
+            QualityCriterion::MinBaseQuality(min_q) => phred_scores(&fq.phred).all(|q| q >= min_q),
+            QualityCriterion::MaxFractionBelow { quality, fraction } => {
+                let below = phred_scores(&fq.phred).filter(|&q| q < quality).count();
+                below as f64 <= fraction * fq.phred.len() as f64
+            }
+            QualityCriterion::MaxMeanErrorProbability(max_p) => {
+                let probs: f32 = fq.phred.chars().map(|c| PHRED_LOOKUP.get_prob(c)).sum();
+                ((probs / fq.phred.len() as f32) as f64) < max_p
+            }
+            QualityCriterion::MaxDust(max_dust) => dust_score(&fq.seq) <= max_dust,
+            QualityCriterion::MinEntropy(min_entropy) => entropy(&fq.seq) >= min_entropy,
+            QualityCriterion::NoPolyX { base, min_length } => poly_x_tail_start(&fq.seq, base, min_length) == fq.seq.len(),
+        }
+    }
+}
+
+/// Short description, as in the summary
+impl fmt::Display for QualityCriterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QualityCriterion::MaxExpectedErrors(x) => write!(f, "maxee<={x}"),
+            QualityCriterion::MinMeanQuality(x) => write!(f, "mean_q>={x}"),
+            QualityCriterion::MinBaseQuality(x) => write!(f, "base_q>={x}"),
+            QualityCriterion::MaxFractionBelow { quality, fraction } => write!(f, "frac_below_q{quality}<={fraction}"),
+            QualityCriterion::MaxMeanErrorProbability(x) => write!(f, "mean_error_prob<{x}"),
+            QualityCriterion::MaxDust(x) => write!(f, "dust<={x}"),
+            QualityCriterion::MinEntropy(x) => write!(f, "entropy>={x}"),
+            QualityCriterion::NoPolyX { base, min_length } => write!(f, "no_poly{base}>={min_length}"),
+        }
+    }
+}
+
+/// Parses `QUALITY:FRACTION` into [`QualityCriterion::MaxFractionBelow`], e.g. `20:0.1`
+pub fn parse_fraction_below(s: &str) -> Result<QualityCriterion, String> {
+    let (quality, fraction) = s.split_once(':').ok_or(format!("expected QUALITY:FRACTION, got {s}"))?;
+    Ok(QualityCriterion::MaxFractionBelow {
+        quality: quality.parse().map_err(|_| format!("invalid quality in {s}"))?,
+        fraction: fraction.parse().map_err(|_| format!("invalid fraction in {s}"))?,
+    })
+}
+
+/// Parses `BASE[:MIN_LENGTH]` into [`QualityCriterion::NoPolyX`], e.g. `G` or `A:15`
+pub fn parse_poly_x(s: &str) -> Result<QualityCriterion, String> {
+    let (base, min_length) = parse_poly_x_tail(s)?;
+    Ok(QualityCriterion::NoPolyX { base, min_length })
+}
+
+/// A read passes if it passes all criteria
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityFilter {
+    pub criteria: Vec<QualityCriterion>,
+}
+
+impl QualityFilter {
+    pub fn new(criteria: Vec<QualityCriterion>) -> Self {
+        QualityFilter { criteria }
+    }
+
+    /// For each criterion, whether the read fails it
+    pub fn failures(&self, fq: &FastqEntry) -> Vec<bool> {
+        self.criteria.iter().map(|c| !c.passes(fq)).collect()
+    }
+
+    pub fn passes(&self, fq: &FastqEntry) -> bool {
+        self.criteria.iter().all(|c| c.passes(fq))
+    }
+
+    /// Name of the first criterion failed, from [`QualityFilter::failures`]
+    pub fn rejection_reason(&self, failures: &[bool]) -> Option<String> {
+        self.criteria.iter().zip(failures).find(|(_, &failed)| failed).map(|(c, _)| c.to_string())
+    }
+}
+
+/// Number of reads passing the filter and failing each criterion
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterSummary {
+    pub total: u64,
+    pub passed: u64,
+    criteria: Vec<String>,
+    failed: Vec<u64>,
+}
+
+/// One line of the [`FilterSummary`] table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CriterionSummary {
+    pub criterion: String,
+    pub failed: u64,
+    pub percent: f64,
+}
+
+impl FilterSummary {
+    pub fn new(filter: &QualityFilter) -> Self {
+        FilterSummary {
+            total: 0,
+            passed: 0,
+            criteria: filter.criteria.iter().map(|c| c.to_string()).collect(),
+            failed: vec![0; filter.criteria.len()],
+        }
+    }
+
+    /// Counts a read with the given failures (see [`QualityFilter::failures`]);
+    /// returns whether it passed
+    pub fn add(&mut self, failures: &[bool]) -> bool {
+        self.total += 1;
+        for (count, &failed) in self.failed.iter_mut().zip(failures) {
+            if failed {
+                *count += 1;
+            }
+        }
+        let passed = !failures.iter().any(|&f| f);
+        if passed {
+            self.passed += 1;
+        }
+        passed
+    }
+
+    /// Failures per criterion, plus a final `passed` row
+    pub fn rows(&self) -> Vec<CriterionSummary> {
+        let percent = |x: u64| 100.0 * x as f64 / self.total as f64;
+        self.criteria.iter().zip(&self.failed)
+            .map(|(criterion, &failed)| CriterionSummary { criterion: criterion.clone(), failed, percent: percent(failed) })
+            .chain(std::iter::once(CriterionSummary {
+                criterion: "passed".to_string(),
+                failed: self.passed,
+                percent: percent(self.passed),
+            }))
+            .collect()
+    }
+}
+
+impl fmt::Display for FilterSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}/{}({}) reads passed QC", self.passed, self.total, self.passed as f32 / self.total as f32)?;
+        for (criterion, failed) in self.criteria.iter().zip(&self.failed) {
+            writeln!(f, "  failed {criterion}: {failed}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes all reads passing the filter to `outname`, the others to `rejected`
+pub fn filter_fastq(fastq_list: &[String], outname: &str, filter: &QualityFilter, rejected: &mut RejectedReads) -> FilterSummary {
+    let mut writer = get_bgzf_writer(outname);
+    let mut summary = FilterSummary::new(filter);
+    for fq in fastq_list_iter(fastq_list) {
+        let failures = filter.failures(&fq);
+        if summary.add(&failures) {
+            write!(writer, "{}", fq.to_string()).unwrap();
+        } else {
+            rejected.add(&fq, &filter.rejection_reason(&failures).unwrap());
+        }
+    }
+    summary
+}
+
+/// When a pair of reads passes the filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PairRule {
+    /// both reads have to pass; a read whose mate failed is a singleton
+    #[default]
+    Both,
+    /// keep the pair if either read passes
+    Either,
+}
+
+impl fmt::Display for PairRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PairRule::Both => write!(f, "both"),
+            PairRule::Either => write!(f, "either"),
+        }
+    }
+}
+
+impl FromStr for PairRule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "both" => Ok(PairRule::Both),
+            "either" => Ok(PairRule::Either),
+            _ => Err(format!("expected both or either, got {s}")),
+        }
+    }
+}
+
+/// Output files of [`filter_fastq_paired`]; the index reads are only written if given as input
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PairedOutputs {
+    pub r1: String,
+    pub r2: String,
+    pub i1: Option<String>,
+    pub i2: Option<String>,
+    /// R1 reads that passed while their R2 failed (only with [`PairRule::Both`]); dropped if `None`
+    pub singletons_r1: Option<String>,
+    pub singletons_r2: Option<String>,
+}
+
+/// Per-read summaries of R1 and R2, plus the pairs
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairedFilterSummary {
+    pub rule: PairRule,
+    pub r1: FilterSummary,
+    pub r2: FilterSummary,
+    pub pairs_passed: u64,
+    pub singletons_r1: u64,
+    pub singletons_r2: u64,
+}
+
+impl PairedFilterSummary {
+    pub fn new(filter: &QualityFilter, rule: PairRule) -> Self {
+        PairedFilterSummary {
+            rule,
+            r1: FilterSummary::new(filter),
+            r2: FilterSummary::new(filter),
+            pairs_passed: 0,
+            singletons_r1: 0,
+            singletons_r2: 0,
+        }
+    }
+
+    /// Failures per criterion of R1 and R2 (prefixed `r1:`/`r2:`), then the passed pairs and singletons.
+    /// Percentages are relative to the number of pairs
+    pub fn rows(&self) -> Vec<CriterionSummary> {
+        let percent = |x: u64| 100.0 * x as f64 / self.r1.total as f64;
+        let mate_rows = |mate: &str, summary: &FilterSummary| -> Vec<CriterionSummary> {
+            summary.rows().into_iter()
+                .map(|row| CriterionSummary { criterion: format!("{mate}:{}", row.criterion), ..row })
+                .collect()
+        };
+        let mut rows = mate_rows("r1", &self.r1);
+        rows.extend(mate_rows("r2", &self.r2));
+        for (criterion, count) in [("pairs_passed", self.pairs_passed), ("singletons_r1", self.singletons_r1), ("singletons_r2", self.singletons_r2)] {
+            rows.push(CriterionSummary { criterion: criterion.to_string(), failed: count, percent: percent(count) });
+        }
+        rows
+    }
+}
+
+impl fmt::Display for PairedFilterSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "R1: {}", self.r1)?;
+        write!(f, "R2: {}", self.r2)?;
+        let total = self.r1.total;
+        writeln!(f, "{}/{}({}) pairs passed QC ({} pass)", self.pairs_passed, total, self.pairs_passed as f32 / total as f32, self.rule)?;
+        writeln!(f, "singletons: {} R1, {} R2", self.singletons_r1, self.singletons_r2)
+    }
+}
+
+/// Reads of the index files, if there are any
+fn optional_iter(fastq_list: &[String]) -> Option<impl Iterator<Item = FastqEntry> + '_> {
+    (!fastq_list.is_empty()).then(|| fastq_list_iter(fastq_list))
+}
+
+/// Filters R1/R2 (and optionally I1/I2, which are kept/dropped with their pair) together.
+/// Dropped reads go to `rejected` (R1 and R2), except singletons if their output is given.
+/// Panics if the files don't have the same number of reads, or if index files have no output
+#[allow(clippy::too_many_arguments)]
+pub fn filter_fastq_paired(
+    r1_list: &[String],
+    r2_list: &[String],
+    i1_list: &[String],
+    i2_list: &[String],
+    outputs: &PairedOutputs,
+    filter: &QualityFilter,
+    rule: PairRule,
+    rejected: &mut [RejectedReads],
+) -> PairedFilterSummary {
+    let mut i1_iter = optional_iter(i1_list);
+    let mut i2_iter = optional_iter(i2_list);
+
+    let mut r1_writer = get_bgzf_writer(&outputs.r1);
+    let mut r2_writer = get_bgzf_writer(&outputs.r2);
+    let mut i1_writer = i1_iter.as_ref().map(|_| get_bgzf_writer(outputs.i1.as_ref().expect("I1 given, but no I1 output")));
+    let mut i2_writer = i2_iter.as_ref().map(|_| get_bgzf_writer(outputs.i2.as_ref().expect("I2 given, but no I2 output")));
+    let mut singletons_r1 = outputs.singletons_r1.as_ref().map(|f| get_bgzf_writer(f));
+    let mut singletons_r2 = outputs.singletons_r2.as_ref().map(|f| get_bgzf_writer(f));
+
+    let mut summary = PairedFilterSummary::new(filter, rule);
+    for (fq1, fq2) in paired_fastq_list_iter(r1_list, r2_list) {
+        let ix1 = i1_iter.as_mut().map(|it| it.next().expect("I1 has fewer reads than R1"));
+        let ix2 = i2_iter.as_mut().map(|it| it.next().expect("I2 has fewer reads than R1"));
+
+        let failures1 = filter.failures(&fq1);
+        let failures2 = filter.failures(&fq2);
+        let pass1 = summary.r1.add(&failures1);
+        let pass2 = summary.r2.add(&failures2);
+        let keep_pair = match rule {
+            PairRule::Both => pass1 && pass2,
+            PairRule::Either => pass1 || pass2,
+        };
+
+        if keep_pair {
+            summary.pairs_passed += 1;
+            write!(r1_writer, "{}", fq1.to_string()).unwrap();
+            write!(r2_writer, "{}", fq2.to_string()).unwrap();
+            for (writer, ix) in [(&mut i1_writer, ix1), (&mut i2_writer, ix2)] {
+                if let (Some(writer), Some(ix)) = (writer, ix) {
+                    write!(writer, "{}", ix.to_string()).unwrap();
+                }
+            }
+        } else {
+            let mates = [
+                (&fq1, &failures1, &mut singletons_r1, &mut summary.singletons_r1),
+                (&fq2, &failures2, &mut singletons_r2, &mut summary.singletons_r2),
+            ];
+            for ((fq, failures, singletons, count), rejected) in mates.into_iter().zip(rejected.iter_mut()) {
+                match filter.rejection_reason(failures) {
+                    Some(reason) => rejected.add(fq, &reason),
+                    // passed, but the mate didn't
+                    None => {
+                        *count += 1;
+                        match singletons {
+                            Some(writer) => write!(writer, "{}", fq.to_string()).unwrap(),
+                            None => rejected.add(fq, MATE_REJECTED),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    assert!(i1_iter.is_none_or(|mut it| it.next().is_none()), "I1 has more reads than R1");
+    assert!(i2_iter.is_none_or(|mut it| it.next().is_none()), "I2 has more reads than R1");
+    summary
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{filter_fastq, filter_fastq_paired, parse_fraction_below, FilterSummary, PairRule, PairedOutputs, QualityCriterion, QualityFilter};
+    use crate::io::{FastIterator, FastqEntry};
+    use crate::rejected::RejectedReads;
+    use crate::test_files::write_fastq_gz;
+
+    fn entry(phred: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: "A".repeat(phred.len()), phred: phred.to_string() }
+    }
+
+    #[test]
+    fn test_criteria() {
+        // Q40 x 9, Q10 x 1: 0.1009 expected errors, mean Q37
+        let fq = entry("IIIIIIIII+");
+        assert!(QualityCriterion::MaxExpectedErrors(0.2).passes(&fq));
+        assert!(!QualityCriterion::MaxExpectedErrors(0.1).passes(&fq));
+        assert!(QualityCriterion::MinMeanQuality(37.0).passes(&fq));
+        assert!(!QualityCriterion::MinMeanQuality(37.5).passes(&fq));
+        assert!(QualityCriterion::MinBaseQuality(10).passes(&fq));
+        assert!(!QualityCriterion::MinBaseQuality(11).passes(&fq));
+        assert!(QualityCriterion::MaxFractionBelow { quality: 20, fraction: 0.1 }.passes(&fq));
+        assert!(!QualityCriterion::MaxFractionBelow { quality: 20, fraction: 0.05 }.passes(&fq));
+        // mean error probability 0.01009
+        assert!(QualityCriterion::MaxMeanErrorProbability(0.011).passes(&fq));
+        assert!(!QualityCriterion::MaxMeanErrorProbability(0.01).passes(&fq));
+
+        let low_complexity = FastqEntry { header: "@r".to_string(), seq: "CA".repeat(20) + &"G".repeat(12), phred: "I".repeat(52) };
+        assert!(!QualityCriterion::MaxDust(7.0).passes(&low_complexity));
+        assert!(!QualityCriterion::MinEntropy(70.0).passes(&low_complexity));
+        assert!(!QualityCriterion::NoPolyX { base: 'G', min_length: 10 }.passes(&low_complexity));
+        assert!(QualityCriterion::NoPolyX { base: 'A', min_length: 10 }.passes(&low_complexity));
+    }
+
+    #[test]
+    fn test_parse_fraction_below() {
+        assert_eq!(parse_fraction_below("20:0.1").unwrap(), QualityCriterion::MaxFractionBelow { quality: 20, fraction: 0.1 });
+        assert!(parse_fraction_below("20").is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let filter = QualityFilter::new(vec![QualityCriterion::MinMeanQuality(30.0), QualityCriterion::MinBaseQuality(20)]);
+        let mut summary = FilterSummary::new(&filter);
+        for phred in ["IIII", "III+", "++++", "5555"] {
+            summary.add(&filter.failures(&entry(phred)));
+        }
+        assert_eq!((summary.total, summary.passed), (4, 1));
+        let rows = summary.rows();
+        assert_eq!(rows[0].criterion, "mean_q>=30");
+        assert_eq!(rows[0].failed, 2);
+        assert_eq!(rows[1].failed, 2);
+        assert_eq!(rows[2].criterion, "passed");
+        assert_eq!(rows[2].percent, 25.0);
+    }
+
+    #[test]
+    fn test_filter_fastq() {
+        let fname = "/tmp/rustfastq_qcfilter_in.fastq.gz";
+        let out = "/tmp/rustfastq_qcfilter_out.fastq.gz";
+        write_fastq_gz(fname, &[("good", "ACGT", "IIII"), ("bad", "ACGT", "I!!!")]);
+        let failed = "/tmp/rustfastq_qcfilter_failed.fastq.gz";
+        let filter = QualityFilter::new(vec![QualityCriterion::MinBaseQuality(10), QualityCriterion::MaxExpectedErrors(1.0)]);
+        let mut rejected = RejectedReads::new(Some(failed));
+        let summary = filter_fastq(&[fname.to_string()], out, &filter, &mut rejected);
+        assert_eq!(summary.passed, 1);
+        assert_eq!(rejected.total(), 1);
+        drop(rejected);
+        let kept: Vec<String> = FastIterator::new(out).map(|fq| fq.header).collect();
+        assert_eq!(kept, vec!["@good"]);
+        let failed: Vec<String> = FastIterator::new(failed).map(|fq| fq.header).collect();
+        assert_eq!(failed, vec!["@bad reject=base_q>=10"]);
+    }
+
+    #[test]
+    fn test_filter_fastq_paired() {
+        let dir = "/tmp/rustfastq_qcfilter_paired";
+        std::fs::create_dir_all(dir).unwrap();
+        let f = |name: &str| format!("{dir}/{name}.fastq.gz");
+        write_fastq_gz(&f("r1"), &[("a", "ACGT", "IIII"), ("b", "ACGT", "IIII"), ("c", "ACGT", "!!!!"), ("d", "ACGT", "!!!!")]);
+        write_fastq_gz(&f("r2"), &[("a", "ACGT", "IIII"), ("b", "ACGT", "!!!!"), ("c", "ACGT", "IIII"), ("d", "ACGT", "!!!!")]);
+        write_fastq_gz(&f("i1"), &[("a", "AA", "II"), ("b", "CC", "II"), ("c", "GG", "II"), ("d", "TT", "II")]);
+        let filter = QualityFilter::new(vec![QualityCriterion::MaxExpectedErrors(1.0)]);
+        let names = |fname: &str| -> Vec<String> { FastIterator::new(fname).map(|fq| fq.header).collect() };
+
+        let outputs = PairedOutputs {
+            r1: f("out_r1"),
+            r2: f("out_r2"),
+            i1: Some(f("out_i1")),
+            i2: None,
+            singletons_r1: Some(f("single_r1")),
+            singletons_r2: Some(f("single_r2")),
+        };
+        let mut rejected = [RejectedReads::new(Some(&f("failed_r1"))), RejectedReads::new(Some(&f("failed_r2")))];
+        let summary = filter_fastq_paired(&[f("r1")], &[f("r2")], &[f("i1")], &[], &outputs, &filter, PairRule::Both, &mut rejected);
+        assert_eq!((summary.pairs_passed, summary.singletons_r1, summary.singletons_r2), (1, 1, 1));
+        assert_eq!((summary.r1.passed, summary.r2.passed), (2, 2));
+        assert_eq!(names(&f("out_r1")), vec!["@a"]);
+        assert_eq!(names(&f("out_i1")), vec!["@a"]);
+        assert_eq!(names(&f("single_r1")), vec!["@b"]);
+        assert_eq!(names(&f("single_r2")), vec!["@c"]);
+        drop(rejected);
+        assert_eq!(names(&f("failed_r1")), vec!["@c reject=maxee<=1", "@d reject=maxee<=1"]);
+        assert_eq!(names(&f("failed_r2")), vec!["@b reject=maxee<=1", "@d reject=maxee<=1"]);
+
+        // without singleton outputs, the passing mate is rejected with its pair
+        let outputs = PairedOutputs { singletons_r1: None, singletons_r2: None, ..outputs };
+        let mut rejected = [RejectedReads::new(None), RejectedReads::new(None)];
+        filter_fastq_paired(&[f("r1")], &[f("r2")], &[], &[], &outputs, &filter, PairRule::Both, &mut rejected);
+        assert_eq!(RejectedReads::paired_rows(&rejected[0], &rejected[1]).iter().map(|row| (row.reason.as_str(), row.reads)).collect::<Vec<_>>(),
+            vec![("r1:maxee<=1", 2), ("r1:mate_rejected", 1), ("r2:maxee<=1", 2), ("r2:mate_rejected", 1)]);
+
+        let summary = filter_fastq_paired(&[f("r1")], &[f("r2")], &[], &[], &outputs, &filter, PairRule::Either, &mut rejected);
+        assert_eq!((summary.pairs_passed, summary.singletons_r1), (3, 0));
+        assert_eq!(names(&f("out_r2")), vec!["@a", "@b", "@c"]);
+        assert_eq!(summary.rows().last().unwrap().criterion, "singletons_r2");
+    }
+}
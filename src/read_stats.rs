@@ -0,0 +1,101 @@
+//! Per-read distributions: mean quality, expected errors and length of each read
+use crate::accumulator::FastqAccumulator;
+use crate::histogram::Histogram;
+use crate::io::{FastqEntry, PHRED_LOOKUP};
+
+/// bin width of the expected errors histogram
+pub const EXPECTED_ERRORS_BIN_WIDTH: f64 = 0.1;
+
+/// Histograms of read-level statistics:
+/// * mean Phred score of the read (bins of 1)
+/// * expected number of errors in the read, i.e. the sum of the error probabilities 
+/// * read length (bins of 1)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadStatsCounter {
+    pub mean_quality: Histogram,
+    pub expected_errors: Histogram,
+    pub length: Histogram,
+}
+
+impl Default for ReadStatsCounter {
+    fn default() -> Self {
+        ReadStatsCounter {
+            mean_quality: Histogram::new(1.0),
+            expected_errors: Histogram::new(EXPECTED_ERRORS_BIN_WIDTH),
+            length: Histogram::new(1.0),
+        }
+    }
+}
+
+impl ReadStatsCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the three histograms to `{prefix}.mean_quality.csv`, `{prefix}.expected_errors.csv`
+    /// and `{prefix}.length.csv`
+    pub fn write_csv(&self, prefix: &str) -> Result<(), csv::Error> {
+        self.mean_quality.write_csv(&format!("{prefix}.mean_quality.csv"))?;
+        self.expected_errors.write_csv(&format!("{prefix}.expected_errors.csv"))?;
+        self.length.write_csv(&format!("{prefix}.length.csv"))?;
+        Ok(())
+    }
+}
+
+/// Mean Phred score of a quality string (NaN for empty reads)
+pub fn mean_quality(phred: &str) -> f64 {
+    let total: u64 = phred.bytes().map(|c| (c - 33) as u64).sum();
+    total as f64 / phred.len() as f64
+}
+
+/// Expected number of errors in the read: sum of the per-base error probabilities
+pub fn expected_errors(phred: &str) -> f64 {
+    phred.chars().map(|c| PHRED_LOOKUP.get_prob(c) as f64).sum()
+}
+
+impl FastqAccumulator for ReadStatsCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        if !fq.phred.is_empty() {
+            self.mean_quality.add(mean_quality(&fq.phred));
+        }
+        self.expected_errors.add(expected_errors(&fq.phred));
+        self.length.add(fq.seq.len() as f64);
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.mean_quality.merge(&other.mean_quality);
+        self.expected_errors.merge(&other.expected_errors);
+        self.length.merge(&other.length);
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{expected_errors, mean_quality, ReadStatsCounter};
+    use crate::accumulator::FastqAccumulator;
+    use crate::io::FastqEntry;
+
+    #[test]
+    fn test_read_stats() {
+        let mut c = ReadStatsCounter::new();
+        // Q40, Q20 -> mean 30; errors 0.0001 + 0.01
+        c.add(&FastqEntry { header: "@r1".to_string(), seq: "AC".to_string(), phred: "I5".to_string() });
+        // Q10 x 3 -> 0.3 expected errors
+        c.add(&FastqEntry { header: "@r2".to_string(), seq: "ACG".to_string(), phred: "+++".to_string() });
+
+        assert_eq!(c.length.counts(), &[0, 0, 1, 1]);
+        assert_eq!(c.mean_quality.counts()[30], 1);
+        assert_eq!(c.mean_quality.counts()[10], 1);
+        assert_eq!(c.expected_errors.counts()[0], 1);
+        assert_eq!(c.expected_errors.total(), 2);
+        assert!((c.expected_errors.quantile(1.0) - 0.3).abs() < 0.11);
+    }
+
+    #[test]
+    fn test_per_read() {
+        assert_eq!(mean_quality("I5"), 30.0);
+        assert!((expected_errors("+++") - 0.3).abs() < 1e-6);
+        // Q93 is fine too
+        assert!(expected_errors("~") < 1e-9);
+    }
+}
@@ -0,0 +1,130 @@
+//! Reads dropped by a filtering step (`qcfilter`, `trim`, pipeline steps), kept for inspection.
+//!
+//! Each rejected read is written with a `reject=REASON` tag in its header comment, where the reason
+//! is the criterion it failed (e.g. `maxee<=1`, `length>=36`), and the reasons are counted
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use serde::Serialize;
+
+use crate::io::{get_bgzf_writer, FastqEntry};
+
+/// Key of the tag in the header comment
+pub const REJECT_TAG: &str = "reject";
+/// Reason of a read that passed itself, but was dropped with its mate
+pub const MATE_REJECTED: &str = "mate_rejected";
+
+/// Appends `reject=REASON` to the header comment
+pub fn tag_rejected(header: &str, reason: &str) -> String {
+    format!("{header} {REJECT_TAG}={reason}")
+}
+
+/// The reason tagged by [`tag_rejected`], if any
+pub fn rejection_reason(header: &str) -> Option<&str> {
+    let prefix = format!("{REJECT_TAG}=");
+    header.split(' ').skip(1).find_map(|field| field.strip_prefix(prefix.as_str()))
+}
+
+/// One row of the summary table
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectionCount {
+    pub reason: String,
+    pub reads: u64,
+    /// of all rejected reads
+    pub percent: f64,
+}
+
+/// Counts rejected reads by reason, writing them (tagged) to a fastq.gz if given
+pub struct RejectedReads {
+    writer: Option<noodles::bgzf::Writer<BufWriter<File>>>,
+    counts: BTreeMap<String, u64>,
+}
+
+impl RejectedReads {
+    pub fn new(outname: Option<&str>) -> Self {
+        RejectedReads { writer: outname.map(get_bgzf_writer), counts: BTreeMap::new() }
+    }
+
+    pub fn add(&mut self, fq: &FastqEntry, reason: &str) {
+        *self.counts.entry(reason.to_string()).or_insert(0) += 1;
+        if let Some(writer) = self.writer.as_mut() {
+            let tagged = FastqEntry { header: tag_rejected(&fq.header, reason), seq: fq.seq.clone(), phred: fq.phred.clone() };
+            write!(writer, "{}", tagged.to_string()).unwrap();
+        }
+    }
+
+    /// Adds the reads of a dropped fragment (one read, or R1 and R2) to their files:
+    /// each with its own reason, or [`MATE_REJECTED`] if it has none
+    pub fn add_fragment(rejected: &mut [RejectedReads], reads: &[FastqEntry], reasons: &[Option<String>]) {
+        for ((rejected, fq), reason) in rejected.iter_mut().zip(reads).zip(reasons) {
+            rejected.add(fq, reason.as_deref().unwrap_or(MATE_REJECTED));
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Reads per reason, most frequent first
+    pub fn rows(&self) -> Vec<RejectionCount> {
+        let total = self.total();
+        let mut rows: Vec<RejectionCount> = self.counts.iter()
+            .map(|(reason, &reads)| RejectionCount { reason: reason.clone(), reads, percent: 100.0 * reads as f64 / total as f64 })
+            .collect();
+        rows.sort_by_key(|row| std::cmp::Reverse(row.reads));
+        rows
+    }
+
+    /// Rows of R1 and R2, with the reasons prefixed `r1:`/`r2:`
+    pub fn paired_rows(r1: &RejectedReads, r2: &RejectedReads) -> Vec<RejectionCount> {
+        [("r1", r1), ("r2", r2)].into_iter()
+            .flat_map(|(mate, rejected)| {
+                rejected.rows().into_iter().map(move |row| RejectionCount { reason: format!("{mate}:{}", row.reason), ..row })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for RejectedReads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.rows();
+        let width = rows.iter().map(|row| row.reason.len()).chain(std::iter::once("reason".len())).max().unwrap();
+        writeln!(f, "{:<width$}  {:>10}  {:>7}", "reason", "reads", "percent")?;
+        for row in rows {
+            writeln!(f, "{:<width$}  {:>10}  {:>7.2}", row.reason, row.reads, row.percent)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{rejection_reason, tag_rejected, RejectedReads, MATE_REJECTED};
+    use crate::io::{FastIterator, FastqEntry};
+
+    #[test]
+    fn test_rejected_reads() {
+        assert_eq!(tag_rejected("@r1 1:N:0:ACGT", "maxee<=1"), "@r1 1:N:0:ACGT reject=maxee<=1");
+        assert_eq!(rejection_reason("@r1 1:N:0:ACGT reject=maxee<=1"), Some("maxee<=1"));
+        assert_eq!(rejection_reason("@reject=x"), None);
+
+        let out = "/tmp/rustfastq_rejected.fastq.gz";
+        let fq = |name: &str| FastqEntry { header: format!("@{name}"), seq: "ACGT".to_string(), phred: "IIII".to_string() };
+        let mut rejected = [RejectedReads::new(Some(out)), RejectedReads::new(None)];
+        rejected[0].add(&fq("a"), "length>=10");
+        RejectedReads::add_fragment(&mut rejected, &[fq("b"), fq("b")], &[Some("maxee<=1".to_string()), None]);
+        rejected[0].add(&fq("c"), "length>=10");
+
+        let rows = rejected[0].rows();
+        assert_eq!((rows[0].reason.as_str(), rows[0].reads), ("length>=10", 2));
+        assert_eq!(rows[1].percent, 100.0 / 3.0);
+        assert_eq!(rejected[1].rows()[0].reason, MATE_REJECTED);
+        assert_eq!(RejectedReads::paired_rows(&rejected[0], &rejected[1])[2].reason, "r2:mate_rejected");
+
+        drop(rejected);
+        let headers: Vec<String> = FastIterator::new(out).map(|fq| fq.header).collect();
+        assert_eq!(headers, vec!["@a reject=length>=10", "@b reject=maxee<=1", "@c reject=length>=10"]);
+    }
+}
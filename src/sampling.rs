@@ -0,0 +1,431 @@
+//! Reading only part of the reads, for a quick (approximate) QC:
+//! * the first N reads of each file
+//! * a random fraction of the reads (seeded, so repeated runs give the same sample)
+//! * a few reads from BGZF blocks spread evenly across the file: unlike the other two,
+//!   this doesn't decompress the whole file (or its beginning only) but seeks to the blocks
+//!
+//! Anything computed from a sample is an estimate of the whole file
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+use noodles::bgzf as noodles_bgzf;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::io::{FastIterator, FastqEntry};
+
+/// reads taken from each block, if not specified
+pub const DEFAULT_READS_PER_BLOCK: usize = 1000;
+/// BGZF blocks are at most 64kb, so there's a block start within this distance of any offset
+const MAX_BLOCK_DISTANCE: usize = 1 << 16;
+
+/// Which reads to look at
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sampling {
+    #[default]
+    All,
+    /// the first N reads of each file
+    First(u64),
+    /// each read independently with probability `fraction`
+    Fraction { fraction: f64, seed: u64 },
+    /// `reads_per_block` consecutive reads at each of `n_blocks` positions spread across the file
+    Blocks { n_blocks: usize, reads_per_block: usize },
+}
+
+impl Sampling {
+    pub fn is_all(&self) -> bool {
+        *self == Sampling::All
+    }
+}
+
+/// Same format as parsed by [`Sampling::from_str`]
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sampling::All => write!(f, "all"),
+            Sampling::First(n) => write!(f, "first:{n}"),
+            Sampling::Fraction { fraction, seed } => write!(f, "fraction:{fraction}:{seed}"),
+            Sampling::Blocks { n_blocks, reads_per_block } => write!(f, "blocks:{n_blocks}:{reads_per_block}"),
+        }
+    }
+}
+
+/// Parses `all`, `first:N`, `fraction:F[:SEED]` (seed defaults to 0) or
+/// `blocks:N[:READS_PER_BLOCK]` (defaults to [`DEFAULT_READS_PER_BLOCK`])
+impl FromStr for Sampling {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(':').collect();
+        let number = |i: usize| -> Result<u64, String> {
+            fields[i].parse().map_err(|_| format!("invalid number {} in {s}", fields[i]))
+        };
+        match (fields[0], fields.len()) {
+            ("all", 1) => Ok(Sampling::All),
+            ("first", 2) => Ok(Sampling::First(number(1)?)),
+            ("fraction", 2 | 3) => {
+                let fraction: f64 = fields[1].parse().map_err(|_| format!("invalid fraction in {s}"))?;
+                if !(fraction > 0.0 && fraction <= 1.0) {
+                    return Err(format!("fraction must be within (0, 1]: {s}"));
+                }
+                let seed = if fields.len() == 3 { number(2)? } else { 0 };
+                Ok(Sampling::Fraction { fraction, seed })
+            }
+            ("blocks", 2 | 3) => {
+                let n_blocks = number(1)? as usize;
+                let reads_per_block = if fields.len() == 3 { number(2)? as usize } else { DEFAULT_READS_PER_BLOCK };
+                if n_blocks == 0 || reads_per_block == 0 {
+                    return Err(format!("the number of blocks and of reads per block must be positive: {s}"));
+                }
+                Ok(Sampling::Blocks { n_blocks, reads_per_block })
+            }
+            _ => Err(format!("expected all, first:N, fraction:F[:SEED] or blocks:N[:READS], got {s}")),
+        }
+    }
+}
+
+/// The sampled reads of a single file
+pub fn sample_file(fname: &str, sampling: &Sampling) -> Box<dyn Iterator<Item = FastqEntry>> {
+    match *sampling {
+        Sampling::All => Box::new(FastIterator::new(fname)),
+        Sampling::First(n) => Box::new(FastIterator::new(fname).take(n as usize)),
+        Sampling::Fraction { fraction, seed } => {
+            let mut rng = StdRng::seed_from_u64(seed);
+            Box::new(FastIterator::new(fname).filter(move |_| rng.gen_bool(fraction)))
+        }
+        Sampling::Blocks { n_blocks, reads_per_block } => {
+            Box::new(BlockSampler::new(fname, n_blocks, reads_per_block).unwrap())
+        }
+    }
+}
+
+/// The sampled reads of many files, one file after the other
+pub fn sample_files<'a>(fastq_list: &'a [String], sampling: &'a Sampling) -> impl Iterator<Item = FastqEntry> + 'a {
+    fastq_list.iter().flat_map(move |fname| sample_file(fname, sampling))
+}
+
+/// Reads fastq records line by line from a BGZF file, keeping track of the position in the file
+struct BgzfFastqReader {
+    reader: noodles_bgzf::Reader<File>,
+    file_size: u64,
+    lines: [String; 4],
+}
+
+impl BgzfFastqReader {
+    fn open(fname: &str) -> io::Result<Self> {
+        let file = File::open(fname)?;
+        let file_size = file.metadata()?.len();
+        Ok(BgzfFastqReader { reader: noodles_bgzf::Reader::new(file), file_size, lines: Default::default() })
+    }
+
+    /// reads into `lines[i]` (without newline), false at EOF
+    fn read_line(&mut self, i: usize) -> io::Result<bool> {
+        self.lines[i].clear();
+        if self.reader.read_line(&mut self.lines[i])? == 0 {
+            return Ok(false);
+        }
+        let trimmed = self.lines[i].trim_end_matches(['\n', '\r']).len();
+        self.lines[i].truncate(trimmed);
+        Ok(true)
+    }
+
+    fn entry(&self) -> FastqEntry {
+        FastqEntry { header: self.lines[0].clone(), seq: self.lines[1].clone(), phred: self.lines[3].clone() }
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<FastqEntry>> {
+        for i in 0..4 {
+            if !self.read_line(i)? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.entry()))
+    }
+
+    /// After seeking into the middle of the file: skips lines until a complete record, which is returned.
+    /// A record starts with `@` and has the `+` separator two lines later
+    /// (quality lines may start with `@` too, but then the line after next is a sequence)
+    fn resync(&mut self) -> io::Result<Option<FastqEntry>> {
+        for i in 0..3 {
+            if !self.read_line(i)? {
+                return Ok(None);
+            }
+        }
+        while !(self.lines[0].starts_with('@') && self.lines[2].starts_with('+')) {
+            self.lines.rotate_left(1);
+            if !self.read_line(2)? {
+                return Ok(None);
+            }
+        }
+        if !self.read_line(3)? {
+            return Ok(None);
+        }
+        Ok(Some(self.entry()))
+    }
+
+    /// Moves to the first BGZF block starting at or after the (compressed) `offset`.
+    /// Returns the block's offset, `None` if there's no further block
+    fn seek_block(&mut self, offset: u64) -> io::Result<Option<u64>> {
+        let file = self.reader.get_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buffer = Vec::with_capacity(MAX_BLOCK_DISTANCE + 16);
+        file.take((MAX_BLOCK_DISTANCE + 16) as u64).read_to_end(&mut buffer)?;
+
+        // gzip magic with the FEXTRA flag, followed by the 'BC' subfield holding the block size
+        let block_start = buffer.windows(16).position(|w| {
+            w[..4] == [0x1f, 0x8b, 0x08, 0x04] && w[10..16] == [0x06, 0x00, b'B', b'C', 0x02, 0x00]
+        });
+        match block_start {
+            Some(i) => {
+                let block_offset = offset + i as u64;
+                let position = noodles_bgzf::VirtualPosition::new(block_offset, 0).unwrap();
+                self.reader.seek(position)?;
+                Ok(Some(block_offset))
+            }
+            None if offset == 0 => Err(io::Error::new(io::ErrorKind::InvalidData, "not a BGZF file")),
+            None => Ok(None),
+        }
+    }
+
+    /// Approximate compressed position of the next read: the start of the current block
+    /// plus the fraction of the (uncompressed) block consumed so far
+    fn compressed_offset(&mut self) -> io::Result<f64> {
+        let (block_start, within_block) = self.reader.virtual_position().into();
+        let block_end = self.reader.position();
+        if within_block == 0 || block_end <= block_start {
+            return Ok(block_start as f64);
+        }
+        // the uncompressed size (ISIZE) is in the last 4 bytes of the block
+        let file = self.reader.get_mut();
+        let current = file.stream_position()?;
+        let mut footer = [0u8; 4];
+        file.seek(SeekFrom::Start(block_end - 4))?;
+        file.read_exact(&mut footer)?;
+        file.seek(SeekFrom::Start(current))?;
+        let uncompressed_size = u32::from_le_bytes(footer).max(1) as f64;
+        let block_size = (block_end - block_start) as f64;
+        Ok(block_start as f64 + block_size * (within_block as f64 / uncompressed_size).min(1.0))
+    }
+}
+
+/// Iterates over reads from BGZF blocks spread evenly across the file, see [`Sampling::Blocks`].
+/// If the blocks are so close that the reads of one reach into the next, the reading continues
+/// instead, so that no read is sampled twice
+pub struct BlockSampler {
+    reader: BgzfFastqReader,
+    /// compressed offsets where the chunks of reads start
+    offsets: std::vec::IntoIter<u64>,
+    reads_per_block: usize,
+    remaining: usize,
+    chunk_start: f64,
+    /// number of reads sampled so far and the (compressed) bytes they take up
+    n_reads: u64,
+    n_bytes: f64,
+}
+
+impl BlockSampler {
+    pub fn new(fname: &str, n_blocks: usize, reads_per_block: usize) -> io::Result<Self> {
+        let reader = BgzfFastqReader::open(fname)?;
+        let offsets: Vec<u64> = (0..n_blocks as u64).map(|i| i * reader.file_size / n_blocks as u64).collect();
+        Ok(BlockSampler {
+            reader,
+            offsets: offsets.into_iter(),
+            reads_per_block,
+            remaining: 0,
+            chunk_start: 0.0,
+            n_reads: 0,
+            n_bytes: 0.0,
+        })
+    }
+
+    fn end_chunk(&mut self) -> io::Result<()> {
+        self.n_bytes += self.reader.compressed_offset()? - self.chunk_start;
+        self.remaining = 0;
+        Ok(())
+    }
+
+    /// Moves to the next chunk and reads its first record
+    fn next_chunk(&mut self) -> io::Result<Option<FastqEntry>> {
+        let current = self.reader.compressed_offset()?;
+        for offset in self.offsets.by_ref() {
+            self.remaining = self.reads_per_block;
+            if (offset as f64) < current && self.n_reads > 0 {
+                // still within the previous chunk: just keep on reading
+                self.chunk_start = current;
+                return self.reader.read_record();
+            }
+            match self.reader.seek_block(offset)? {
+                Some(block_offset) => {
+                    self.chunk_start = block_offset as f64;
+                    // the first block starts with a record
+                    let record = if block_offset == 0 { self.reader.read_record()? } else { self.reader.resync()? };
+                    if record.is_some() {
+                        return Ok(record);
+                    }
+                }
+                None => break,
+            }
+        }
+        Ok(None)
+    }
+
+    fn try_next(&mut self) -> io::Result<Option<FastqEntry>> {
+        let record = if self.remaining > 0 {
+            self.reader.read_record()?
+        } else {
+            self.next_chunk()?
+        };
+        match record {
+            Some(fq) => {
+                self.remaining -= 1;
+                self.n_reads += 1;
+                if self.remaining == 0 {
+                    self.end_chunk()?;
+                }
+                Ok(Some(fq))
+            }
+            // end of file within this chunk: try the next one (which will be past the end, too)
+            None if self.remaining > 0 => {
+                self.end_chunk()?;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads per compressed byte, among the reads sampled so far
+    pub fn reads_per_byte(&self) -> f64 {
+        self.n_reads as f64 / self.n_bytes
+    }
+}
+
+impl Iterator for BlockSampler {
+    type Item = FastqEntry;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().unwrap()
+    }
+}
+
+/// Number of reads in the file, estimated from the sample:
+/// * [`Sampling::All`]: counts all reads
+/// * [`Sampling::First`]: from the compressed size of the first N reads (exact if the file has fewer)
+/// * [`Sampling::Fraction`]: sampled reads / fraction
+/// * [`Sampling::Blocks`]: from the compressed size of the sampled reads
+pub fn estimate_read_count(fname: &str, sampling: &Sampling) -> io::Result<f64> {
+    match *sampling {
+        Sampling::All => Ok(FastIterator::try_new(fname)?.count() as f64),
+        Sampling::First(n) => {
+            let mut reader = BgzfFastqReader::open(fname)?;
+            let mut count = 0;
+            while count < n {
+                if reader.read_record()?.is_none() {
+                    return Ok(count as f64);
+                }
+                count += 1;
+            }
+            Ok(reader.file_size as f64 * count as f64 / reader.compressed_offset()?)
+        }
+        Sampling::Fraction { fraction, .. } => Ok(sample_file(fname, sampling).count() as f64 / fraction),
+        Sampling::Blocks { n_blocks, reads_per_block } => {
+            let mut sampler = BlockSampler::new(fname, n_blocks, reads_per_block)?;
+            while sampler.try_next()?.is_some() {}
+            Ok(sampler.reader.file_size as f64 * sampler.reads_per_byte())
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{estimate_read_count, sample_file, BlockSampler, Sampling};
+    use crate::test_files::write_fastq_gz;
+
+    /// reads spanning quite a few BGZF blocks; quality lines starting with '@' to make resyncing harder
+    fn write_big_fastq(fname: &str) -> Vec<String> {
+        let names: Vec<String> = (0..20_000).map(|i| format!("read{i}")).collect();
+        let seq = "ACGT".repeat(25);
+        let qual = format!("@{}", "I".repeat(99));
+        let records: Vec<(&str, &str, &str)> = names.iter().map(|n| (n.as_str(), seq.as_str(), qual.as_str())).collect();
+        write_fastq_gz(fname, &records);
+        names
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("all".parse::<Sampling>().unwrap(), Sampling::All);
+        assert_eq!("first:100".parse::<Sampling>().unwrap(), Sampling::First(100));
+        assert_eq!("fraction:0.1".parse::<Sampling>().unwrap(), Sampling::Fraction { fraction: 0.1, seed: 0 });
+        assert_eq!("blocks:10:50".parse::<Sampling>().unwrap(), Sampling::Blocks { n_blocks: 10, reads_per_block: 50 });
+        assert!("fraction:2".parse::<Sampling>().is_err());
+        assert!("fraction:0".parse::<Sampling>().is_err());
+        assert!("blocks:0".parse::<Sampling>().is_err());
+        assert!("blocks:2:0".parse::<Sampling>().is_err());
+        assert!("some:1".parse::<Sampling>().is_err());
+        let s = Sampling::Fraction { fraction: 0.5, seed: 3 };
+        assert_eq!(s.to_string().parse::<Sampling>().unwrap(), s);
+    }
+
+    #[test]
+    fn test_first_and_fraction() {
+        let fname = "/tmp/rustfastq_sampling_small.fastq.gz";
+        write_fastq_gz(fname, &[("r1", "A", "I"), ("r2", "C", "I"), ("r3", "G", "I")]);
+        assert_eq!(sample_file(fname, &Sampling::First(2)).count(), 2);
+        assert_eq!(estimate_read_count(fname, &Sampling::First(10)).unwrap(), 3.0);
+
+        let sampling = Sampling::Fraction { fraction: 0.5, seed: 1 };
+        let a: Vec<String> = sample_file(fname, &sampling).map(|fq| fq.header).collect();
+        let b: Vec<String> = sample_file(fname, &sampling).map(|fq| fq.header).collect();
+        assert_eq!(a, b);
+        assert_eq!(sample_file(fname, &Sampling::Fraction { fraction: 1.0, seed: 1 }).count(), 3);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let fname = "/tmp/rustfastq_sampling_big.fastq.gz";
+        let names = write_big_fastq(fname);
+
+        let sampled: Vec<String> = BlockSampler::new(fname, 5, 10).unwrap().map(|fq| fq.header).collect();
+        assert_eq!(sampled.len(), 50);
+        assert_eq!(sampled[0], "@read0");
+        // all valid, distinct records, spread across the file
+        let mut index: Vec<usize> = sampled.iter().map(|h| names.iter().position(|n| h[1..] == *n).unwrap()).collect();
+        index.dedup();
+        assert_eq!(index.len(), 50);
+        assert!(*index.last().unwrap() > 15_000);
+
+        let estimate = estimate_read_count(fname, &Sampling::Blocks { n_blocks: 5, reads_per_block: 100 }).unwrap();
+        assert!((15_000.0..25_000.0).contains(&estimate), "{estimate}");
+        let estimate = estimate_read_count(fname, &Sampling::First(5000)).unwrap();
+        assert!((15_000.0..25_000.0).contains(&estimate), "{estimate}");
+
+        // so many blocks that they overlap: no read twice
+        let n = BlockSampler::new(fname, 1000, 100).unwrap().count();
+        assert_eq!(n, 20_000);
+    }
+
+    #[test]
+    fn test_estimate_partial_blocks() {
+        use std::io::Write;
+        let seq = "ACGT".repeat(10);
+        let qual = "I".repeat(40);
+        // a single, partial block
+        let fname = "/tmp/rustfastq_sampling_one_block.fastq.gz";
+        let names: Vec<String> = (0..200).map(|i| format!("read{i}")).collect();
+        let records: Vec<(&str, &str, &str)> = names.iter().map(|n| (n.as_str(), seq.as_str(), qual.as_str())).collect();
+        write_fastq_gz(fname, &records);
+        let estimate = estimate_read_count(fname, &Sampling::First(10)).unwrap();
+        assert!((180.0..220.0).contains(&estimate), "{estimate}");
+
+        // small blocks, of about 1000 uncompressed bytes
+        let fname = "/tmp/rustfastq_sampling_small_blocks.fastq.gz";
+        let mut writer = noodles::bgzf::Writer::new(std::fs::File::create(fname).unwrap());
+        for i in 0..50 {
+            write!(writer, "@read{i}\n{seq}\n+\n{qual}\n").unwrap();
+            if i % 10 == 9 {
+                writer.flush().unwrap();
+            }
+        }
+        writer.finish().unwrap();
+        let estimate = estimate_read_count(fname, &Sampling::First(10)).unwrap();
+        assert!((40.0..60.0).contains(&estimate), "{estimate}");
+    }
+}
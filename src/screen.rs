@@ -0,0 +1,368 @@
+//! Screening reads for contaminants (PhiX, rRNA, E. coli, mycoplasma, ...) by k-mers shared with
+//! local references, without an aligner.
+//!
+//! All canonical k-mers of the references go into a [`KmerIndex`]; a read (or pair) is contaminated
+//! if at least `min_fraction` of its k-mers are in the index, and assigned to the reference with most hits.
+//! The index is held in memory (~20 bytes per k-mer), so references are limited to [`MAX_REFERENCE_BASES`]:
+//! enough for bacteria and PhiX, but a host genome is refused (remove host reads with an aligner or `kraken-extract`)
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::io::{fastq_list_iter, get_bgzf_writer, paired_fastq_list_iter, read_fasta, FastqEntry};
+use crate::rejected::RejectedReads;
+
+/// Default k-mer length (as BBDuk)
+pub const DEFAULT_K: usize = 31;
+/// Default fraction of a read's k-mers that has to be in a reference
+pub const DEFAULT_MIN_FRACTION: f64 = 0.5;
+/// Contaminant name of reads whose hits are all k-mers shared by several references
+pub const MULTIPLE: &str = "multiple";
+/// Maximum total length of the references (about 1GB of index)
+pub const MAX_REFERENCE_BASES: usize = 50_000_000;
+/// k-mer in more than one reference
+const SHARED: u32 = u32::MAX;
+
+fn encode_base(base: u8) -> Option<u64> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+/// 2-bit encoded canonical k-mers (the smaller of the k-mer and its reverse complement),
+/// skipping the ones containing N
+pub fn canonical_kmers(seq: &[u8], k: usize) -> Vec<u64> {
+    assert!((1..=32).contains(&k), "k has to be between 1 and 32");
+    let mask = if k == 32 { u64::MAX } else { (1 << (2 * k)) - 1 };
+    let (mut forward, mut reverse, mut valid) = (0_u64, 0_u64, 0);
+    let mut kmers = Vec::with_capacity(seq.len().saturating_sub(k - 1));
+    for &base in seq {
+        match encode_base(base) {
+            Some(code) => {
+                forward = ((forward << 2) | code) & mask;
+                reverse = (reverse >> 2) | ((3 - code) << (2 * (k - 1)));
+                valid += 1;
+                if valid >= k {
+                    kmers.push(forward.min(reverse));
+                }
+            }
+            None => valid = 0,
+        }
+    }
+    kmers
+}
+
+/// k-mer hits of a read (or pair)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KmerHits {
+    pub kmers: usize,
+    /// k-mers in any reference
+    pub hits: usize,
+    /// k-mers in only this reference
+    pub per_reference: Vec<usize>,
+}
+
+impl KmerHits {
+    pub fn fraction(&self) -> f64 {
+        if self.kmers == 0 { 0.0 } else { self.hits as f64 / self.kmers as f64 }
+    }
+
+    /// The reference with the most specific hits (the first, if tied); `None` if there are none
+    pub fn best_reference(&self) -> Option<usize> {
+        let (best, &hits) = self.per_reference.iter().enumerate().rev().max_by_key(|&(_, hits)| hits)?;
+        (hits > 0).then_some(best)
+    }
+}
+
+/// Canonical k-mers of the references, each pointing to its reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct KmerIndex {
+    pub k: usize,
+    pub references: Vec<String>,
+    kmers: HashMap<u64, u32>,
+}
+
+impl KmerIndex {
+    pub fn new(k: usize) -> Self {
+        KmerIndex { k, references: Vec::new(), kmers: HashMap::new() }
+    }
+
+    /// Adds a reference made of several sequences (e.g. contigs)
+    pub fn add_reference(&mut self, name: &str, sequences: &[String]) {
+        let index = self.references.len() as u32;
+        self.references.push(name.to_string());
+        for seq in sequences {
+            for kmer in canonical_kmers(seq.as_bytes(), self.k) {
+                self.kmers.entry(kmer)
+                    .and_modify(|r| if *r != index { *r = SHARED })
+                    .or_insert(index);
+            }
+        }
+    }
+
+    /// An index of FASTA files (plain or gzip), a reference per file, named after the file.
+    /// Fails if the references have more than [`MAX_REFERENCE_BASES`] together
+    pub fn from_fasta(fnames: &[String], k: usize) -> Result<Self, String> {
+        Self::from_fasta_max(fnames, k, MAX_REFERENCE_BASES)
+    }
+
+    fn from_fasta_max(fnames: &[String], k: usize, max_bases: usize) -> Result<Self, String> {
+        let mut index = KmerIndex::new(k);
+        let mut bases = 0;
+        for fname in fnames {
+            let sequences: Vec<String> = read_fasta(fname)?.into_iter().map(|(_, seq)| seq).collect();
+            bases += sequences.iter().map(|seq| seq.len()).sum::<usize>();
+            if bases > max_bases {
+                return Err(format!(
+                    "{fname}: the references have more than {max_bases} bases, too large to screen against \
+                     (for host reads, use an aligner or kraken-extract)"
+                ));
+            }
+            index.add_reference(&reference_name(fname), &sequences);
+        }
+        Ok(index)
+    }
+
+    /// Number of distinct k-mers
+    pub fn len(&self) -> usize {
+        self.kmers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kmers.is_empty()
+    }
+
+    /// Hits of the k-mers of all the sequences together
+    pub fn hits(&self, seqs: &[&str]) -> KmerHits {
+        let mut hits = KmerHits { kmers: 0, hits: 0, per_reference: vec![0; self.references.len()] };
+        for seq in seqs {
+            for kmer in canonical_kmers(seq.as_bytes(), self.k) {
+                hits.kmers += 1;
+                if let Some(&reference) = self.kmers.get(&kmer) {
+                    hits.hits += 1;
+                    if reference != SHARED {
+                        hits.per_reference[reference as usize] += 1;
+                    }
+                }
+            }
+        }
+        hits
+    }
+}
+
+/// File name without the directory and FASTA/gz extensions: `refs/phix.fa.gz` -> `phix`
+fn reference_name(fname: &str) -> String {
+    let mut name = std::path::Path::new(fname).file_name().unwrap().to_str().unwrap();
+    for ext in [".gz", ".fasta", ".fa", ".fna"] {
+        name = name.strip_suffix(ext).unwrap_or(name);
+    }
+    name.to_string()
+}
+
+/// Classifies reads/pairs as clean or contaminated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screener {
+    pub index: KmerIndex,
+    pub min_fraction: f64,
+}
+
+impl Screener {
+    pub fn new(index: KmerIndex, min_fraction: f64) -> Self {
+        Screener { index, min_fraction }
+    }
+
+    /// The contaminant of the fragment (one read, or R1 and R2): a reference or [`MULTIPLE`];
+    /// `None` if it's clean
+    pub fn contaminant(&self, reads: &[FastqEntry]) -> Option<String> {
+        let seqs: Vec<&str> = reads.iter().map(|fq| fq.seq.as_str()).collect();
+        let hits = self.index.hits(&seqs);
+        if hits.hits == 0 || hits.fraction() < self.min_fraction {
+            return None;
+        }
+        Some(hits.best_reference().map_or(MULTIPLE.to_string(), |r| self.index.references[r].clone()))
+    }
+}
+
+/// One row of the report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScreenRow {
+    pub reference: String,
+    pub reads: u64,
+    pub percent: f64,
+}
+
+/// Reads (or pairs) per contaminant
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenSummary {
+    pub total: u64,
+    pub clean: u64,
+    /// per reference, then [`MULTIPLE`]
+    pub contaminated: Vec<(String, u64)>,
+}
+
+impl ScreenSummary {
+    pub fn new(index: &KmerIndex) -> Self {
+        let contaminated = index.references.iter().map(|r| (r.clone(), 0)).chain(std::iter::once((MULTIPLE.to_string(), 0))).collect();
+        ScreenSummary { total: 0, clean: 0, contaminated }
+    }
+
+    pub fn add(&mut self, contaminant: Option<&str>) {
+        self.total += 1;
+        match contaminant {
+            None => self.clean += 1,
+            Some(contaminant) => {
+                let (_, count) = self.contaminated.iter_mut().find(|(r, _)| r == contaminant).unwrap();
+                *count += 1;
+            }
+        }
+    }
+
+    /// % hits per reference, then `multiple` and `clean`
+    pub fn rows(&self) -> Vec<ScreenRow> {
+        let percent = |x: u64| 100.0 * x as f64 / self.total as f64;
+        self.contaminated.iter()
+            .map(|(reference, reads)| (reference.as_str(), *reads))
+            .chain(std::iter::once(("clean", self.clean)))
+            .map(|(reference, reads)| ScreenRow { reference: reference.to_string(), reads, percent: percent(reads) })
+            .collect()
+    }
+}
+
+impl fmt::Display for ScreenSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}/{}({}) clean", self.clean, self.total, self.clean as f32 / self.total as f32)?;
+        for row in self.rows() {
+            writeln!(f, "  {}: {} ({:.2}%)", row.reference, row.reads, row.percent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the clean reads to `outname`, the contaminated ones to `contaminated`
+/// (tagged with the reference, see [`crate::rejected`])
+pub fn screen_fastq(fastq_list: &[String], outname: &str, screener: &Screener, contaminated: &mut RejectedReads) -> ScreenSummary {
+    let mut writer = get_bgzf_writer(outname);
+    let mut summary = ScreenSummary::new(&screener.index);
+    for fq in fastq_list_iter(fastq_list) {
+        let reads = [fq];
+        let contaminant = screener.contaminant(&reads);
+        summary.add(contaminant.as_deref());
+        match contaminant {
+            None => write!(writer, "{}", reads[0].to_string()).unwrap(),
+            Some(contaminant) => contaminated.add(&reads[0], &contaminant),
+        }
+    }
+    summary
+}
+
+/// Screens R1/R2 together, by the k-mers of both reads
+pub fn screen_fastq_paired(
+    r1_list: &[String],
+    r2_list: &[String],
+    outname_r1: &str,
+    outname_r2: &str,
+    screener: &Screener,
+    contaminated: &mut [RejectedReads],
+) -> ScreenSummary {
+    let mut writer_r1 = get_bgzf_writer(outname_r1);
+    let mut writer_r2 = get_bgzf_writer(outname_r2);
+    let mut summary = ScreenSummary::new(&screener.index);
+    for (fq1, fq2) in paired_fastq_list_iter(r1_list, r2_list) {
+        let reads = [fq1, fq2];
+        let contaminant = screener.contaminant(&reads);
+        summary.add(contaminant.as_deref());
+        match contaminant {
+            None => {
+                write!(writer_r1, "{}", reads[0].to_string()).unwrap();
+                write!(writer_r2, "{}", reads[1].to_string()).unwrap();
+            }
+            Some(contaminant) => RejectedReads::add_fragment(contaminated, &reads, &[Some(contaminant.clone()), Some(contaminant)]),
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{canonical_kmers, reference_name, screen_fastq_paired, KmerIndex, Screener, MULTIPLE};
+    use crate::io::{reverse_complement, FastIterator, FastqEntry};
+    use crate::rejected::RejectedReads;
+    use crate::test_files::write_fastq_gz;
+    use std::io::Write;
+
+    const PHIX: &str = "GAGTTTTATCGCTTCCATGACGCAGAAGTTAACACTTTCGGATATTTCTGATGAGTCGAAAAATTATCTTGATAAAGCAGGAATTACTACTGCTTGTTTACG";
+    const ECOLI: &str = "AGCTTTTCATTCTGACTGCAACGGGCAATATGTCTCTGTGTGGATTAAAAAAAGAGTGTCTGATAGCAGCTTCTGAACTGGTTACCTGCCGTGAGTAAATTA";
+
+    fn read(seq: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: "I".repeat(seq.len()) }
+    }
+
+    #[test]
+    fn test_canonical_kmers() {
+        let kmers = canonical_kmers(PHIX.as_bytes(), 21);
+        assert_eq!(kmers.len(), PHIX.len() - 20);
+        let mut rc = canonical_kmers(reverse_complement(PHIX).as_bytes(), 21);
+        rc.reverse();
+        assert_eq!(kmers, rc);
+        // the N breaks the k-mers
+        assert_eq!(canonical_kmers(b"ACGTNACGTA", 4).len(), 3);
+        assert_eq!(canonical_kmers(b"ACG", 4).len(), 0);
+        assert_eq!(canonical_kmers(b"AAAA", 2), vec![0; 3]);
+        assert_eq!(canonical_kmers(b"TT", 2), vec![0]);
+    }
+
+    #[test]
+    fn test_screener() {
+        let mut index = KmerIndex::new(21);
+        index.add_reference("phix", &[PHIX.to_string()]);
+        index.add_reference("ecoli", &[ECOLI.to_string()]);
+        index.add_reference("phix_copy", &[PHIX[..60].to_string()]);
+        let screener = Screener::new(index, 0.5);
+
+        assert_eq!(screener.contaminant(&[read(&reverse_complement(&ECOLI[10..80]))]).unwrap(), "ecoli");
+        // the start of PhiX is in both phix references, the rest only in phix
+        assert_eq!(screener.contaminant(&[read(&PHIX[..60])]).unwrap(), MULTIPLE);
+        assert_eq!(screener.contaminant(&[read(&PHIX[30..])]).unwrap(), "phix");
+        // half the k-mers of the pair are E. coli
+        let clean = "TGCATGCAAGGCTTACCGATTACGGATCAGGCATTCAGGACGTACCATTGCATGCAAGGCTTACCGATTAC";
+        assert_eq!(screener.contaminant(&[read(&clean[..70]), read(&ECOLI[..70])]).unwrap(), "ecoli");
+        assert!(screener.contaminant(&[read(clean), read(&ECOLI[..40])]).is_none());
+        assert!(screener.contaminant(&[read("ACGT")]).is_none());
+        assert_eq!(reference_name("refs/phix.fa.gz"), "phix");
+    }
+
+    #[test]
+    fn test_screen_fastq_paired() {
+        let dir = "/tmp/rustfastq_screen";
+        std::fs::create_dir_all(dir).unwrap();
+        let f = |name: &str| format!("{dir}/{name}");
+        // plain gzip, as downloaded
+        let mut gz = flate2::write::GzEncoder::new(std::fs::File::create(f("phix.fa.gz")).unwrap(), flate2::Compression::default());
+        write!(gz, ">phix\n{}\n{}\n", &PHIX[..50], &PHIX[50..]).unwrap();
+        gz.finish().unwrap();
+        let index = KmerIndex::from_fasta(&[f("phix.fa.gz")], 21).unwrap();
+        assert_eq!(index.references, vec!["phix"]);
+        assert!(KmerIndex::from_fasta_max(&[f("phix.fa.gz")], 21, 100).is_err());
+        let clean = "TGCATGCAAGGCTTACCGATTACGGATCAGGCATTCAGGACGTACCAT";
+        let q = "I".repeat(48);
+        write_fastq_gz(&f("r1.fastq.gz"), &[("a", clean, &q), ("b", &PHIX[..48], &q)]);
+        write_fastq_gz(&f("r2.fastq.gz"), &[("a", clean, &q), ("b", &reverse_complement(&PHIX[50..98]), &q)]);
+
+        let mut contaminated = [RejectedReads::new(Some(&f("contaminated_r1.fastq.gz"))), RejectedReads::new(None)];
+        let summary = screen_fastq_paired(&[f("r1.fastq.gz")], &[f("r2.fastq.gz")], &f("clean_r1.fastq.gz"), &f("clean_r2.fastq.gz"),
+            &Screener::new(index, 0.5), &mut contaminated);
+        assert_eq!((summary.total, summary.clean), (2, 1));
+        let rows = summary.rows();
+        assert_eq!((rows[0].reference.as_str(), rows[0].reads, rows[0].percent), ("phix", 1, 50.0));
+        drop(contaminated);
+        let headers = |fname: &str| -> Vec<String> { FastIterator::new(fname).map(|fq| fq.header).collect() };
+        assert_eq!(headers(&f("clean_r2.fastq.gz")), vec!["@a"]);
+        assert_eq!(headers(&f("contaminated_r1.fastq.gz")), vec!["@b reject=phix"]);
+    }
+}
@@ -0,0 +1,302 @@
+//! Simple per-file summary statistics (similar to `seqkit stats`)
+use std::fs::File;
+use std::io::{BufWriter, Read};
+
+use noodles::bgzf as noodles_bgzf;
+use serde::{Deserialize, Serialize};
+
+use crate::accumulator::{accumulate_files_sampled, map_files_parallel, FastqAccumulator};
+use crate::histogram::Histogram;
+use crate::io::FastqEntry;
+use crate::sampling::{estimate_read_count, Sampling};
+
+/// Collects the numbers behind [`FastqStats`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsCounter {
+    reads: u64,
+    bases: u64,
+    /// needed for min/max and N50
+    length: Histogram,
+    gc: u64,
+    n: u64,
+    q20: u64,
+    q30: u64,
+    quality_sum: u64,
+}
+
+impl Default for StatsCounter {
+    fn default() -> Self {
+        StatsCounter {
+            reads: 0,
+            bases: 0,
+            length: Histogram::new(1.0),
+            gc: 0,
+            n: 0,
+            q20: 0,
+            q30: 0,
+            quality_sum: 0,
+        }
+    }
+}
+
+impl FastqAccumulator for StatsCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        self.reads += 1;
+        self.bases += fq.seq.len() as u64;
+        self.length.add(fq.seq.len() as f64);
+        for base in fq.seq.bytes() {
+            match base {
+                b'G' | b'C' | b'g' | b'c' => self.gc += 1,
+                b'N' | b'n' => self.n += 1,
+                _ => {}
+            }
+        }
+        for symbol in fq.phred.bytes() {
+            let q = (symbol - 33) as u64;
+            self.quality_sum += q;
+            if q >= 20 {
+                self.q20 += 1;
+            }
+            if q >= 30 {
+                self.q30 += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.reads += other.reads;
+        self.bases += other.bases;
+        self.length.merge(&other.length);
+        self.gc += other.gc;
+        self.n += other.n;
+        self.q20 += other.q20;
+        self.q30 += other.q30;
+        self.quality_sum += other.quality_sum;
+    }
+}
+
+impl StatsCounter {
+    /// Shortest read length such that reads of this length or longer
+    /// contain at least half of all bases
+    pub fn n50(&self) -> u64 {
+        let mut cumulative = 0;
+        for (len, &count) in self.length.counts().iter().enumerate().rev() {
+            cumulative += len as u64 * count;
+            if 2 * cumulative >= self.bases {
+                return len as u64;
+            }
+        }
+        0
+    }
+
+    pub fn summary(&self, file: &str) -> FastqStats {
+        let percent_of_bases = |x: u64| 100.0 * x as f64 / self.bases as f64;
+        let counts = self.length.counts();
+        FastqStats {
+            file: file.to_string(),
+            reads: self.reads,
+            bases: self.bases,
+            min_len: counts.iter().position(|&c| c > 0).unwrap_or(0) as u64,
+            mean_len: self.bases as f64 / self.reads as f64,
+            max_len: counts.len().saturating_sub(1) as u64,
+            n50: self.n50(),
+            gc_percent: percent_of_bases(self.gc),
+            n_percent: percent_of_bases(self.n),
+            q20_percent: percent_of_bases(self.q20),
+            q30_percent: percent_of_bases(self.q30),
+            avg_quality: self.quality_sum as f64 / self.bases as f64,
+            sampled: false,
+        }
+    }
+}
+
+/// Summary statistics of a single fastq file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FastqStats {
+    pub file: String,
+    pub reads: u64,
+    pub bases: u64,
+    pub min_len: u64,
+    pub mean_len: f64,
+    pub max_len: u64,
+    pub n50: u64,
+    pub gc_percent: f64,
+    pub n_percent: f64,
+    /// percentage of bases with quality >= Q20
+    pub q20_percent: f64,
+    /// percentage of bases with quality >= Q30
+    pub q30_percent: f64,
+    /// mean Phred score over all bases
+    pub avg_quality: f64,
+    /// computed from a sample of the reads; `stats` then scales `reads` and `bases`
+    /// to estimates for the whole file, see [`FastqStats::scale_to`]
+    pub sampled: bool,
+}
+
+impl FastqStats {
+    /// Scales `reads` and `bases` of a sample up to the (estimated) reads of the whole file
+    pub fn scale_to(&mut self, reads: f64) {
+        let factor = reads / self.reads as f64;
+        self.reads = reads.round() as u64;
+        self.bases = (self.bases as f64 * factor).round() as u64;
+        self.sampled = true;
+    }
+}
+
+/// Number of reads of a file, see [`count_reads_fast`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadCount {
+    pub file: String,
+    pub reads: u64,
+    /// estimated from a sample
+    pub sampled: bool,
+}
+
+/// Computes [`FastqStats`] for each file (files are processed in parallel).
+/// When sampling, reads and bases are estimated for the whole file, as in [`count_reads_files`]
+pub fn stats_files(fastq_files: &[String], sampling: &Sampling) -> Vec<FastqStats> {
+    accumulate_files_sampled::<StatsCounter>(fastq_files, sampling)
+        .into_iter()
+        .zip(fastq_files)
+        .map(|(counter, fname)| {
+            let mut stats = counter.summary(fname);
+            if !sampling.is_all() {
+                stats.scale_to(estimated_reads(std::slice::from_ref(fname), sampling, counter.reads));
+            }
+            stats
+        })
+        .collect()
+}
+
+/// [`estimate_read_count`] summed over the files, but from the `sampled` reads already counted for a fraction (no second pass)
+pub fn estimated_reads(fastq_files: &[String], sampling: &Sampling, sampled: u64) -> f64 {
+    match *sampling {
+        Sampling::Fraction { fraction, .. } => sampled as f64 / fraction,
+        _ => fastq_files.iter().map(|fname| estimate_read_count(fname, sampling).unwrap()).sum(),
+    }
+}
+
+/// Counts the reads in a fastq(.gz) by only counting lines,
+/// which is much faster than parsing the records
+pub fn count_reads_fast(fastq_file: &str) -> std::io::Result<u64> {
+    let mut reader = noodles_bgzf::Reader::new(File::open(fastq_file)?);
+    let mut buffer = vec![0; 1 << 20];
+    let mut n_lines = 0;
+    let mut last_byte = b'\n';
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        n_lines += buffer[..n].iter().filter(|&&b| b == b'\n').count() as u64;
+        last_byte = buffer[n - 1];
+    }
+    // the last line might lack the newline
+    if last_byte != b'\n' {
+        n_lines += 1;
+    }
+    Ok(n_lines / 4)
+}
+
+/// [`count_reads_fast`] for each file, files processed in parallel.
+/// When sampling, the counts are estimated via [`estimate_read_count`]
+pub fn count_reads_files(fastq_files: &[String], sampling: &Sampling) -> Vec<ReadCount> {
+    map_files_parallel(fastq_files, |fname| ReadCount {
+        file: fname.to_string(),
+        reads: if sampling.is_all() {
+            count_reads_fast(fname).unwrap()
+        } else {
+            estimate_read_count(fname, sampling).unwrap().round() as u64
+        },
+        sampled: !sampling.is_all(),
+    })
+}
+
+/// Writes the rows as tab separated table, with header
+pub fn write_tsv<T: Serialize>(rows: &[T], output_file: &str) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new().delimiter(b'\t').from_path(output_file)?;
+    for row in rows {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes the rows as a JSON array
+pub fn write_json<T: Serialize>(rows: &[T], output_file: &str) -> std::io::Result<()> {
+    let writer = BufWriter::new(File::create(output_file)?);
+    serde_json::to_writer_pretty(writer, rows)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{count_reads_fast, count_reads_files, stats_files, StatsCounter};
+    use crate::accumulator::FastqAccumulator;
+    use crate::io::FastqEntry;
+    use crate::sampling::Sampling;
+    use crate::test_files::write_fastq_gz;
+
+    fn entry(seq: &str, phred: &str) -> FastqEntry {
+        FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: phred.to_string() }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut c = StatsCounter::default();
+        c.add(&entry("ACGTN", "II555"));  // Q40 Q40 Q20 Q20 Q20
+        c.add(&entry("GG", "++"));  // Q10
+        c.add(&entry("ACGTACGTAC", "IIIIIIIIII"));
+
+        let s = c.summary("some.fastq.gz");
+        assert_eq!(s.reads, 3);
+        assert_eq!(s.bases, 17);
+        assert_eq!(s.min_len, 2);
+        assert_eq!(s.max_len, 10);
+        // 10 >= 17/2
+        assert_eq!(s.n50, 10);
+        assert_eq!(s.gc_percent, 100.0 * 9.0 / 17.0);
+        assert_eq!(s.n_percent, 100.0 / 17.0);
+        assert_eq!(s.q20_percent, 100.0 * 15.0 / 17.0);
+        assert_eq!(s.q30_percent, 100.0 * 12.0 / 17.0);
+        assert_eq!(s.avg_quality, (12.0 * 40.0 + 3.0 * 20.0 + 2.0 * 10.0) / 17.0);
+    }
+
+    #[test]
+    fn test_n50() {
+        let mut c = StatsCounter::default();
+        for len in [2, 3, 4, 5, 6] {
+            c.add(&entry(&"A".repeat(len), &"I".repeat(len)));
+        }
+        // 20 bases total: 6 + 5 = 11 >= 10
+        assert_eq!(c.n50(), 5);
+    }
+
+    #[test]
+    fn test_count_fast() {
+        let fname = "/tmp/rustfastq_stats_count.fastq.gz";
+        write_fastq_gz(fname, &[("r1", "ACGT", "IIII"), ("r2", "AC", "II"), ("r3", "A", "I")]);
+        assert_eq!(count_reads_fast(fname).unwrap(), 3);
+
+        let stats = stats_files(&[fname.to_string()], &Sampling::All);
+        assert_eq!(stats[0].reads, 3);
+        assert_eq!(stats[0].bases, 7);
+        assert!(!stats[0].sampled);
+    }
+
+    #[test]
+    fn test_stats_sampled() {
+        let fname = "/tmp/rustfastq_stats_sampled.fastq.gz";
+        let reads: Vec<(String, String, String)> = (0..1000).map(|i| (format!("r{i}"), "ACGT".to_string(), "IIII".to_string())).collect();
+        let reads: Vec<(&str, &str, &str)> = reads.iter().map(|(n, s, q)| (n.as_str(), s.as_str(), q.as_str())).collect();
+        write_fastq_gz(fname, &reads);
+        // the sample's counts are scaled up, as the read count
+        let sampling: Sampling = "fraction:0.5:1".parse().unwrap();
+        let stats = &stats_files(&[fname.to_string()], &sampling)[0];
+        let count = &count_reads_files(&[fname.to_string()], &sampling)[0];
+        assert!(stats.sampled && count.sampled);
+        assert_eq!(stats.reads, count.reads);
+        assert!(stats.reads > 800 && stats.reads < 1200);
+        assert_eq!(stats.bases, 4 * stats.reads);
+    }
+}
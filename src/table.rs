@@ -0,0 +1,205 @@
+//! Writing the count tables as Parquet or Arrow IPC files with typed columns,
+//! e.g. for loading them into polars/duckdb. Requires the `parquet` feature.
+//!
+//! The format is picked from the file extension, see [`ArrowFormat::from_path`]
+#[cfg(feature = "parquet")]
+use std::{fs::File, sync::Arc};
+
+#[cfg(feature = "parquet")]
+use arrow::{
+    array::{ArrayRef, BooleanArray, Float64Array, StringArray, UInt32Array, UInt64Array, UInt8Array},
+    record_batch::RecordBatch,
+};
+#[cfg(feature = "parquet")]
+use parquet::{arrow::ArrowWriter, basic::Compression, errors::ParquetError, file::properties::WriterProperties};
+
+#[cfg(feature = "parquet")]
+use crate::{phred_counter::PhredCounter, qc::IndexCount, stats::{FastqStats, ReadCount}};
+
+/// Columnar file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrowFormat {
+    Parquet,
+    /// Arrow IPC file (aka Feather v2)
+    Ipc,
+}
+
+impl ArrowFormat {
+    /// `.parquet`, or `.arrow`/`.ipc`/`.feather` for Arrow IPC; `None` for anything else (csv)
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        match extension {
+            "parquet" => Some(ArrowFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Some(ArrowFormat::Ipc),
+            _ => None,
+        }
+    }
+}
+
+/// Tables that can be converted into an Arrow [`RecordBatch`]
+#[cfg(feature = "parquet")]
+pub trait ToRecordBatch {
+    fn to_record_batch(&self) -> RecordBatch;
+}
+
+#[cfg(feature = "parquet")]
+fn string_column<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+#[cfg(feature = "parquet")]
+fn u64_column(values: impl Iterator<Item = u64>) -> ArrayRef {
+    Arc::new(UInt64Array::from_iter_values(values))
+}
+
+#[cfg(feature = "parquet")]
+fn f64_column(values: impl Iterator<Item = f64>) -> ArrayRef {
+    Arc::new(Float64Array::from_iter_values(values))
+}
+
+#[cfg(feature = "parquet")]
+fn bool_column(values: impl Iterator<Item = bool>) -> ArrayRef {
+    Arc::new(values.map(Some).collect::<BooleanArray>())
+}
+
+/// Same columns as [`PhredCounter::write_csv`], plus the numeric quality
+#[cfg(feature = "parquet")]
+impl ToRecordBatch for PhredCounter {
+    fn to_record_batch(&self) -> RecordBatch {
+        let rows: Vec<(char, usize, u64)> = self.iter().collect();
+        let phred: Vec<String> = rows.iter().map(|r| r.0.to_string()).collect();
+        RecordBatch::try_from_iter([
+            ("phred", string_column(phred.iter().map(|s| s.as_str()))),
+            ("quality", Arc::new(UInt8Array::from_iter_values(rows.iter().map(|r| r.0 as u8 - 33))) as ArrayRef),
+            ("frequency", u64_column(rows.iter().map(|r| r.2))),
+            ("position", Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.1 as u32))) as ArrayRef),
+        ]).unwrap()
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl ToRecordBatch for [FastqStats] {
+    fn to_record_batch(&self) -> RecordBatch {
+        RecordBatch::try_from_iter([
+            ("file", string_column(self.iter().map(|s| s.file.as_str()))),
+            ("reads", u64_column(self.iter().map(|s| s.reads))),
+            ("bases", u64_column(self.iter().map(|s| s.bases))),
+            ("min_len", u64_column(self.iter().map(|s| s.min_len))),
+            ("mean_len", f64_column(self.iter().map(|s| s.mean_len))),
+            ("max_len", u64_column(self.iter().map(|s| s.max_len))),
+            ("n50", u64_column(self.iter().map(|s| s.n50))),
+            ("gc_percent", f64_column(self.iter().map(|s| s.gc_percent))),
+            ("n_percent", f64_column(self.iter().map(|s| s.n_percent))),
+            ("q20_percent", f64_column(self.iter().map(|s| s.q20_percent))),
+            ("q30_percent", f64_column(self.iter().map(|s| s.q30_percent))),
+            ("avg_quality", f64_column(self.iter().map(|s| s.avg_quality))),
+            ("sampled", bool_column(self.iter().map(|s| s.sampled))),
+        ]).unwrap()
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl ToRecordBatch for [ReadCount] {
+    fn to_record_batch(&self) -> RecordBatch {
+        RecordBatch::try_from_iter([
+            ("file", string_column(self.iter().map(|s| s.file.as_str()))),
+            ("reads", u64_column(self.iter().map(|s| s.reads))),
+            ("sampled", bool_column(self.iter().map(|s| s.sampled))),
+        ]).unwrap()
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl ToRecordBatch for [IndexCount] {
+    fn to_record_batch(&self) -> RecordBatch {
+        RecordBatch::try_from_iter([
+            ("index", string_column(self.iter().map(|s| s.index.as_str()))),
+            ("count", u64_column(self.iter().map(|s| s.count))),
+            ("percent", f64_column(self.iter().map(|s| s.percent))),
+        ]).unwrap()
+    }
+}
+
+/// Counts of (index1, index2) pairs, as from `count-sampleix`
+#[cfg(feature = "parquet")]
+impl ToRecordBatch for [((String, String), usize)] {
+    fn to_record_batch(&self) -> RecordBatch {
+        RecordBatch::try_from_iter([
+            ("index1", string_column(self.iter().map(|((i1, _), _)| i1.as_str()))),
+            ("index2", string_column(self.iter().map(|((_, i2), _)| i2.as_str()))),
+            ("count", u64_column(self.iter().map(|(_, c)| *c as u64))),
+        ]).unwrap()
+    }
+}
+
+/// Writes the batch as (snappy compressed) Parquet or as Arrow IPC file
+#[cfg(feature = "parquet")]
+pub fn write_record_batch(batch: &RecordBatch, output_file: &str, format: ArrowFormat) -> Result<(), ParquetError> {
+    let file = File::create(output_file)?;
+    match format {
+        ArrowFormat::Parquet => {
+            let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props))?;
+            writer.write(batch)?;
+            writer.close()?;
+        }
+        ArrowFormat::Ipc => {
+            let mut writer = arrow::ipc::writer::FileWriter::try_new(file, &batch.schema())?;
+            writer.write(batch)?;
+            writer.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the table as Parquet/Arrow IPC
+#[cfg(feature = "parquet")]
+pub fn write_table<T: ToRecordBatch + ?Sized>(table: &T, output_file: &str, format: ArrowFormat) {
+    write_record_batch(&table.to_record_batch(), output_file, format).unwrap()
+}
+
+/// Without the `parquet` feature, there's no way to write Parquet/Arrow IPC
+/// (the CLI doesn't offer these formats then, and refuses such an output file up front)
+#[cfg(not(feature = "parquet"))]
+pub fn write_table<T: ?Sized>(_table: &T, output_file: &str, _format: ArrowFormat) {
+    panic!("can't write {output_file}: rustfastq was built without the `parquet` feature")
+}
+
+#[cfg(test)]
+mod testing {
+    use super::ArrowFormat;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ArrowFormat::from_path("/tmp/phred.parquet"), Some(ArrowFormat::Parquet));
+        assert_eq!(ArrowFormat::from_path("phred.arrow"), Some(ArrowFormat::Ipc));
+        assert_eq!(ArrowFormat::from_path("phred.csv"), None);
+        assert_eq!(ArrowFormat::from_path("phred"), None);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_write_parquet() {
+        use super::{write_table, ToRecordBatch};
+        use crate::phred_counter::PhredCounter;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let mut c = PhredCounter::new();
+        c.add_phred("II5");
+        c.add_phred("I");
+        let batch = c.to_record_batch();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.schema().field(1).data_type(), &arrow::datatypes::DataType::UInt8);
+
+        let fname = "/tmp/rustfastq_phred.parquet";
+        write_table(&c, fname, ArrowFormat::Parquet);
+        let reader = SerializedFileReader::new(std::fs::File::open(fname).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+
+        let fname = "/tmp/rustfastq_phred.arrow";
+        write_table(&c, fname, ArrowFormat::Ipc);
+        let reader = arrow::ipc::reader::FileReader::try_new(std::fs::File::open(fname).unwrap(), None).unwrap();
+        let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches[0], batch);
+    }
+}
@@ -6,4 +6,15 @@ pub const TEST_T2G: &str = "/home/michi/transcripts_to_genes.txt";
 
 
 
-pub const TEST_FASTQ_R1: &str = "/home/michi/mounts/TB4drive/ISB_data/Exp_121712592/fastq/01_d0a_20190123_L001_ds.b879614c922245658a99f7ef70251849/01-d0a-20190123_S1_L001_R1_001.fastq.gz";
\ No newline at end of file
+pub const TEST_FASTQ_R1: &str = "/home/michi/mounts/TB4drive/ISB_data/Exp_121712592/fastq/01_d0a_20190123_L001_ds.b879614c922245658a99f7ef70251849/01-d0a-20190123_S1_L001_R1_001.fastq.gz";
+
+/// Writes (name, seq, qual) records into a bgzf compressed fastq, for tests that need a small file on disk
+#[cfg(test)]
+pub fn write_fastq_gz(fname: &str, records: &[(&str, &str, &str)]) {
+    use std::io::Write;
+    let mut writer = noodles::bgzf::Writer::new(std::fs::File::create(fname).unwrap());
+    for (name, seq, qual) in records {
+        write!(writer, "@{name}\n{seq}\n+\n{qual}\n").unwrap();
+    }
+    writer.finish().unwrap();
+}
@@ -0,0 +1,221 @@
+//! Per-tile quality, to spot bubbles or flow-cell defects that only affect some tiles
+//! (and which get averaged away in the [`crate::phred_counter::PhredCounter`])
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::accumulator::{accumulate_files, FastqAccumulator};
+use crate::illumina::{IlluminaHeader, TileId};
+use crate::io::{fastq_list_iter, get_bgzf_writer, FastqEntry};
+
+/// Default for [`TileQualityCounter::flagged_tiles`]: tiles whose mean quality drops more than
+/// this below the cycle mean (same as FastQC's warning level)
+pub const DEFAULT_TILE_DEVIATION: f64 = 5.0;
+
+/// Sum of quality scores and number of bases, for each tile and position
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileQualityCounter {
+    tiles: BTreeMap<TileId, Vec<(u64, u64)>>,
+    /// reads whose header isn't in Illumina format
+    n_unparsed: u64,
+}
+
+/// Mean quality of a single tile at a single position,
+/// and its deviation from the mean over all tiles at that position
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TileCycleQuality {
+    pub lane: u32,
+    pub tile: u32,
+    /// 0-based position in the read
+    pub position: usize,
+    pub n_bases: u64,
+    pub mean_quality: f64,
+    pub deviation: f64,
+}
+
+impl TileQualityCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, fq: &FastqEntry) {
+        let Some(header) = IlluminaHeader::parse(&fq.header) else {
+            self.n_unparsed += 1;
+            return;
+        };
+        let sums = self.tiles.entry(header.tile_id()).or_default();
+        if fq.phred.len() > sums.len() {
+            sums.resize(fq.phred.len(), (0, 0));
+        }
+        for ((sum, n), symbol) in sums.iter_mut().zip(fq.phred.bytes()) {
+            *sum += (symbol - 33) as u64;
+            *n += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: TileQualityCounter) {
+        for (tile, other_sums) in other.tiles {
+            let sums = self.tiles.entry(tile).or_default();
+            if other_sums.len() > sums.len() {
+                sums.resize(other_sums.len(), (0, 0));
+            }
+            for ((sum, n), (other_sum, other_n)) in sums.iter_mut().zip(other_sums) {
+                *sum += other_sum;
+                *n += other_n;
+            }
+        }
+        self.n_unparsed += other.n_unparsed;
+    }
+
+    pub fn from_files(fastq_files: &[String]) -> Self {
+        accumulate_files(fastq_files)
+    }
+
+    /// number of reads that couldn't be assigned to a tile
+    pub fn n_unparsed(&self) -> u64 {
+        self.n_unparsed
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = &TileId> {
+        self.tiles.keys()
+    }
+
+    /// mean quality per position across all tiles
+    fn position_means(&self) -> Vec<f64> {
+        let n_positions = self.tiles.values().map(|s| s.len()).max().unwrap_or(0);
+        let mut totals = vec![(0_u64, 0_u64); n_positions];
+        for sums in self.tiles.values() {
+            for ((total_sum, total_n), (sum, n)) in totals.iter_mut().zip(sums) {
+                *total_sum += sum;
+                *total_n += n;
+            }
+        }
+        totals.into_iter().map(|(sum, n)| sum as f64 / n as f64).collect()
+    }
+
+    /// Per tile and position: mean quality and deviation from the position's mean over all tiles
+    pub fn table(&self) -> Vec<TileCycleQuality> {
+        let position_means = self.position_means();
+        let mut table = Vec::new();
+        for (tile, sums) in self.tiles.iter() {
+            for (position, &(sum, n)) in sums.iter().enumerate() {
+                if n == 0 {
+                    continue;
+                }
+                let mean_quality = sum as f64 / n as f64;
+                table.push(TileCycleQuality {
+                    lane: tile.lane,
+                    tile: tile.tile,
+                    position,
+                    n_bases: n,
+                    mean_quality,
+                    deviation: mean_quality - position_means[position],
+                });
+            }
+        }
+        table
+    }
+
+    /// Tiles whose mean quality is more than `max_deviation` below the
+    /// position mean at any position
+    pub fn flagged_tiles(&self, max_deviation: f64) -> Vec<TileId> {
+        let mut flagged: Vec<TileId> = self.table().into_iter()
+            .filter(|t| t.deviation < -max_deviation)
+            .map(|t| TileId { lane: t.lane, tile: t.tile })
+            .collect();
+        flagged.dedup();
+        flagged
+    }
+}
+
+impl FastqAccumulator for TileQualityCounter {
+    fn add(&mut self, fq: &FastqEntry) {
+        TileQualityCounter::add(self, fq)
+    }
+    fn merge(&mut self, other: Self) {
+        TileQualityCounter::merge(self, other)
+    }
+}
+
+/// Filters the fastq files, dropping all reads from the given tiles.
+/// Reads without Illumina header (i.e. unknown tile) are kept
+pub fn filter_tiles(fastq_list: &[String], outname: &str, exclude: &HashSet<TileId>) {
+    let mut writer = get_bgzf_writer(outname);
+
+    let mut total_reads = 0;
+    let mut passing_reads = 0;
+
+    for fq in fastq_list_iter(fastq_list) {
+        total_reads += 1;
+
+        let keep = match IlluminaHeader::parse(&fq.header) {
+            Some(h) => !exclude.contains(&h.tile_id()),
+            None => true,
+        };
+        if keep {
+            write!(writer, "{}", fq.to_string()).unwrap();
+            passing_reads += 1;
+        }
+    }
+    println!(
+        "{}/{}({}) reads passed the tile filter",
+        passing_reads,
+        total_reads,
+        (passing_reads as f32) / (total_reads as f32)
+    )
+}
+
+#[cfg(test)]
+mod testing {
+    use super::TileQualityCounter;
+    use crate::illumina::TileId;
+    use crate::io::FastqEntry;
+
+    fn entry(tile: u32, phred: &str) -> FastqEntry {
+        FastqEntry {
+            header: format!("@A00123:8:FC:1:{tile}:100:200 1:N:0:ACGT"),
+            seq: "A".repeat(phred.len()),
+            phred: phred.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_tile_table() {
+        let mut c = TileQualityCounter::new();
+        c.add(&entry(1101, "II"));  // Q40
+        c.add(&entry(1101, "II"));
+        c.add(&entry(1102, "I+"));  // Q40, Q10
+        c.add(&FastqEntry { header: "@read1".to_string(), seq: "A".to_string(), phred: "I".to_string() });
+        assert_eq!(c.n_unparsed(), 1);
+
+        let table = c.table();
+        assert_eq!(table.len(), 4);
+        // position 1: overall mean (40+40+10)/3 = 30
+        let t = table.iter().find(|t| t.tile == 1102 && t.position == 1).unwrap();
+        assert_eq!(t.mean_quality, 10.0);
+        assert_eq!(t.deviation, -20.0);
+        let t = table.iter().find(|t| t.tile == 1101 && t.position == 1).unwrap();
+        assert_eq!(t.deviation, 10.0);
+
+        assert_eq!(c.flagged_tiles(5.0), vec![TileId { lane: 1, tile: 1102 }]);
+        assert_eq!(c.flagged_tiles(25.0), vec![]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = TileQualityCounter::new();
+        a.add(&entry(1101, "II"));
+        let mut b = TileQualityCounter::new();
+        b.add(&entry(1102, "I+"));
+        b.add(&entry(1101, "+"));
+
+        let mut both = TileQualityCounter::new();
+        both.add(&entry(1101, "II"));
+        both.add(&entry(1102, "I+"));
+        both.add(&entry(1101, "+"));
+
+        a.merge(b);
+        assert_eq!(a, both);
+    }
+}
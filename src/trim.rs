@@ -0,0 +1,659 @@
+//! Quality trimming of reads, keeping `seq` and `phred` in sync (rather than dropping
+//! the whole read, as [`crate::qcfilter`] does).
+//!
+//! The [`Trimmer`] applies its [`TrimOperation`]s in order, then removes adapters
+//! (as cutadapt, see [`crate::adapter`]) and poly-X tails, optionally masks low-quality
+//! bases, and drops reads shorter than the minimum length or failing its filter (e.g. low complexity).
+//! Besides quality trimming, fixed positions can be cropped (e.g. the first bases of R2).
+//! Pairs are trimmed by [`trim_fastq_paired`], optionally cutting both reads to the insert
+//! size found from their overlap first.
+//! Dropped reads can be kept (as trimmed) in [`RejectedReads`], tagged `length>=MIN_LENGTH` or
+//! with the filter criterion they failed
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use crate::adapter::{trim_pair_by_overlap, AdapterMatch, AdapterOptions, AdapterTrimmer, OverlapParams};
+use crate::complexity::{parse_poly_x_tail, poly_x_tail_start};
+use crate::io::{fastq_list_iter, get_bgzf_writer, paired_fastq_list_iter, FastqEntry};
+use crate::qcfilter::{QualityCriterion, QualityFilter};
+use crate::rejected::RejectedReads;
+
+/// Quality of masked bases: Q2, as Illumina's N calls
+pub const MASKED_QUALITY: char = '#';
+
+fn phred_scores(phred: &str) -> impl DoubleEndedIterator<Item = u8> + ExactSizeIterator + '_ {
+    phred.bytes().map(|c| c - 33)
+}
+
+/// Position of the first base with at least quality `min_quality` (the read length if there's none)
+pub fn leading_end(phred: &str, min_quality: u8) -> usize {
+    phred_scores(phred).position(|q| q >= min_quality).unwrap_or(phred.len())
+}
+
+/// Length after removing bases below `min_quality` from the 3' end
+pub fn trailing_end(phred: &str, min_quality: u8) -> usize {
+    phred_scores(phred).rposition(|q| q >= min_quality).map_or(0, |i| i + 1)
+}
+
+/// Trimmomatic's SLIDINGWINDOW: scanning from the 5' end, cuts at the first window whose
+/// mean quality drops below `min_quality`, keeping the good bases at the start of that window.
+/// Returns the length to keep
+pub fn sliding_window_end(phred: &str, window: usize, min_quality: u8) -> usize {
+    let quals: Vec<u8> = phred_scores(phred).collect();
+    let window = window.clamp(1, quals.len().max(1));
+    if quals.len() < window {
+        return 0;
+    }
+    let required = window as u32 * min_quality as u32;
+    let mut total: u32 = quals[..window].iter().map(|&q| q as u32).sum();
+    for start in 0..=quals.len() - window {
+        if start > 0 {
+            total = total + quals[start + window - 1] as u32 - quals[start - 1] as u32;
+        }
+        if total < required {
+            let good = quals[start..start + window].iter().take_while(|&&q| q >= min_quality).count();
+            return start + good;
+        }
+    }
+    quals.len()
+}
+
+/// BWA's (and cutadapt's `-q`) 3' quality trimming: cuts the suffix maximizing
+/// the sum of `cutoff - quality`. Returns the length to keep
+pub fn bwa_trim_end(phred: &str, cutoff: u8) -> usize {
+    let (mut sum, mut max, mut end) = (0_i64, 0_i64, phred.len());
+    for (i, q) in phred_scores(phred).enumerate().rev() {
+        sum += cutoff as i64 - q as i64;
+        if sum < 0 {
+            break;
+        }
+        if sum > max {
+            max = sum;
+            end = i;
+        }
+    }
+    end
+}
+
+impl FastqEntry {
+    /// Removes bases below `min_quality` from the 5' end (Trimmomatic LEADING)
+    pub fn trim_leading(&mut self, min_quality: u8) {
+        let start = leading_end(&self.phred, min_quality);
+        self.keep_range(start, self.len());
+    }
+
+    /// Removes bases below `min_quality` from the 3' end (Trimmomatic TRAILING)
+    pub fn trim_trailing(&mut self, min_quality: u8) {
+        let end = trailing_end(&self.phred, min_quality);
+        self.keep_range(0, end);
+    }
+
+    /// See [`sliding_window_end`]
+    pub fn trim_sliding_window(&mut self, window: usize, min_quality: u8) {
+        let end = sliding_window_end(&self.phred, window, min_quality);
+        self.keep_range(0, end);
+    }
+
+    /// See [`bwa_trim_end`]
+    pub fn trim_quality_3p(&mut self, cutoff: u8) {
+        let end = bwa_trim_end(&self.phred, cutoff);
+        self.keep_range(0, end);
+    }
+
+    /// See [`poly_x_tail_start`]
+    pub fn trim_poly_x(&mut self, base: char, min_length: usize) {
+        let end = poly_x_tail_start(&self.seq, base, min_length);
+        self.keep_range(0, end);
+    }
+
+    /// Removes the first `n` bases (Trimmomatic HEADCROP)
+    pub fn head_crop(&mut self, n: usize) {
+        self.keep_range(n.min(self.len()), self.len());
+    }
+
+    /// Cuts the read to at most `length` bases, removing them from the 3' end (Trimmomatic CROP)
+    pub fn crop(&mut self, length: usize) {
+        self.keep_range(0, length.min(self.len()));
+    }
+
+    /// Replaces bases below `min_quality` by `N` (with quality [`MASKED_QUALITY`]), keeping the length
+    pub fn mask_low_quality(&mut self, min_quality: u8) {
+        let (seq, phred): (String, String) = self.seq.chars().zip(phred_scores(&self.phred).zip(self.phred.chars()))
+            .map(|(base, (q, symbol))| if q < min_quality { ('N', MASKED_QUALITY) } else { (base, symbol) })
+            .unzip();
+        self.seq = seq;
+        self.phred = phred;
+    }
+}
+
+/// A single trimming step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimOperation {
+    Leading(u8),
+    Trailing(u8),
+    SlidingWindow { window: usize, quality: u8 },
+    /// BWA-style 3' trimming with this cutoff
+    Quality3p(u8),
+    PolyX { base: char, min_length: usize },
+    /// remove this many bases from the 5' end
+    HeadCrop(usize),
+    /// cut to this length
+    Crop(usize),
+    /// replace bases below this quality by N
+    Mask(u8),
+}
+
+impl TrimOperation {
+    pub fn apply(&self, fq: &mut FastqEntry) {
+        match *self {
+            TrimOperation::Leading(q) => fq.trim_leading(q),
+            TrimOperation::Trailing(q) => fq.trim_trailing(q),
+            TrimOperation::SlidingWindow { window, quality } => fq.trim_sliding_window(window, quality),
+            TrimOperation::Quality3p(q) => fq.trim_quality_3p(q),
+            TrimOperation::PolyX { base, min_length } => fq.trim_poly_x(base, min_length),
+            TrimOperation::HeadCrop(n) => fq.head_crop(n),
+            TrimOperation::Crop(length) => fq.crop(length),
+            TrimOperation::Mask(q) => fq.mask_low_quality(q),
+        }
+    }
+}
+
+/// Trimmomatic-like names, e.g. `SLIDINGWINDOW:4:20`
+impl fmt::Display for TrimOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrimOperation::Leading(q) => write!(f, "LEADING:{q}"),
+            TrimOperation::Trailing(q) => write!(f, "TRAILING:{q}"),
+            TrimOperation::SlidingWindow { window, quality } => write!(f, "SLIDINGWINDOW:{window}:{quality}"),
+            TrimOperation::Quality3p(q) => write!(f, "QUALITY3P:{q}"),
+            TrimOperation::PolyX { base, min_length } => write!(f, "POLYX:{base}:{min_length}"),
+            TrimOperation::HeadCrop(n) => write!(f, "HEADCROP:{n}"),
+            TrimOperation::Crop(length) => write!(f, "CROP:{length}"),
+            TrimOperation::Mask(q) => write!(f, "MASK:{q}"),
+        }
+    }
+}
+
+/// Parses `WINDOW:QUALITY` into [`TrimOperation::SlidingWindow`], e.g. `4:20`
+pub fn parse_sliding_window(s: &str) -> Result<TrimOperation, String> {
+    let (window, quality) = s.split_once(':').ok_or(format!("expected WINDOW:QUALITY, got {s}"))?;
+    let window: usize = window.parse().map_err(|_| format!("invalid window in {s}"))?;
+    if window == 0 {
+        return Err(format!("window must be at least 1, got {s}"));
+    }
+    Ok(TrimOperation::SlidingWindow {
+        window,
+        quality: quality.parse().map_err(|_| format!("invalid quality in {s}"))?,
+    })
+}
+
+/// Parses `BASE[:MIN_LENGTH]` into [`TrimOperation::PolyX`]
+pub fn parse_poly_x(s: &str) -> Result<TrimOperation, String> {
+    let (base, min_length) = parse_poly_x_tail(s)?;
+    Ok(TrimOperation::PolyX { base, min_length })
+}
+
+impl FromStr for TrimOperation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, args) = s.split_once(':').ok_or(format!("expected NAME:ARGS, got {s}"))?;
+        let quality = || args.parse::<u8>().map_err(|_| format!("invalid quality in {s}"));
+        let length = || args.parse::<usize>().map_err(|_| format!("invalid length in {s}"));
+        match name.to_uppercase().as_str() {
+            "LEADING" => Ok(TrimOperation::Leading(quality()?)),
+            "TRAILING" => Ok(TrimOperation::Trailing(quality()?)),
+            "SLIDINGWINDOW" => parse_sliding_window(args),
+            "QUALITY3P" => Ok(TrimOperation::Quality3p(quality()?)),
+            "POLYX" => parse_poly_x(args),
+            "HEADCROP" => Ok(TrimOperation::HeadCrop(length()?)),
+            "CROP" => Ok(TrimOperation::Crop(length()?)),
+            "MASK" => Ok(TrimOperation::Mask(quality()?)),
+            _ => Err(format!("unknown trim operation {name}")),
+        }
+    }
+}
+
+/// Whether a trimmed read is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimResult {
+    Kept,
+    TooShort,
+    /// failed the [`Trimmer::filter`]
+    Filtered,
+}
+
+/// Trimming operations, applied in order, then adapter and poly-X tail removal;
+/// finally the minimum length and filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trimmer {
+    pub operations: Vec<TrimOperation>,
+    pub adapters: Option<AdapterTrimmer>,
+    /// applied after the adapters: [`TrimOperation::PolyX`] (a poly-A tail can be in front of the adapter),
+    /// and [`TrimOperation::Mask`] on what's left of the read
+    pub post_adapter: Vec<TrimOperation>,
+    pub min_length: usize,
+    pub filter: Option<QualityFilter>,
+}
+
+impl Trimmer {
+    pub fn new(operations: Vec<TrimOperation>, min_length: usize) -> Self {
+        Trimmer { operations, adapters: None, post_adapter: Vec::new(), min_length, filter: None }
+    }
+
+    pub fn with_adapters(mut self, adapters: AdapterTrimmer) -> Self {
+        self.adapters = Some(adapters);
+        self
+    }
+
+    /// Poly-X tails to remove after the adapters, `(base, min_length)`
+    pub fn with_tails(mut self, tails: &[(char, usize)]) -> Self {
+        self.post_adapter.retain(|op| !matches!(op, TrimOperation::PolyX { .. }));
+        let poly_x = tails.iter().map(|&(base, min_length)| TrimOperation::PolyX { base, min_length });
+        self.post_adapter.splice(0..0, poly_x);
+        self
+    }
+
+    /// Mask bases below `min_quality` by N, after all the trimming
+    pub fn with_mask(mut self, min_quality: u8) -> Self {
+        self.post_adapter.retain(|op| !matches!(op, TrimOperation::Mask(_)));
+        self.post_adapter.push(TrimOperation::Mask(min_quality));
+        self
+    }
+
+    /// Drop trimmed reads failing the filter
+    pub fn with_filter(mut self, filter: QualityFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Trims the read in place, without checking the length; returns the adapter found
+    pub fn trim_read(&self, fq: &mut FastqEntry) -> Option<AdapterMatch> {
+        for op in &self.operations {
+            op.apply(fq);
+        }
+        let adapter = self.adapters.as_ref().and_then(|adapters| fq.trim_adapters(adapters));
+        for op in &self.post_adapter {
+            op.apply(fq);
+        }
+        adapter
+    }
+
+    /// Whether the trimmed read is kept
+    pub fn check(&self, fq: &FastqEntry) -> TrimResult {
+        if fq.len() < self.min_length {
+            TrimResult::TooShort
+        } else if self.filter.as_ref().is_some_and(|filter| !filter.passes(fq)) {
+            TrimResult::Filtered
+        } else {
+            TrimResult::Kept
+        }
+    }
+
+    /// Why the trimmed read isn't kept: `length>=MIN_LENGTH`, or the filter criterion it failed
+    pub fn rejection_reason(&self, fq: &FastqEntry) -> Option<String> {
+        match self.check(fq) {
+            TrimResult::Kept => None,
+            TrimResult::TooShort => Some(format!("length>={}", self.min_length)),
+            TrimResult::Filtered => self.filter.as_ref().and_then(|filter| filter.rejection_reason(&filter.failures(fq))),
+        }
+    }
+
+    /// Trims the read in place; returns whether it's kept
+    pub fn trim(&self, fq: &mut FastqEntry) -> bool {
+        self.trim_read(fq);
+        self.check(fq) == TrimResult::Kept
+    }
+}
+
+/// What to trim, as given on the command line or in a pipeline config
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TrimOptions {
+    /// bases to remove from the 5' end
+    pub head_crop: Option<usize>,
+    /// instead of `head_crop` for R2
+    pub head_crop_r2: Option<usize>,
+    /// cut reads to this length
+    pub crop: Option<usize>,
+    pub leading: Option<u8>,
+    pub trailing: Option<u8>,
+    /// BWA-style 3' trimming cutoff
+    pub quality_cutoff: Option<u8>,
+    /// `WINDOW:QUALITY`
+    pub sliding_window: Option<String>,
+    /// poly-X tails as `BASE[:MIN_LENGTH]`
+    pub poly_x: Vec<String>,
+    pub min_length: usize,
+    pub max_dust: Option<f64>,
+    pub min_entropy: Option<f64>,
+    /// replace bases below this quality by N, after trimming
+    pub mask_below: Option<u8>,
+    #[serde(flatten)]
+    pub adapters: AdapterOptions,
+    /// for pairs, cut both reads to the insert size from their overlap
+    pub overlap: bool,
+    /// keys of a pipeline config that aren't options (typos); `deny_unknown_fields` doesn't work
+    /// with `flatten`, so [`crate::pipeline::PipelineConfig`] rejects them
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl Default for TrimOptions {
+    fn default() -> Self {
+        TrimOptions {
+            head_crop: None,
+            head_crop_r2: None,
+            crop: None,
+            leading: None,
+            trailing: None,
+            quality_cutoff: None,
+            sliding_window: None,
+            poly_x: Vec::new(),
+            min_length: 1,
+            max_dust: None,
+            min_entropy: None,
+            mask_below: None,
+            adapters: AdapterOptions::default(),
+            overlap: false,
+            unknown: BTreeMap::new(),
+        }
+    }
+}
+
+impl TrimOptions {
+    /// Trimmers for R1 and R2 (the same, except for R2's own adapters and head crop); `r2_list` may be empty.
+    /// Operations are applied as head crop, crop, leading, trailing, quality cutoff, sliding window;
+    /// masking comes last, after the adapters and poly-X tails
+    pub fn trimmers(&self, r1_list: &[String], r2_list: &[String]) -> Result<(Trimmer, Trimmer), String> {
+        let mut operations: Vec<TrimOperation> = [
+            self.crop.map(TrimOperation::Crop),
+            self.leading.map(TrimOperation::Leading),
+            self.trailing.map(TrimOperation::Trailing),
+            self.quality_cutoff.map(TrimOperation::Quality3p),
+        ].into_iter().flatten().collect();
+        if let Some(window) = &self.sliding_window {
+            operations.push(parse_sliding_window(window)?);
+        }
+        let tails: Vec<(char, usize)> = self.poly_x.iter().map(|s| parse_poly_x_tail(s)).collect::<Result<_, _>>()?;
+        let complexity: Vec<QualityCriterion> = [
+            self.max_dust.map(QualityCriterion::MaxDust),
+            self.min_entropy.map(QualityCriterion::MinEntropy),
+        ].into_iter().flatten().collect();
+
+        let (adapters_r1, adapters_r2) = self.adapters.trimmers(r1_list, r2_list)?;
+        let trimmer = |head_crop: Option<usize>, adapters: Option<AdapterTrimmer>| {
+            let operations = head_crop.map(TrimOperation::HeadCrop).into_iter().chain(operations.iter().copied()).collect();
+            let mut trimmer = Trimmer::new(operations, self.min_length).with_tails(&tails);
+            if let Some(min_quality) = self.mask_below {
+                trimmer = trimmer.with_mask(min_quality);
+            }
+            trimmer.adapters = adapters;
+            if !complexity.is_empty() {
+                trimmer = trimmer.with_filter(QualityFilter::new(complexity.clone()));
+            }
+            trimmer
+        };
+        Ok((trimmer(self.head_crop, adapters_r1), trimmer(self.head_crop_r2.or(self.head_crop), adapters_r2)))
+    }
+
+    pub fn overlap_params(&self) -> Option<OverlapParams> {
+        self.overlap.then(OverlapParams::default)
+    }
+}
+
+/// Reads and bases before/after trimming
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrimSummary {
+    pub reads_in: u64,
+    pub reads_trimmed: u64,
+    /// adapter found by sequence or (for pairs) by the overlap
+    pub reads_with_adapter: u64,
+    pub too_short: u64,
+    pub filtered: u64,
+    pub reads_out: u64,
+    pub bases_in: u64,
+    pub bases_out: u64,
+}
+
+impl TrimSummary {
+    /// Counts a read of length `len_in`, trimmed to `len_out`
+    pub fn add(&mut self, len_in: usize, len_out: usize, adapter: bool, result: TrimResult) {
+        self.reads_in += 1;
+        if adapter {
+            self.reads_with_adapter += 1;
+        }
+        self.bases_in += len_in as u64;
+        if len_out < len_in {
+            self.reads_trimmed += 1;
+        }
+        match result {
+            TrimResult::Kept => {
+                self.reads_out += 1;
+                self.bases_out += len_out as u64;
+            }
+            TrimResult::TooShort => self.too_short += 1,
+            TrimResult::Filtered => self.filtered += 1,
+        }
+    }
+}
+
+impl fmt::Display for TrimSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} reads, {} trimmed, {} with adapter, {} too short, {} filtered, {} written",
+            self.reads_in, self.reads_trimmed, self.reads_with_adapter, self.too_short, self.filtered, self.reads_out)?;
+        writeln!(f, "{}/{}({}) bases kept", self.bases_out, self.bases_in, self.bases_out as f32 / self.bases_in as f32)
+    }
+}
+
+/// Trims all reads, writing the ones that are kept to `outname`, the others to `rejected`
+pub fn trim_fastq(fastq_list: &[String], outname: &str, trimmer: &Trimmer, rejected: &mut RejectedReads) -> TrimSummary {
+    let mut writer = get_bgzf_writer(outname);
+    let mut summary = TrimSummary::default();
+    for mut fq in fastq_list_iter(fastq_list) {
+        let len_in = fq.len();
+        let adapter = trimmer.trim_read(&mut fq).is_some();
+        let result = trimmer.check(&fq);
+        summary.add(len_in, fq.len(), adapter, result);
+        if result == TrimResult::Kept {
+            write!(writer, "{}", fq.to_string()).unwrap();
+        } else {
+            rejected.add(&fq, &trimmer.rejection_reason(&fq).unwrap());
+        }
+    }
+    summary
+}
+
+/// Trims R1/R2 together: first to the insert size (if `overlap` is given), then each read with
+/// its trimmer. A pair is dropped if either read isn't kept (counted with R1's reason, else R2's, for both),
+/// and goes to `rejected` (R1 and R2)
+pub fn trim_fastq_paired(
+    r1_list: &[String],
+    r2_list: &[String],
+    outname_r1: &str,
+    outname_r2: &str,
+    trimmers: (&Trimmer, &Trimmer),
+    overlap: Option<&OverlapParams>,
+    rejected: &mut [RejectedReads],
+) -> (TrimSummary, TrimSummary) {
+    let mut writer_r1 = get_bgzf_writer(outname_r1);
+    let mut writer_r2 = get_bgzf_writer(outname_r2);
+    let (mut summary_r1, mut summary_r2) = (TrimSummary::default(), TrimSummary::default());
+    for (mut fq1, mut fq2) in paired_fastq_list_iter(r1_list, r2_list) {
+        let (len1, len2) = (fq1.len(), fq2.len());
+        let insert = overlap.and_then(|params| trim_pair_by_overlap(&mut fq1, &mut fq2, params));
+        let adapter1 = trimmers.0.trim_read(&mut fq1).is_some() || insert.is_some_and(|l| l < len1);
+        let adapter2 = trimmers.1.trim_read(&mut fq2).is_some() || insert.is_some_and(|l| l < len2);
+        let result = match trimmers.0.check(&fq1) {
+            TrimResult::Kept => trimmers.1.check(&fq2),
+            r => r,
+        };
+        summary_r1.add(len1, fq1.len(), adapter1, result);
+        summary_r2.add(len2, fq2.len(), adapter2, result);
+        if result == TrimResult::Kept {
+            write!(writer_r1, "{}", fq1.to_string()).unwrap();
+            write!(writer_r2, "{}", fq2.to_string()).unwrap();
+        } else {
+            let reasons = [trimmers.0.rejection_reason(&fq1), trimmers.1.rejection_reason(&fq2)];
+            RejectedReads::add_fragment(rejected, &[fq1, fq2], &reasons);
+        }
+    }
+    (summary_r1, summary_r2)
+}
+
+#[cfg(test)]
+mod testing {
+    use super::{bwa_trim_end, leading_end, sliding_window_end, trailing_end, trim_fastq, trim_fastq_paired, TrimOperation, TrimOptions, TrimResult, Trimmer};
+    use crate::adapter::{Adapter, AdapterEnd, AdapterTrimmer, OverlapParams};
+    use crate::io::{reverse_complement, FastIterator, FastqEntry};
+    use crate::qcfilter::{QualityCriterion, QualityFilter};
+    use crate::rejected::RejectedReads;
+    use crate::test_files::write_fastq_gz;
+
+    #[test]
+    fn test_leading_trailing() {
+        // Q2 Q2 Q40 Q40 Q2
+        assert_eq!(leading_end("##II#", 3), 2);
+        assert_eq!(trailing_end("##II#", 3), 4);
+        assert_eq!(leading_end("###", 3), 3);
+        assert_eq!(trailing_end("###", 3), 0);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        // Q40 x 4, then Q10 x 4
+        let phred = "IIII++++";
+        assert_eq!(sliding_window_end(phred, 4, 20), 4);
+        assert_eq!(sliding_window_end(phred, 2, 20), 4);
+        assert_eq!(sliding_window_end("IIII", 4, 20), 4);
+        assert_eq!(sliding_window_end("++++", 4, 20), 0);
+        // window longer than the read: the whole read is the window
+        assert_eq!(sliding_window_end("II", 4, 20), 2);
+        assert_eq!(sliding_window_end("", 4, 20), 0);
+    }
+
+    #[test]
+    fn test_bwa_trim() {
+        // example from the cutadapt docs: 42 40 26 27 8 7 11 4 2 3, cutoff 10
+        let phred: String = [42, 40, 26, 27, 8, 7, 11, 4, 2, 3].iter().map(|q| (q + 33) as u8 as char).collect();
+        assert_eq!(bwa_trim_end(&phred, 10), 4);
+        assert_eq!(bwa_trim_end("IIII", 10), 4);
+        assert_eq!(bwa_trim_end("", 10), 0);
+    }
+
+    #[test]
+    fn test_poly_x() {
+        assert_eq!("POLYX:a:15".parse::<TrimOperation>().unwrap(), TrimOperation::PolyX { base: 'A', min_length: 15 });
+        assert!(super::parse_poly_x("N").is_err());
+    }
+
+    #[test]
+    fn test_crop_and_mask() {
+        let mut fq = FastqEntry { header: "@r".to_string(), seq: "ACGTACGTAC".to_string(), phred: "II+III+III".to_string() };
+        fq.mask_low_quality(20);
+        assert_eq!((fq.seq.as_str(), fq.phred.as_str()), ("ACNTACNTAC", "II#III#III"));
+        fq.head_crop(3);
+        fq.crop(4);
+        assert_eq!((fq.seq.as_str(), fq.phred.as_str()), ("TACN", "III#"));
+        fq.crop(10);
+        fq.head_crop(10);
+        assert!(fq.is_empty());
+        assert_eq!("HEADCROP:10".parse::<TrimOperation>().unwrap(), TrimOperation::HeadCrop(10));
+        assert_eq!(TrimOperation::Mask(10).to_string(), "MASK:10");
+
+        // R2 has its own head crop; masking comes after quality trimming
+        let options = TrimOptions { head_crop: Some(1), head_crop_r2: Some(2), trailing: Some(3), mask_below: Some(20), ..TrimOptions::default() };
+        let (trimmer_r1, trimmer_r2) = options.trimmers(&[], &[]).unwrap();
+        let read = || FastqEntry { header: "@r".to_string(), seq: "ACGTACGT".to_string(), phred: "III+III#".to_string() };
+        let (mut fq, mut fq2) = (read(), read());
+        assert!(trimmer_r1.trim(&mut fq));
+        assert!(trimmer_r2.trim(&mut fq2));
+        assert_eq!((fq.seq.as_str(), fq.phred.as_str()), ("CGNACG", "II#III"));
+        assert_eq!(fq2.seq, "GNACG");
+    }
+
+    #[test]
+    fn test_tails_and_filter() {
+        let adapter = AdapterTrimmer::new(vec![Adapter::parse("truseq", AdapterEnd::ThreePrime).unwrap()]);
+        let trimmer = Trimmer::new(vec![], 1)
+            .with_adapters(adapter)
+            .with_tails(&[('A', 10)])
+            .with_filter(QualityFilter::new(vec![QualityCriterion::MaxDust(7.0)]));
+        let seq = "TGCATGCAAGGCTTACCGATAAAAAAAAAAAAAAAGATCGGAAGAGCACACG";
+        let mut fq = FastqEntry { header: "@r".to_string(), seq: seq.to_string(), phred: "I".repeat(seq.len()) };
+        trimmer.trim_read(&mut fq);
+        assert_eq!(fq.seq, "TGCATGCAAGGCTTACCGAT");
+        assert_eq!(trimmer.check(&fq), TrimResult::Kept);
+        let mut fq = FastqEntry { header: "@r".to_string(), seq: "CA".repeat(30), phred: "I".repeat(60) };
+        assert!(!trimmer.trim(&mut fq));
+        assert_eq!(trimmer.check(&fq), TrimResult::Filtered);
+        assert_eq!(trimmer.rejection_reason(&fq).unwrap(), "dust<=7");
+
+        // masking stays after the poly-X tails whatever the builder order
+        let trimmer = Trimmer::new(vec![], 1).with_mask(20).with_tails(&[('A', 10)]);
+        assert_eq!(trimmer.post_adapter, vec![TrimOperation::PolyX { base: 'A', min_length: 10 }, TrimOperation::Mask(20)]);
+    }
+
+    #[test]
+    fn test_trimmer() {
+        let mut fq = FastqEntry { header: "@r".to_string(), seq: "ACGTACGT".to_string(), phred: "#III+++#".to_string() };
+        let trimmer = Trimmer::new(vec![TrimOperation::Leading(3), "SLIDINGWINDOW:2:20".parse().unwrap()], 3);
+        assert!(trimmer.trim(&mut fq));
+        assert_eq!(fq.seq, "CGT");
+        assert_eq!(fq.phred, "III");
+        assert!(!Trimmer::new(vec![], 9).trim(&mut fq));
+        assert!("SLIDINGWINDOW:0:20".parse::<TrimOperation>().is_err());
+    }
+
+    #[test]
+    fn test_trim_fastq() {
+        let fname = "/tmp/rustfastq_trim_in.fastq.gz";
+        let out = "/tmp/rustfastq_trim_out.fastq.gz";
+        write_fastq_gz(fname, &[("a", "ACGTAC", "IIII##"), ("b", "ACGTAC", "######")]);
+        let mut rejected = RejectedReads::new(None);
+        let summary = trim_fastq(&[fname.to_string()], out, &Trimmer::new(vec![TrimOperation::Trailing(3)], 1), &mut rejected);
+        assert_eq!((summary.reads_in, summary.reads_trimmed, summary.too_short), (2, 2, 1));
+        assert_eq!(rejected.rows()[0].reason, "length>=1");
+        assert_eq!(summary.bases_out, 4);
+        let kept: Vec<String> = FastIterator::new(out).map(|fq| fq.seq).collect();
+        assert_eq!(kept, vec!["ACGT"]);
+    }
+
+    #[test]
+    fn test_trim_fastq_paired() {
+        let insert = "TGCATGCAAGGCTTACCGATTACGGATCAGGCATTCAGGACGTACCAT";
+        let r1 = format!("{insert}AGATCGGAAGAGCACACGTC");
+        let r2 = format!("{}AGATCGGAAGAGCGTCGTGT", reverse_complement(insert));
+        let (f1, f2) = ("/tmp/rustfastq_trim_r1.fastq.gz", "/tmp/rustfastq_trim_r2.fastq.gz");
+        let (o1, o2) = ("/tmp/rustfastq_trim_out_r1.fastq.gz", "/tmp/rustfastq_trim_out_r2.fastq.gz");
+        let q = "I".repeat(r1.len());
+        write_fastq_gz(f1, &[("a", &r1, &q), ("b", &r1[..40], &q[..40])]);
+        write_fastq_gz(f2, &[("a", &r2, &q), ("b", &r2[..40], &q[..40])]);
+        let seqs = |fname: &str| -> Vec<String> { FastIterator::new(fname).map(|fq| fq.seq).collect() };
+
+        // by overlap: the second pair overlaps with an insert of 48, longer than its reads, so nothing is trimmed
+        let trimmer = Trimmer::new(vec![], 1);
+        let mut rejected = [RejectedReads::new(None), RejectedReads::new(None)];
+        let (s1, s2) = trim_fastq_paired(&[f1.to_string()], &[f2.to_string()], o1, o2, (&trimmer, &trimmer), Some(&OverlapParams::default()), &mut rejected);
+        assert_eq!((s1.reads_with_adapter, s2.reads_with_adapter), (1, 1));
+        assert_eq!(seqs(o1)[0], insert);
+        assert_eq!(seqs(o2)[0], reverse_complement(insert));
+        assert_eq!(seqs(o1)[1], &r1[..40]);
+
+        // by sequence, dropping pairs that get too short
+        let trimmer = Trimmer::new(vec![], 45)
+            .with_adapters(AdapterTrimmer::new(vec![Adapter::parse("truseq", AdapterEnd::ThreePrime).unwrap()]));
+        let failed = "/tmp/rustfastq_trim_failed_r2.fastq.gz";
+        let mut rejected = [RejectedReads::new(None), RejectedReads::new(Some(failed))];
+        let (s1, _) = trim_fastq_paired(&[f1.to_string()], &[f2.to_string()], o1, o2, (&trimmer, &trimmer), None, &mut rejected);
+        assert_eq!((s1.reads_with_adapter, s1.too_short, s1.reads_out), (1, 1, 1));
+        assert_eq!(seqs(o1), vec![insert]);
+        drop(rejected);
+        let failed: Vec<String> = FastIterator::new(failed).map(|fq| fq.header).collect();
+        assert_eq!(failed, vec!["@b reject=length>=45"]);
+    }
+}
@@ -1,3 +1,5 @@
+use serde::Serialize;
+
 pub fn get_spinner() -> indicatif::ProgressBar{
     let bar = indicatif::ProgressBar::new_spinner();
     bar.set_style(
@@ -7,4 +9,14 @@ pub fn get_spinner() -> indicatif::ProgressBar{
             .progress_chars("##-"),
     );
     bar
-}
\ No newline at end of file
+}
+
+/// Writes the rows as csv, with header
+pub fn write_csv<T: Serialize>(rows: &[T], output_csv_file: &str) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_path(output_csv_file)?;
+    for row in rows {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
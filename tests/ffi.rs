@@ -0,0 +1,106 @@
+//! Exercises the C ABI from C: compiles tests/ffi/harness.c against the cdylib
+//! and the generated header. Only built with `--features ffi`
+#![cfg(feature = "ffi")]
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// target/<profile>, where cargo puts librustfastq.so
+fn target_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    // target/<profile>/deps/ffi-<hash>
+    exe.parent().unwrap().parent().unwrap().to_path_buf()
+}
+
+/// The crate is only an rlib, so the cdylib has to be built explicitly
+fn build_cdylib() {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut cmd = Command::new(cargo);
+    cmd.args(["rustc", "--lib", "--features", "ffi", "--crate-type", "cdylib"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"));
+    if target_dir().ends_with("release") {
+        cmd.arg("--release");
+    }
+    assert!(cmd.status().unwrap().success());
+}
+
+fn compile_harness(outdir: &Path) -> PathBuf {
+    build_cdylib();
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = target_dir();
+    let exe = outdir.join("harness");
+    let status = Command::new("cc")
+        .arg(manifest.join("tests/ffi/harness.c"))
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lrustfastq")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .expect("a C compiler (cc)");
+    assert!(status.success());
+    exe
+}
+
+fn write_fastq_gz(fname: &Path, records: &[(&str, &str, &str)]) {
+    let mut writer = noodles::bgzf::Writer::new(std::fs::File::create(fname).unwrap());
+    for (name, seq, qual) in records {
+        write!(writer, "@{name}\n{seq}\n+\n{qual}\n").unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+/// The checked-in header has to match the one generated from src/ffi.rs;
+/// `RUSTFASTQ_UPDATE_HEADER=1 cargo test --features ffi --test ffi` updates it
+#[test]
+fn test_header_is_current() {
+    let generated = std::fs::read_to_string(concat!(env!("OUT_DIR"), "/rustfastq.h")).unwrap();
+    let checked_in = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/rustfastq.h");
+    if std::env::var_os("RUSTFASTQ_UPDATE_HEADER").is_some() {
+        std::fs::write(&checked_in, &generated).unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(&checked_in).unwrap(), generated, "include/rustfastq.h is out of date");
+}
+
+#[test]
+fn test_c_harness() {
+    let outdir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi_c_harness");
+    std::fs::create_dir_all(&outdir).unwrap();
+    let harness = compile_harness(&outdir);
+
+    let f1 = outdir.join("a.fastq.gz");
+    let f2 = outdir.join("b.fastq.gz");
+    write_fastq_gz(&f1, &[("r1 1:N:0", "ACGTACGT", "FFFFFFFF"), ("r2 1:N:0", "GGGG", "IIII")]);
+    write_fastq_gz(&f2, &[("r3 1:N:0", "TTTTTTTTTTTTTTTTTTTT", "55555555555555555555")]);
+
+    let output = Command::new(&harness).arg(&f1).arg(&f2).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "@r1 1:N:0\tACGTACGT\tFFFFFFFF",
+            "@r2 1:N:0\tGGGG\tIIII",
+            "@r3 1:N:0\tTTTTTTTTTTTTTTTTTTTT\t55555555555555555555",
+            // the first record and the long one in the second file don't fit
+            "records=3 resized=2",
+        ]
+    );
+}
+
+#[test]
+fn test_c_harness_missing_file() {
+    let outdir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi_c_harness_missing");
+    std::fs::create_dir_all(&outdir).unwrap();
+    let harness = compile_harness(&outdir);
+
+    let output = Command::new(&harness).arg(outdir.join("does_not_exist.fastq.gz")).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("open failed (-3)"), "{stderr}");
+}
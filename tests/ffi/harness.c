@@ -0,0 +1,63 @@
+/*
+ * Minimal consumer of the rustfastq C ABI, driven by tests/ffi.rs.
+ *
+ * usage: harness <fastq> [<fastq> ...]
+ * prints one line per record: <header>\t<seq>\t<qual>
+ * and a final line: records=<n> resized=<n>
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "rustfastq.h"
+
+static int grow(RfqBuffer *buf) {
+    if (buf->capacity >= buf->len + 1) {
+        return 1;
+    }
+    char *data = realloc(buf->data, buf->len + 1);
+    if (data == NULL) {
+        return 0;
+    }
+    buf->data = data;
+    buf->capacity = buf->len + 1;
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    RfqReader *reader = rfq_reader_open((const char *const *)(argv + 1), (size_t)(argc - 1));
+    if (reader == NULL) {
+        fprintf(stderr, "open failed (%d): %s\n", rfq_last_error_code(), rfq_last_error_message());
+        return 2;
+    }
+
+    /* deliberately tiny, so the resize path gets exercised */
+    RfqBuffer header = {malloc(4), 4, 0};
+    RfqBuffer seq = {malloc(4), 4, 0};
+    RfqBuffer qual = {malloc(4), 4, 0};
+    long records = 0;
+    long resized = 0;
+    int rc;
+
+    while ((rc = rfq_reader_next(reader, &header, &seq, &qual)) != RFQ_EOF) {
+        if (rc == RFQ_ERR_BUFFER_TOO_SMALL) {
+            if (!grow(&header) || !grow(&seq) || !grow(&qual)) {
+                return 3;
+            }
+            resized++;
+            continue;
+        }
+        if (rc != RFQ_OK) {
+            fprintf(stderr, "read failed (%d): %s\n", rc, rfq_last_error_message());
+            return 4;
+        }
+        printf("%s\t%s\t%s\n", header.data, seq.data, qual.data);
+        records++;
+    }
+    printf("records=%ld resized=%ld\n", records, resized);
+
+    rfq_reader_close(reader);
+    free(header.data);
+    free(seq.data);
+    free(qual.data);
+    return 0;
+}